@@ -1,26 +1,82 @@
-use crate::config::{MergeOptions, TimeoutInput, UserConfig};
+use crate::config::{
+    MergeOptions, OutputLinesInput, RetriesInput, TimeoutInput, UserConfig,
+    UserTriggerConfig,
+};
+use crate::duration_format::parse_duration;
+use crate::environment::{parse_variable, NULL_STDIN};
+use crate::exit_code::ExitCode;
 use crate::incantation::Incantation;
+use crate::job::parse_step;
 use crate::notifications::email::EmailConfig;
+use crate::parallel::{Parallel, SEPARATOR};
+use crate::retry::{Backoff, BACKOFF_NAMES};
+use crate::signature::{SignatureCheck, DEFAULT_TOLERANCE};
+use crate::watch::{Pid, WatchTarget};
 use crate::DID_IT_RUN_NAME;
-use clap;
-use clap::{crate_authors, crate_description, crate_version, Arg};
+use clap::{
+    crate_authors, crate_description, crate_version, AppSettings, Arg,
+    ErrorKind, SubCommand,
+};
+use regex::Regex;
 use std::default::Default;
 use std::ffi::OsString;
+use std::num::ParseIntError;
 use std::path::PathBuf;
 
 const ARGUMENTS: &str = "ARGUMENTS";
+const BACKOFF: &str = "BACKOFF";
+const CLEAR_ENV: &str = "CLEAR_ENV";
 const CONFIG_FILE: &str = "CONFIG_FILE";
 const CREDENTIALS_FILE: &str = "CREDENTIALS_FILE";
 const COMMAND: &str = "COMMAND";
+const COMMANDS: &str = "COMMANDS";
+const CWD: &str = "CWD";
 const EMAIL: &str = "EMAIL";
+const ENV: &str = "ENV";
+const ENV_FILE: &str = "ENV_FILE";
+const HEARTBEAT: &str = "HEARTBEAT";
+const JOB_FILE: &str = "JOB_FILE";
+const JOBS: &str = "JOBS";
+const KEEP_GOING: &str = "KEEP_GOING";
+const KILL_ON_STALL: &str = "KILL_ON_STALL";
 const NO_DESKTOP: &str = "NO_DESKTOP";
 const NO_EMAIL: &str = "NO_EMAIL";
 const NO_VALIDATE: &str = "NO_VALIDATE";
+const NOTIFY_EACH: &str = "NOTIFY_EACH";
+const NOTIFY_EACH_ATTEMPT: &str = "NOTIFY_EACH_ATTEMPT";
+const NOTIFY_ON_OUTPUT: &str = "NOTIFY_ON_OUTPUT";
+const OUTPUT_LINES: &str = "OUTPUT_LINES";
+const PARALLEL: &str = "parallel";
+const PID: &str = "PID";
+const PID_OF: &str = "PID_OF";
+const PTY: &str = "PTY";
+const RETRIES: &str = "RETRIES";
+const RETRY_DELAY: &str = "RETRY_DELAY";
+const SHELL: &str = "SHELL";
+const SIGNATURE: &str = "SIGNATURE";
+const STALL_AFTER: &str = "STALL_AFTER";
+const STDIN: &str = "STDIN";
+const STEP: &str = "STEP";
+const SUCCESS_CODES: &str = "SUCCESS_CODES";
 const TIMEOUT: &str = "TIMEOUT";
+const TIMESTAMP: &str = "TIMESTAMP";
+const TOLERANCE: &str = "TOLERANCE";
+const VERIFY_SIGNATURE: &str = "verify-signature";
 
 #[derive(Debug)]
 pub struct CliOptions {
-    pub incantation: Incantation,
+    /// The command to run, unless an existing process is watched or steps
+    /// or parallel commands are run instead.
+    pub incantation: Option<Incantation>,
+    /// Whether to run the incantation's words as a command line through the
+    /// shell.
+    pub shell: bool,
+    pub watch: Option<WatchTarget>,
+    pub steps: Vec<Incantation>,
+    pub job_file: Option<PathBuf>,
+    pub parallel: Option<Parallel>,
+    /// A signed webhook request to check instead of running anything.
+    pub signature_check: Option<SignatureCheck>,
     pub cli_config: UserConfig,
     pub config_file: Option<PathBuf>,
     pub credentials_file: Option<PathBuf>,
@@ -32,45 +88,216 @@ where
     I: IntoIterator<Item = S>,
     S: Into<OsString> + Clone,
 {
+    // Subcommands are only recognized as the first argument since a command
+    // may share its name with one, e.g., GNU parallel. Options are global so
+    // that they can follow the subcommand.
     let matches = clap::App::new(DID_IT_RUN_NAME)
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .setting(AppSettings::DisableHelpSubcommand)
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name(CONFIG_FILE)
                 .long("config")
                 .value_name("FILE")
-                .help("Path to config file"),
+                .help("Path to config file")
+                .global(true),
         )
         .arg(
             Arg::with_name(CREDENTIALS_FILE)
                 .long("credentials")
                 .value_name("FILE")
-                .help("Path to credentials file"),
+                .help("Path to credentials file")
+                .global(true),
         )
         .arg(
             Arg::with_name(NO_DESKTOP)
                 .long("no-desktop")
                 .help("Do not show desktop notifications")
-                .conflicts_with(EMAIL),
+                .conflicts_with(EMAIL)
+                .global(true),
         )
         .arg(
             Arg::with_name(NO_EMAIL)
                 .long("no-email")
                 .help("Do not send email notifications")
-                .conflicts_with(EMAIL),
+                .conflicts_with(EMAIL)
+                .global(true),
         )
         .arg(
             Arg::with_name(NO_VALIDATE)
                 .long("no-validate")
-                .help("Do not validate credentials and inputs"),
+                .help("Do not validate credentials and inputs")
+                .global(true),
         )
         .arg(
             Arg::with_name(TIMEOUT)
                 .long("timeout")
-                .help("Timeout in seconds")
+                .help("Terminate COMMAND after this many seconds")
                 .number_of_values(1)
-                .validator(validate_timeout),
+                .validator(validate_timeout)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(OUTPUT_LINES)
+                .long("output-lines")
+                .value_name("LINES")
+                .help(
+                    "Include the last LINES lines of COMMAND's stdout and \
+                     stderr in notifications",
+                )
+                .number_of_values(1)
+                .validator(validate_output_lines)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(PTY)
+                .long("pty")
+                .help(
+                    "Run COMMAND in a pseudo-terminal so that it keeps colors \
+                     and progress bars even when its output is captured",
+                )
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(CWD)
+                .long("cwd")
+                .value_name("DIR")
+                .help("Run COMMAND in this directory")
+                .number_of_values(1)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(ENV)
+                .long("env")
+                .value_name("NAME=VALUE")
+                .help("Set this environment variable for COMMAND")
+                .number_of_values(1)
+                .multiple(true)
+                .validator(validate_variable)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(ENV_FILE)
+                .long("env-file")
+                .value_name("FILE")
+                .help(
+                    "Set the environment variables listed in this file, one \
+                     NAME=VALUE per line, for COMMAND",
+                )
+                .number_of_values(1)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(CLEAR_ENV)
+                .long("clear-env")
+                .help(
+                    "Run COMMAND with only the environment variables given by \
+                     --env and --env-file",
+                )
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(STDIN)
+                .long("stdin")
+                .value_name("FILE")
+                .help(&format!(
+                    "Feed this file to COMMAND as its input, or nothing if {}",
+                    NULL_STDIN
+                ))
+                .number_of_values(1)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(RETRIES)
+                .long("retries")
+                .value_name("N")
+                .help("Run COMMAND up to N more times until it succeeds")
+                .number_of_values(1)
+                .validator(validate_retries)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(RETRY_DELAY)
+                .long("retry-delay")
+                .value_name("DURATION")
+                .help(
+                    "Wait this long, e.g., 30s, before retrying [default: 10s]",
+                )
+                .number_of_values(1)
+                .validator(validate_duration)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(BACKOFF)
+                .long("backoff")
+                .help("How the delay grows between retries [default: constant]")
+                .number_of_values(1)
+                .possible_values(&BACKOFF_NAMES)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(NOTIFY_EACH_ATTEMPT)
+                .long("notify-each-attempt")
+                .help("Also notify when an attempt fails and will be retried")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(HEARTBEAT)
+                .long("heartbeat")
+                .value_name("DURATION")
+                .help(
+                    "Notify every DURATION, e.g., 6h, that COMMAND is still \
+                     running",
+                )
+                .number_of_values(1)
+                .validator(validate_duration)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(STALL_AFTER)
+                .long("stall-after")
+                .value_name("DURATION")
+                .help(
+                    "Notify when COMMAND writes no output for DURATION, e.g., \
+                     20m",
+                )
+                .number_of_values(1)
+                .validator(validate_duration)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(KILL_ON_STALL)
+                .long("kill-on-stall")
+                .help("Terminate COMMAND and fail once it has stalled")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(NOTIFY_ON_OUTPUT)
+                .long("notify-on-output")
+                .value_name("REGEX")
+                .help(
+                    "Notify as soon as a line of COMMAND's output matches \
+                     REGEX",
+                )
+                .number_of_values(1)
+                .multiple(true)
+                .validator(validate_regex)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(SUCCESS_CODES)
+                .long("success-codes")
+                .value_name("CODES")
+                .help(
+                    "Comma-separated exit codes that count as COMMAND \
+                     succeeding [default: 0]",
+                )
+                .number_of_values(1)
+                .validator(validate_success_codes)
+                .global(true),
         )
         .arg(
             Arg::with_name(EMAIL)
@@ -78,24 +305,191 @@ where
                 .long("email")
                 .help("Email address(es) to receive notifications")
                 .number_of_values(1)
-                .multiple(true),
+                .multiple(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(PID)
+                .long("pid")
+                .help(
+                    "Watch the running process with this PID instead, exiting \
+                     with 0 once it exits whatever its exit code, which \
+                     cannot be known",
+                )
+                .number_of_values(1)
+                .validator(validate_pid)
+                .conflicts_with_all(&[COMMAND, PID_OF])
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(PID_OF)
+                .long("pid-of")
+                .value_name("NAME")
+                .help(
+                    "Watch the running process with this name instead, \
+                     exiting as with --pid",
+                )
+                .number_of_values(1)
+                .conflicts_with(COMMAND)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(STEP)
+                .long("step")
+                .value_name("COMMAND")
+                .help("Run this command as the next step instead")
+                .number_of_values(1)
+                .multiple(true)
+                .validator(validate_step)
+                .conflicts_with_all(&[COMMAND, PID, PID_OF, JOB_FILE])
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(JOB_FILE)
+                .long("job")
+                .value_name("FILE")
+                .help("Run the steps listed in this job file instead")
+                .number_of_values(1)
+                .conflicts_with_all(&[COMMAND, PID, PID_OF])
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(KEEP_GOING)
+                .long("keep-going")
+                .help("Run the remaining steps after one fails")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name(SHELL)
+                .long("shell")
+                .help(
+                    "Run COMMAND and its ARGUMENTS as a command line through \
+                     the shell, e.g., --shell 'make | tee log'",
+                )
+                .requires(COMMAND),
         )
         .arg(
             Arg::with_name(COMMAND)
                 .help("Command to run")
-                .required(true),
+                .required_unless_one(&[PID, PID_OF, STEP, JOB_FILE]),
         )
         .arg(
             Arg::with_name(ARGUMENTS)
                 .help(&format!("{} arguments", COMMAND))
                 .min_values(0),
         )
+        .subcommand(
+            SubCommand::with_name(PARALLEL)
+                .about(
+                    "Run several commands at once and notify when all have \
+                     finished",
+                )
+                .setting(AppSettings::TrailingVarArg)
+                .arg(
+                    Arg::with_name(JOBS)
+                        .short("j")
+                        .long("jobs")
+                        .value_name("N")
+                        .help("Run at most N commands at a time [default: all]")
+                        .number_of_values(1)
+                        .validator(validate_jobs),
+                )
+                .arg(
+                    Arg::with_name(NOTIFY_EACH)
+                        .long("notify-each")
+                        .help("Also notify as each command finishes"),
+                )
+                .arg(
+                    Arg::with_name(COMMANDS)
+                        .help(&format!(
+                            "Commands to run with their arguments, separated \
+                             by {}",
+                            SEPARATOR
+                        ))
+                        .required(true)
+                        .multiple(true)
+                        .allow_hyphen_values(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(VERIFY_SIGNATURE)
+                .about(
+                    "Check that the webhook request read from standard input \
+                     was signed with the webhook secret",
+                )
+                .arg(
+                    Arg::with_name(TIMESTAMP)
+                        .long("timestamp")
+                        .value_name("TIMESTAMP")
+                        .help("Value of the X-Diditrun-Timestamp header")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(SIGNATURE)
+                        .long("signature")
+                        .value_name("SIGNATURE")
+                        .help("Value of the X-Diditrun-Signature header")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(TOLERANCE)
+                        .long("tolerance")
+                        .value_name("DURATION")
+                        .help(
+                            "Reject requests signed further than this from \
+                             now [default: 5m]",
+                        )
+                        .validator(validate_duration),
+                ),
+        )
         .get_matches_from_safe(args)?;
-    // Command is a required argument. We return an Err before reaching this
-    // point if it is not provided.
-    let command = matches.value_of_os(COMMAND).unwrap();
-    let args = matches.values_of_os(ARGUMENTS).unwrap_or_default();
-    let incantation = Incantation::new(command, args);
+    // Command is required unless a process is watched or steps or parallel
+    // commands are run. We return an Err before reaching this point if none
+    // is provided.
+    let incantation = matches.value_of_os(COMMAND).map(|command| {
+        let args = matches.values_of_os(ARGUMENTS).unwrap_or_default();
+        Incantation::new(command, args)
+    });
+    let watch = if let Some(pid) = matches.value_of(PID) {
+        // Clap already validates this value using `validate_pid`.
+        Some(WatchTarget::Pid(pid.parse().unwrap()))
+    } else {
+        matches
+            .value_of(PID_OF)
+            .map(|name| WatchTarget::Name(name.to_string()))
+    };
+    // Clap already validates these values using `validate_step`.
+    let steps: Vec<Incantation> = matches
+        .values_of(STEP)
+        .map(|steps| steps.map(|step| parse_step(step).unwrap()).collect())
+        .unwrap_or_default();
+    let job_file = matches.value_of_os(JOB_FILE).map(PathBuf::from);
+    let parallel = match matches.subcommand_matches(PARALLEL) {
+        Some(parallel_matches) => {
+            if watch.is_some() || !steps.is_empty() || job_file.is_some() {
+                return Err(clap::Error::with_description(
+                    "The parallel subcommand cannot be used with --pid, \
+                     --pid-of, --step or --job",
+                    ErrorKind::ArgumentConflict,
+                ));
+            }
+            Some(parse_parallel(parallel_matches)?)
+        },
+        None => None,
+    };
+    let signature_check = match matches.subcommand_matches(VERIFY_SIGNATURE) {
+        Some(verify_matches) => {
+            if watch.is_some() || !steps.is_empty() || job_file.is_some() {
+                return Err(clap::Error::with_description(
+                    "The verify-signature subcommand cannot be used with \
+                     --pid, --pid-of, --step or --job",
+                    ErrorKind::ArgumentConflict,
+                ));
+            }
+            Some(parse_signature_check(verify_matches))
+        },
+        None => None,
+    };
     let mut cli_config: UserConfig = Default::default();
     if let Some(recipients) = matches.values_of_lossy(EMAIL) {
         cli_config.email = Some(EmailConfig { recipients });
@@ -106,6 +500,69 @@ where
         // Clap already validates this value using `validate_timeout`.
         cli_config.timeout = Some(timeout.parse().unwrap());
     }
+    if let Some(output_lines) = matches.value_of(OUTPUT_LINES) {
+        // Clap already validates this value using `validate_output_lines`.
+        cli_config.output_lines = Some(output_lines.parse().unwrap());
+    }
+    if let Some(success_codes) = matches.value_of(SUCCESS_CODES) {
+        // Clap already validates this value using `validate_success_codes`.
+        cli_config.success_codes =
+            Some(parse_success_codes(success_codes).unwrap());
+    }
+    if let Some(retries) = matches.value_of(RETRIES) {
+        // Clap already validates this value using `validate_retries`.
+        cli_config.retries = Some(retries.parse().unwrap());
+    }
+    if let Some(retry_delay) = matches.value_of(RETRY_DELAY) {
+        cli_config.retry_delay = Some(retry_delay.to_string());
+    }
+    if let Some(heartbeat) = matches.value_of(HEARTBEAT) {
+        cli_config.heartbeat = Some(heartbeat.to_string());
+    }
+    if let Some(stall_after) = matches.value_of(STALL_AFTER) {
+        cli_config.stall_after = Some(stall_after.to_string());
+    }
+    if matches.is_present(KILL_ON_STALL) {
+        cli_config.kill_on_stall = Some(true);
+    }
+    if matches.is_present(KEEP_GOING) {
+        cli_config.keep_going = Some(true);
+    }
+    if let Some(patterns) = matches.values_of_lossy(NOTIFY_ON_OUTPUT) {
+        let triggers = patterns
+            .into_iter()
+            .map(|pattern| UserTriggerConfig {
+                pattern,
+                rate_limit: None,
+            })
+            .collect();
+        cli_config.triggers = Some(triggers);
+    }
+    if let Some(backoff) = matches.value_of(BACKOFF) {
+        // Clap only accepts `BACKOFF_NAMES`.
+        cli_config.backoff = Backoff::from_name(backoff);
+    }
+    if matches.is_present(NOTIFY_EACH_ATTEMPT) {
+        cli_config.notify_each_attempt = Some(true);
+    }
+    if matches.is_present(PTY) {
+        cli_config.pty = Some(true);
+    }
+    if let Some(cwd) = matches.value_of_lossy(CWD) {
+        cli_config.cwd = Some(cwd.into_owned());
+    }
+    if let Some(variables) = matches.values_of_lossy(ENV) {
+        cli_config.env = Some(variables);
+    }
+    if let Some(env_file) = matches.value_of_lossy(ENV_FILE) {
+        cli_config.env_file = Some(env_file.into_owned());
+    }
+    if matches.is_present(CLEAR_ENV) {
+        cli_config.clear_env = Some(true);
+    }
+    if let Some(stdin) = matches.value_of_lossy(STDIN) {
+        cli_config.stdin = Some(stdin.into_owned());
+    }
     let config_file = matches.value_of_os(CONFIG_FILE).map(PathBuf::from);
     let credentials_file =
         matches.value_of_os(CREDENTIALS_FILE).map(PathBuf::from);
@@ -114,6 +571,12 @@ where
     };
     Ok(CliOptions {
         incantation,
+        shell: matches.is_present(SHELL),
+        watch,
+        steps,
+        job_file,
+        parallel,
+        signature_check,
         cli_config,
         config_file,
         credentials_file,
@@ -121,6 +584,45 @@ where
     })
 }
 
+/// Reads the arguments of the parallel subcommand.
+fn parse_parallel(matches: &clap::ArgMatches) -> Result<Parallel, clap::Error> {
+    let mut incantations = vec![];
+    // Clap requires at least one value.
+    let values = matches.values_of_os(COMMANDS).unwrap().collect::<Vec<_>>();
+    for words in values.split(|word| word.to_str() == Some(SEPARATOR)) {
+        if words.is_empty() {
+            return Err(clap::Error::with_description(
+                &format!(
+                    "Each command separated by {} must be given",
+                    SEPARATOR
+                ),
+                ErrorKind::EmptyValue,
+            ));
+        }
+        incantations.push(Incantation::new(words[0], words[1..].to_vec()));
+    }
+    Ok(Parallel {
+        incantations,
+        // Clap already validates this value using `validate_jobs`.
+        jobs: matches.value_of(JOBS).map(|jobs| jobs.parse().unwrap()),
+        notify_each: matches.is_present(NOTIFY_EACH),
+    })
+}
+
+/// Reads the arguments of the verify-signature subcommand.
+fn parse_signature_check(matches: &clap::ArgMatches) -> SignatureCheck {
+    SignatureCheck {
+        // Clap requires these values.
+        timestamp: matches.value_of(TIMESTAMP).unwrap().to_string(),
+        signature: matches.value_of(SIGNATURE).unwrap().to_string(),
+        // Clap already validates this value using `validate_duration`.
+        tolerance: matches
+            .value_of(TOLERANCE)
+            .map(|tolerance| parse_duration(tolerance).unwrap())
+            .unwrap_or(DEFAULT_TOLERANCE),
+    }
+}
+
 fn validate_timeout(timeout: String) -> Result<(), String> {
     match timeout.parse::<TimeoutInput>() {
         Ok(_) => Ok(()),
@@ -131,17 +633,103 @@ fn validate_timeout(timeout: String) -> Result<(), String> {
     }
 }
 
+fn validate_output_lines(output_lines: String) -> Result<(), String> {
+    match output_lines.parse::<OutputLinesInput>() {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!(
+            "Cannot parse integer output lines value \"{}\": {}",
+            output_lines, err
+        )),
+    }
+}
+
+fn validate_pid(pid: String) -> Result<(), String> {
+    match pid.parse::<Pid>() {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("Cannot parse PID \"{}\": {}", pid, err)),
+    }
+}
+
+fn validate_retries(retries: String) -> Result<(), String> {
+    match retries.parse::<RetriesInput>() {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!(
+            "Cannot parse integer retries value \"{}\": {}",
+            retries, err
+        )),
+    }
+}
+
+fn validate_jobs(jobs: String) -> Result<(), String> {
+    match jobs.parse::<usize>() {
+        Ok(0) => Err("At least one command must run at a time".to_string()),
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!(
+            "Cannot parse integer jobs value \"{}\": {}",
+            jobs, err
+        )),
+    }
+}
+
+fn validate_duration(duration: String) -> Result<(), String> {
+    match parse_duration(&duration) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn validate_variable(variable: String) -> Result<(), String> {
+    match parse_variable(&variable) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn validate_step(step: String) -> Result<(), String> {
+    match parse_step(&step) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn validate_regex(pattern: String) -> Result<(), String> {
+    match Regex::new(&pattern) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn validate_success_codes(success_codes: String) -> Result<(), String> {
+    match parse_success_codes(&success_codes) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!(
+            "Cannot parse integer success codes \"{}\": {}",
+            success_codes, err
+        )),
+    }
+}
+
+fn parse_success_codes(
+    success_codes: &str,
+) -> Result<Vec<ExitCode>, ParseIntError> {
+    success_codes
+        .split(',')
+        .map(|code| code.trim().parse::<ExitCode>())
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use clap::ErrorKind;
+    use std::time::Duration;
 
     const BINARY_NAME: &str = "diditrun";
 
     #[test]
     fn returns_error_with_missing_command() {
         let args = [BINARY_NAME];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().kind,
@@ -152,7 +740,7 @@ mod test {
     #[test]
     fn returns_error_with_bad_flag() {
         let args = [BINARY_NAME, "--some-flag"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().kind, ErrorKind::UnknownArgument);
     }
@@ -161,7 +749,7 @@ mod test {
     fn returns_help_with_help_flag() {
         for help_flag in &["-h", "--help"] {
             let args = [BINARY_NAME, help_flag];
-            let result = parse_arguments(&args);
+            let result = parse_arguments(args);
             assert!(result.is_err());
             assert_eq!(result.unwrap_err().kind, ErrorKind::HelpDisplayed);
         }
@@ -171,7 +759,7 @@ mod test {
     fn returns_version_with_version_flag() {
         for version_flag in &["-V", "--version"] {
             let args = [BINARY_NAME, version_flag];
-            let result = parse_arguments(&args);
+            let result = parse_arguments(args);
             assert!(result.is_err());
             assert_eq!(result.unwrap_err().kind, ErrorKind::VersionDisplayed);
         }
@@ -181,7 +769,7 @@ mod test {
     fn parses_config_file_argument() {
         let file = "path/to/file";
         let args = [BINARY_NAME, "--config", file, "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_ok());
         assert_eq!(result.unwrap().config_file, Some(PathBuf::from(file)));
     }
@@ -189,13 +777,13 @@ mod test {
     #[test]
     fn returns_error_with_no_config_file_argument() {
         let args = [BINARY_NAME, "--config", "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().kind,
             ErrorKind::MissingRequiredArgument
         );
-        let result = parse_arguments(&[BINARY_NAME, "command", "--config"]);
+        let result = parse_arguments([BINARY_NAME, "command", "--config"]);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().kind, ErrorKind::EmptyValue);
     }
@@ -204,7 +792,7 @@ mod test {
     fn parses_credentials_file_argument() {
         let file = "path/to/file";
         let args = [BINARY_NAME, "--credentials", file, "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_ok());
         assert_eq!(result.unwrap().credentials_file, Some(PathBuf::from(file)));
     }
@@ -212,13 +800,13 @@ mod test {
     #[test]
     fn returns_error_with_no_credentials_file_argument() {
         let args = [BINARY_NAME, "--credentials", "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().kind,
             ErrorKind::MissingRequiredArgument
         );
-        let result = parse_arguments(&[BINARY_NAME, "command", "--config"]);
+        let result = parse_arguments([BINARY_NAME, "command", "--config"]);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().kind, ErrorKind::EmptyValue);
     }
@@ -226,11 +814,11 @@ mod test {
     #[test]
     fn removes_validation_with_no_validate_flag() {
         let args = [BINARY_NAME, "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_ok());
         assert!(result.unwrap().cli_config.validate.unwrap());
         let args = [BINARY_NAME, "--no-validate", "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_ok());
         assert!(!result.unwrap().cli_config.validate.unwrap());
     }
@@ -239,7 +827,7 @@ mod test {
     fn configures_timeout_with_timeout_option() {
         let timeout = 60;
         let args = [BINARY_NAME, "--timeout", &timeout.to_string(), "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_ok());
         assert_eq!(result.unwrap().cli_config.timeout, Some(timeout));
     }
@@ -247,7 +835,7 @@ mod test {
     #[test]
     fn omits_timeout_withut_timeout_option() {
         let args = [BINARY_NAME, "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_ok());
         assert_eq!(result.unwrap().cli_config.timeout, None);
     }
@@ -257,7 +845,7 @@ mod test {
         let invalid_timeout_values = ["ten", "3.14"];
         for timeout in &invalid_timeout_values {
             let args = [BINARY_NAME, "--timeout", timeout, "command"];
-            let result = parse_arguments(&args);
+            let result = parse_arguments(args);
             assert!(result.is_err());
             assert_eq!(result.unwrap_err().kind, ErrorKind::ValueValidation);
         }
@@ -266,27 +854,493 @@ mod test {
     #[test]
     fn returns_error_with_no_timeout_argument() {
         let args = [BINARY_NAME, "--timeout", "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().kind,
             ErrorKind::MissingRequiredArgument
         );
-        let result = parse_arguments(&[BINARY_NAME, "command", "--timeout"]);
+        let result = parse_arguments([BINARY_NAME, "command", "--timeout"]);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().kind, ErrorKind::EmptyValue);
     }
 
+    #[test]
+    fn configures_output_lines_with_output_lines_option() {
+        let args = [BINARY_NAME, "--output-lines", "20", "command"];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().cli_config.output_lines, Some(20));
+    }
+
+    #[test]
+    fn omits_output_lines_without_output_lines_option() {
+        let args = [BINARY_NAME, "command"];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().cli_config.output_lines, None);
+    }
+
+    #[test]
+    fn returns_error_with_bad_output_lines_argument() {
+        for output_lines in &["many", "2.5"] {
+            let args = [BINARY_NAME, "--output-lines", output_lines, "command"];
+            let result = parse_arguments(args);
+            assert!(result.is_err());
+            assert_eq!(result.unwrap_err().kind, ErrorKind::ValueValidation);
+        }
+    }
+
+    #[test]
+    fn configures_success_codes_with_success_codes_option() {
+        let args = [BINARY_NAME, "--success-codes", "0,1, 24", "command"];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let success_codes = result.unwrap().cli_config.success_codes;
+        assert_eq!(success_codes, Some(vec![0, 1, 24]));
+    }
+
+    #[test]
+    fn returns_error_with_bad_success_codes_argument() {
+        for success_codes in &["zero", "0,,1", "0;1"] {
+            let args =
+                [BINARY_NAME, "--success-codes", success_codes, "command"];
+            let result = parse_arguments(args);
+            assert!(result.is_err());
+            assert_eq!(result.unwrap_err().kind, ErrorKind::ValueValidation);
+        }
+    }
+
+    #[test]
+    fn configures_retries_with_retry_options() {
+        let args = [
+            BINARY_NAME,
+            "--retries",
+            "3",
+            "--retry-delay",
+            "30s",
+            "--backoff",
+            "exponential",
+            "--notify-each-attempt",
+            "command",
+        ];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let cli_config = result.unwrap().cli_config;
+        assert_eq!(cli_config.retries, Some(3));
+        assert_eq!(cli_config.retry_delay, Some("30s".to_string()));
+        assert_eq!(cli_config.backoff, Some(Backoff::Exponential));
+        assert_eq!(cli_config.notify_each_attempt, Some(true));
+    }
+
+    #[test]
+    fn omits_retries_without_retry_options() {
+        let args = [BINARY_NAME, "command"];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let cli_config = result.unwrap().cli_config;
+        assert_eq!(cli_config.retries, None);
+        assert_eq!(cli_config.retry_delay, None);
+        assert_eq!(cli_config.backoff, None);
+        assert_eq!(cli_config.notify_each_attempt, None);
+    }
+
+    #[test]
+    fn returns_error_with_bad_retry_arguments() {
+        let args_variants = [
+            [BINARY_NAME, "--retries", "-1", "command"],
+            [BINARY_NAME, "--retries", "some", "command"],
+            [BINARY_NAME, "--retry-delay", "later", "command"],
+            [BINARY_NAME, "--backoff", "linear", "command"],
+        ];
+        for args in &args_variants {
+            let result = parse_arguments(args);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn configures_heartbeat_with_heartbeat_option() {
+        let args = [BINARY_NAME, "--heartbeat", "6h", "command"];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let heartbeat = result.unwrap().cli_config.heartbeat;
+        assert_eq!(heartbeat, Some("6h".to_string()));
+    }
+
+    #[test]
+    fn configures_environment_with_environment_options() {
+        let args = [
+            BINARY_NAME,
+            "--cwd",
+            "/tmp",
+            "--env",
+            "A=1",
+            "--env",
+            "B=2",
+            "--env-file",
+            ".env",
+            "--clear-env",
+            "--stdin",
+            "null",
+            "command",
+        ];
+        let cli_config = parse_arguments(args).unwrap().cli_config;
+        assert_eq!(cli_config.cwd, Some("/tmp".to_string()));
+        assert_eq!(cli_config.env, Some(vec!["A=1".into(), "B=2".into()]));
+        assert_eq!(cli_config.env_file, Some(".env".to_string()));
+        assert_eq!(cli_config.clear_env, Some(true));
+        assert_eq!(cli_config.stdin, Some("null".to_string()));
+        let cli_config = parse_arguments([BINARY_NAME, "command"])
+            .unwrap()
+            .cli_config;
+        assert_eq!(cli_config.env, None);
+        assert_eq!(cli_config.clear_env, None);
+    }
+
+    #[test]
+    fn returns_error_with_bad_env_argument() {
+        let args = [BINARY_NAME, "--env", "A", "command"];
+        let result = parse_arguments(args);
+        assert_eq!(result.unwrap_err().kind, ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn returns_error_with_bad_heartbeat_argument() {
+        let args = [BINARY_NAME, "--heartbeat", "often", "command"];
+        let result = parse_arguments(args);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().kind, ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn configures_stall_detection_with_stall_options() {
+        let args = [
+            BINARY_NAME,
+            "--stall-after",
+            "20m",
+            "--kill-on-stall",
+            "command",
+        ];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let cli_config = result.unwrap().cli_config;
+        assert_eq!(cli_config.stall_after, Some("20m".to_string()));
+        assert_eq!(cli_config.kill_on_stall, Some(true));
+    }
+
+    #[test]
+    fn returns_error_with_bad_stall_after_argument() {
+        let args = [BINARY_NAME, "--stall-after", "a while", "command"];
+        let result = parse_arguments(args);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().kind, ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn configures_triggers_with_notify_on_output_option() {
+        let args = [
+            BINARY_NAME,
+            "--notify-on-output",
+            "^error:",
+            "--notify-on-output",
+            "NaN loss",
+            "command",
+        ];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let options = result.unwrap();
+        let patterns = options
+            .cli_config
+            .triggers
+            .unwrap()
+            .into_iter()
+            .map(|trigger| trigger.pattern)
+            .collect::<Vec<_>>();
+        assert_eq!(patterns, ["^error:", "NaN loss"]);
+        assert_eq!(options.incantation.unwrap().command, "command");
+    }
+
+    #[test]
+    fn returns_error_with_bad_notify_on_output_argument() {
+        let args = [BINARY_NAME, "--notify-on-output", "error:(", "command"];
+        let result = parse_arguments(args);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().kind, ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn runs_steps_with_step_options() {
+        let args = [
+            BINARY_NAME,
+            "--step",
+            "make",
+            "--step",
+            "make 'test all'",
+            "--keep-going",
+        ];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let options = result.unwrap();
+        assert!(options.incantation.is_none());
+        let steps = options
+            .steps
+            .iter()
+            .map(|step| (step.command.clone(), step.args.clone()))
+            .collect::<Vec<_>>();
+        let expected = [
+            ("make".into(), vec![]),
+            ("make".into(), vec!["test all".into()]),
+        ];
+        assert_eq!(steps, expected);
+        assert_eq!(options.cli_config.keep_going, Some(true));
+    }
+
+    #[test]
+    fn runs_steps_with_job_option() {
+        let args = [BINARY_NAME, "--job", "job.toml"];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let options = result.unwrap();
+        assert_eq!(options.job_file, Some(PathBuf::from("job.toml")));
+        assert!(options.incantation.is_none());
+        assert!(options.steps.is_empty());
+    }
+
+    #[test]
+    fn returns_error_with_bad_step_arguments() {
+        let args_variants = [
+            vec![BINARY_NAME, "--step", "echo 'unterminated"],
+            vec![BINARY_NAME, "--step", " "],
+            vec![BINARY_NAME, "--step", "make", "command"],
+            vec![BINARY_NAME, "--step", "make", "--job", "job.toml"],
+            vec![BINARY_NAME, "--job", "job.toml", "command"],
+        ];
+        for args in &args_variants {
+            let result = parse_arguments(args);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn runs_commands_with_parallel_subcommand() {
+        let args = [
+            BINARY_NAME,
+            "parallel",
+            "--no-desktop",
+            "-j",
+            "2",
+            "--notify-each",
+            "--",
+            "ssh",
+            "host-1",
+            "make",
+            ":::",
+            "ssh",
+            "-v",
+            "host-2",
+            "make",
+        ];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let options = result.unwrap();
+        assert!(options.incantation.is_none());
+        assert_eq!(options.cli_config.desktop_notifications, Some(false));
+        let parallel = options.parallel.unwrap();
+        let commands = parallel
+            .incantations
+            .iter()
+            .map(|incantation| incantation.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(commands, ["ssh host-1 make", "ssh -v host-2 make"]);
+        assert_eq!(parallel.jobs, Some(2));
+        assert!(parallel.notify_each);
+    }
+
+    #[test]
+    fn runs_command_named_like_subcommand() {
+        let args_variants = [
+            vec![BINARY_NAME, "echo", "parallel"],
+            vec![BINARY_NAME, "--no-desktop", "parallel", "echo"],
+            vec![BINARY_NAME, "/usr/bin/parallel", "echo"],
+        ];
+        for args in &args_variants {
+            let options = parse_arguments(args).unwrap();
+            assert!(options.incantation.is_some());
+            assert!(options.parallel.is_none());
+        }
+    }
+
+    #[test]
+    fn returns_error_with_bad_parallel_arguments() {
+        let args_variants = [
+            vec![BINARY_NAME, "parallel"],
+            vec![BINARY_NAME, "parallel", "-j", "0", "true"],
+            vec![BINARY_NAME, "parallel", "true", ":::"],
+            vec![BINARY_NAME, "parallel", "true", ":::", ":::", "false"],
+            vec![BINARY_NAME, "parallel", "--pid", "1234", "true"],
+        ];
+        for args in &args_variants {
+            let result = parse_arguments(args);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn verifies_signature_with_verify_signature_subcommand() {
+        let args = [
+            BINARY_NAME,
+            "verify-signature",
+            "--credentials",
+            "credentials.toml",
+            "--timestamp",
+            "1580521505",
+            "--signature",
+            "sha256=be8a",
+        ];
+        let options = parse_arguments(args).unwrap();
+        assert!(options.incantation.is_none());
+        assert_eq!(
+            options.credentials_file,
+            Some(PathBuf::from("credentials.toml"))
+        );
+        let check = options.signature_check.unwrap();
+        assert_eq!(check.timestamp, "1580521505");
+        assert_eq!(check.signature, "sha256=be8a");
+        assert_eq!(check.tolerance, DEFAULT_TOLERANCE);
+        let args = [
+            BINARY_NAME,
+            "verify-signature",
+            "--timestamp",
+            "1580521505",
+            "--signature",
+            "sha256=be8a",
+            "--tolerance",
+            "30s",
+        ];
+        let check = parse_arguments(args).unwrap().signature_check.unwrap();
+        assert_eq!(check.tolerance, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn returns_error_with_bad_verify_signature_arguments() {
+        let args_variants = [
+            vec![BINARY_NAME, "verify-signature"],
+            vec![BINARY_NAME, "verify-signature", "--timestamp", "1"],
+            vec![BINARY_NAME, "verify-signature", "--signature", "sha256="],
+            vec![
+                BINARY_NAME,
+                "verify-signature",
+                "--timestamp",
+                "1",
+                "--signature",
+                "sha256=",
+                "--tolerance",
+                "soon",
+            ],
+            vec![
+                BINARY_NAME,
+                "verify-signature",
+                "--timestamp",
+                "1",
+                "--signature",
+                "sha256=",
+                "--pid",
+                "1234",
+            ],
+        ];
+        for args in &args_variants {
+            let result = parse_arguments(args);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn watches_process_with_pid_option() {
+        let args = [BINARY_NAME, "--pid", "1234"];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let options = result.unwrap();
+        assert_eq!(options.watch, Some(WatchTarget::Pid(1234)));
+        assert!(options.incantation.is_none());
+    }
+
+    #[test]
+    fn watches_process_with_pid_of_option() {
+        let args = [BINARY_NAME, "--pid-of", "rsync"];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        let options = result.unwrap();
+        assert_eq!(options.watch, Some(WatchTarget::Name("rsync".into())));
+        assert!(options.incantation.is_none());
+    }
+
+    #[test]
+    fn returns_error_with_bad_pid_argument() {
+        for pid in &["1.5", "init"] {
+            let args = [BINARY_NAME, "--pid", pid];
+            let result = parse_arguments(args);
+            assert!(result.is_err());
+            assert_eq!(result.unwrap_err().kind, ErrorKind::ValueValidation);
+        }
+    }
+
+    #[test]
+    fn watching_process_conflicts_with_command() {
+        let args_variants = [
+            vec![BINARY_NAME, "--pid", "1234", "command"],
+            vec![BINARY_NAME, "--pid-of", "rsync", "command"],
+            vec![BINARY_NAME, "--pid", "1234", "--pid-of", "rsync"],
+        ];
+        for args in &args_variants {
+            let result = parse_arguments(args);
+            assert!(result.is_err());
+            assert_eq!(result.unwrap_err().kind, ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn enables_pty_with_pty_flag() {
+        let args = [BINARY_NAME, "--pty", "command"];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().cli_config.pty, Some(true));
+    }
+
+    #[test]
+    fn leaves_pty_to_config_file_without_pty_flag() {
+        let args = [BINARY_NAME, "command"];
+        let result = parse_arguments(args);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().cli_config.pty, None);
+    }
+
+    #[test]
+    fn runs_command_line_through_shell_with_shell_flag() {
+        let args = [BINARY_NAME, "--shell", "make | tee log"];
+        let options = parse_arguments(args).unwrap();
+        assert!(options.shell);
+        assert_eq!(options.incantation.unwrap().command, "make | tee log");
+        let options = parse_arguments([BINARY_NAME, "command"]).unwrap();
+        assert!(!options.shell);
+        let args = [BINARY_NAME, "--shell", "--pid", "1"];
+        let result = parse_arguments(args);
+        assert_eq!(
+            result.unwrap_err().kind,
+            ErrorKind::MissingRequiredArgument
+        );
+    }
+
     #[test]
     fn returns_error_with_unspecified_email() {
         let args = [BINARY_NAME, "--email", "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err().kind,
             ErrorKind::MissingRequiredArgument
         );
-        let result = parse_arguments(&[BINARY_NAME, "command", "--email"]);
+        let result = parse_arguments([BINARY_NAME, "command", "--email"]);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().kind, ErrorKind::EmptyValue);
     }
@@ -294,14 +1348,14 @@ mod test {
     #[test]
     fn specifying_no_email_flag_adds_config() {
         let args = [BINARY_NAME, "--no-email", "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.unwrap().merge_options.no_email);
     }
 
     #[test]
     fn not_no_email_flag_does_not_add_config() {
         let args = [BINARY_NAME, "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(!result.unwrap().merge_options.no_email);
     }
 
@@ -314,7 +1368,7 @@ mod test {
             "--no-email",
             "command",
         ];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().kind, ErrorKind::ArgumentConflict);
     }
@@ -322,9 +1376,9 @@ mod test {
     #[test]
     fn parses_command_with_no_arguments() {
         let args = [BINARY_NAME, "command"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_ok());
-        let incantation = result.unwrap().incantation;
+        let incantation = result.unwrap().incantation.unwrap();
         assert_eq!(incantation.command, "command");
         assert!(incantation.args.is_empty());
     }
@@ -332,9 +1386,9 @@ mod test {
     #[test]
     fn parses_command_with_one_argument() {
         let args = [BINARY_NAME, "command", "foo"];
-        let result = parse_arguments(&args);
+        let result = parse_arguments(args);
         assert!(result.is_ok());
-        let incantation = result.unwrap().incantation;
+        let incantation = result.unwrap().incantation.unwrap();
         assert_eq!(incantation.command, "command");
         assert_eq!(incantation.args, ["foo"]);
     }
@@ -349,7 +1403,7 @@ mod test {
         for args in &args_variants {
             let result = parse_arguments(args);
             assert!(result.is_ok());
-            let incantation = result.unwrap().incantation;
+            let incantation = result.unwrap().incantation.unwrap();
             assert_eq!(incantation.command, "command");
             assert_eq!(incantation.args, ["foo", "bar", "baz"]);
         }
@@ -364,7 +1418,7 @@ mod test {
         for args in &args_variants {
             let result = parse_arguments(args);
             assert!(result.is_ok());
-            let incantation = result.unwrap().incantation;
+            let incantation = result.unwrap().incantation.unwrap();
             assert_eq!(incantation.command, "command");
             assert_eq!(incantation.args, ["arg", "--flag"]);
         }
@@ -418,8 +1472,9 @@ mod test {
             let result = parse_arguments(args);
             assert!(result.is_ok());
             let options = result.unwrap();
-            assert_eq!(options.incantation.command, "command");
-            assert_eq!(options.incantation.args, ["foo", "bar"]);
+            let incantation = options.incantation.unwrap();
+            assert_eq!(incantation.command, "command");
+            assert_eq!(incantation.args, ["foo", "bar"]);
             assert_eq!(
                 options.cli_config.email.unwrap().recipients,
                 ["someone@example.com"]
@@ -453,8 +1508,9 @@ mod test {
             let result = parse_arguments(args);
             assert!(result.is_ok());
             let options = result.unwrap();
-            assert_eq!(options.incantation.command, "command");
-            assert_eq!(options.incantation.args, ["foo"]);
+            let incantation = options.incantation.unwrap();
+            assert_eq!(incantation.command, "command");
+            assert_eq!(incantation.args, ["foo"]);
             assert_eq!(
                 options.cli_config.email.unwrap().recipients,
                 ["someone@example.com", "someone_else@example.com"]
@@ -488,8 +1544,9 @@ mod test {
             let result = parse_arguments(args);
             assert!(result.is_ok());
             let options = result.unwrap();
-            assert_eq!(options.incantation.command, "command");
-            assert_eq!(options.incantation.args, ["arg", "--flag"]);
+            let incantation = options.incantation.unwrap();
+            assert_eq!(incantation.command, "command");
+            assert_eq!(incantation.args, ["arg", "--flag"]);
             assert_eq!(
                 options.cli_config.email.unwrap().recipients,
                 ["someone@example.com"]
@@ -501,7 +1558,13 @@ mod test {
     #[test]
     fn maximize_coverage_report() {
         let options = CliOptions {
-            incantation: Incantation::new("command", vec!["arg"]),
+            incantation: Some(Incantation::new("command", vec!["arg"])),
+            shell: false,
+            watch: None,
+            steps: vec![],
+            job_file: None,
+            parallel: None,
+            signature_check: None,
             cli_config: UserConfig::default(),
             config_file: None,
             credentials_file: None,
@@ -1,18 +1,46 @@
+use crate::duration_format::{parse_duration, ParseDurationError};
+use crate::environment::{
+    load_variables, parse_variable, Environment, EnvironmentError, Stdin,
+};
+use crate::exit_code;
+use crate::exit_code::ExitCode;
+use crate::incantation::Incantation;
+use crate::notifications::discord::{DiscordConfig, DiscordCredentials};
 use crate::notifications::email::{EmailConfig, SmtpCredentials};
+use crate::notifications::slack::{SlackConfig, SlackCredentials};
+use crate::notifications::teams::{TeamsConfig, TeamsCredentials};
+use crate::notifications::webhook::{
+    UserWebhookConfig, WebhookConfig, WebhookCredentials,
+};
+use crate::retry::Backoff;
 use lazy_static::lazy_static;
+use regex::Regex;
 use semver::{SemVerError, Version};
 use serde::de::DeserializeOwned;
 use serde_derive::Deserialize;
 use std::default::Default;
+use std::env;
 use std::error;
+use std::ffi::OsString;
 use std::fmt;
 use std::fs;
 use std::io;
 use std::path::PathBuf;
 use std::time::Duration;
-use toml;
 
 pub type TimeoutInput = u64;
+pub type OutputLinesInput = usize;
+pub type RetriesInput = u32;
+
+/// How long to wait before retrying a failed command unless configured.
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// The shell that runs command lines if neither configured nor set in
+/// `$SHELL`.
+pub const DEFAULT_SHELL: &str = "/bin/sh";
+
+/// How often a trigger may notify unless configured.
+pub const DEFAULT_TRIGGER_RATE_LIMIT: Duration = Duration::from_secs(60);
 
 lazy_static! {
     static ref HOME_DIR: PathBuf = dirs::home_dir().unwrap_or_default();
@@ -38,8 +66,55 @@ pub struct UserConfig {
     pub version: Option<String>,
     pub desktop_notifications: Option<bool>,
     pub email: Option<EmailConfig>,
+    pub webhook: Option<UserWebhookConfig>,
+    pub slack: Option<SlackConfig>,
+    pub discord: Option<DiscordConfig>,
+    pub teams: Option<TeamsConfig>,
     pub validate: Option<bool>,
     pub timeout: Option<TimeoutInput>,
+    pub output_lines: Option<OutputLinesInput>,
+    pub pty: Option<bool>,
+    pub shell: Option<String>,
+    pub cwd: Option<String>,
+    pub env: Option<Vec<String>>,
+    pub env_file: Option<String>,
+    pub clear_env: Option<bool>,
+    pub stdin: Option<String>,
+    pub retries: Option<RetriesInput>,
+    pub retry_delay: Option<String>,
+    pub backoff: Option<Backoff>,
+    pub notify_each_attempt: Option<bool>,
+    pub success_codes: Option<Vec<ExitCode>>,
+    pub heartbeat: Option<String>,
+    pub stall_after: Option<String>,
+    pub kill_on_stall: Option<bool>,
+    pub keep_going: Option<bool>,
+    pub commands: Option<Vec<UserCommandConfig>>,
+    pub triggers: Option<Vec<UserTriggerConfig>>,
+    pub redact_defaults: Option<bool>,
+    pub redactions: Option<Vec<UserRedactionConfig>>,
+}
+
+/// Settings for commands whose command line matches `pattern`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct UserCommandConfig {
+    pub pattern: String,
+    pub success_codes: Option<Vec<ExitCode>>,
+}
+
+/// Notifies when a line of output matches `pattern`, at most once per
+/// `rate_limit`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct UserTriggerConfig {
+    pub pattern: String,
+    pub rate_limit: Option<String>,
+}
+
+/// Hides text matching `pattern`, or only its `secret` group if it has one,
+/// from notifications.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct UserRedactionConfig {
+    pub pattern: String,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -47,8 +122,54 @@ pub struct Config {
     pub version: Version,
     pub desktop_notifications: bool,
     pub email: Option<EmailConfig>,
+    pub webhook: Option<WebhookConfig>,
+    pub slack: Option<SlackConfig>,
+    pub discord: Option<DiscordConfig>,
+    pub teams: Option<TeamsConfig>,
     pub validate: bool,
     pub timeout: Option<Duration>,
+    pub output_lines: Option<usize>,
+    pub pty: bool,
+    /// The shell that runs command lines given with `--shell`, or the
+    /// user's shell if `None`.
+    pub shell: Option<PathBuf>,
+    pub environment: Environment,
+    pub retries: u32,
+    pub retry_delay: Duration,
+    pub backoff: Backoff,
+    pub notify_each_attempt: bool,
+    pub success_codes: Vec<ExitCode>,
+    /// How often to notify that the command is still running.
+    pub heartbeat: Option<Duration>,
+    /// How long the command may go without writing output before it counts
+    /// as stalled.
+    pub stall_after: Option<Duration>,
+    pub kill_on_stall: bool,
+    /// Whether to run the remaining steps of a job after one fails.
+    pub keep_going: bool,
+    pub commands: Vec<CommandConfig>,
+    pub triggers: Vec<TriggerConfig>,
+    /// Whether to also redact common shapes of secrets, such as passwords
+    /// passed as options.
+    pub redact_defaults: bool,
+    pub redactions: Vec<RedactionConfig>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CommandConfig {
+    pub pattern: Regex,
+    pub success_codes: Option<Vec<ExitCode>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TriggerConfig {
+    pub pattern: Regex,
+    pub rate_limit: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct RedactionConfig {
+    pub pattern: Regex,
 }
 
 #[derive(Debug, Default)]
@@ -60,12 +181,20 @@ pub struct MergeOptions {
 pub struct UserCredentials {
     pub version: Option<String>,
     pub smtp: Option<SmtpCredentials>,
+    pub webhook: Option<WebhookCredentials>,
+    pub slack: Option<SlackCredentials>,
+    pub discord: Option<DiscordCredentials>,
+    pub teams: Option<TeamsCredentials>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Credentials {
     pub version: Version,
     pub smtp: Option<SmtpCredentials>,
+    pub webhook: Option<WebhookCredentials>,
+    pub slack: Option<SlackCredentials>,
+    pub discord: Option<DiscordCredentials>,
+    pub teams: Option<TeamsCredentials>,
 }
 
 #[derive(Debug)]
@@ -78,6 +207,12 @@ pub enum LoadConfigError {
 pub enum ConfigError {
     MalformedVersion(SemVerError),
     InvalidVersion(Version),
+    MalformedPattern(regex::Error),
+    InvalidSuccessCode(ExitCode),
+    MalformedDuration(ParseDurationError),
+    Environment(EnvironmentError),
+    InvalidWebhookUrl(String),
+    InvalidWebhookMethod(String),
 }
 
 pub fn load_file<T: Default + DeserializeOwned>(
@@ -115,12 +250,51 @@ pub fn merge(
     let desktop_notifications = cli_config
         .desktop_notifications
         .or(file_config.desktop_notifications);
+    let mut commands = cli_config.commands.or(file_config.commands);
+    // Success codes given on the command line are meant for this command, so
+    // they take precedence over the ones configured for matching commands.
+    if let (Some(_), Some(commands)) =
+        (&cli_config.success_codes, &mut commands)
+    {
+        for command in commands {
+            command.success_codes = None;
+        }
+    }
     UserConfig {
         version: cli_config.version.or(file_config.version),
         desktop_notifications,
         email,
+        webhook: cli_config.webhook.or(file_config.webhook),
+        slack: cli_config.slack.or(file_config.slack),
+        discord: cli_config.discord.or(file_config.discord),
+        teams: cli_config.teams.or(file_config.teams),
         validate: cli_config.validate.or(file_config.validate),
         timeout: cli_config.timeout.or(file_config.timeout),
+        output_lines: cli_config.output_lines.or(file_config.output_lines),
+        pty: cli_config.pty.or(file_config.pty),
+        shell: cli_config.shell.or(file_config.shell),
+        cwd: cli_config.cwd.or(file_config.cwd),
+        env: cli_config.env.or(file_config.env),
+        env_file: cli_config.env_file.or(file_config.env_file),
+        clear_env: cli_config.clear_env.or(file_config.clear_env),
+        stdin: cli_config.stdin.or(file_config.stdin),
+        retries: cli_config.retries.or(file_config.retries),
+        retry_delay: cli_config.retry_delay.or(file_config.retry_delay),
+        backoff: cli_config.backoff.or(file_config.backoff),
+        notify_each_attempt: cli_config
+            .notify_each_attempt
+            .or(file_config.notify_each_attempt),
+        success_codes: cli_config.success_codes.or(file_config.success_codes),
+        heartbeat: cli_config.heartbeat.or(file_config.heartbeat),
+        stall_after: cli_config.stall_after.or(file_config.stall_after),
+        kill_on_stall: cli_config.kill_on_stall.or(file_config.kill_on_stall),
+        keep_going: cli_config.keep_going.or(file_config.keep_going),
+        commands,
+        triggers: cli_config.triggers.or(file_config.triggers),
+        redact_defaults: cli_config
+            .redact_defaults
+            .or(file_config.redact_defaults),
+        redactions: cli_config.redactions.or(file_config.redactions),
     }
 }
 
@@ -142,8 +316,62 @@ impl Config {
                 })
             }
         });
+        let webhook = match user_config.webhook {
+            Some(webhook) => {
+                Some(WebhookConfig::from_user_webhook_config(webhook)?)
+            },
+            None => None,
+        };
         let desktop_notifications =
             user_config.desktop_notifications.unwrap_or(true);
+        let success_codes = user_config
+            .success_codes
+            .unwrap_or_else(|| vec![exit_code::SUCCESS]);
+        validate_success_codes(&success_codes)?;
+        let retry_delay = match user_config.retry_delay {
+            Some(retry_delay) => parse_duration(&retry_delay)?,
+            None => DEFAULT_RETRY_DELAY,
+        };
+        let heartbeat = match user_config.heartbeat {
+            Some(heartbeat) => Some(parse_duration(&heartbeat)?),
+            None => None,
+        };
+        let stall_after = match user_config.stall_after {
+            Some(stall_after) => Some(parse_duration(&stall_after)?),
+            None => None,
+        };
+        let commands = user_config
+            .commands
+            .unwrap_or_default()
+            .into_iter()
+            .map(CommandConfig::from_user_command_config)
+            .collect::<Result<Vec<_>, _>>()?;
+        let triggers = user_config
+            .triggers
+            .unwrap_or_default()
+            .into_iter()
+            .map(TriggerConfig::from_user_trigger_config)
+            .collect::<Result<Vec<_>, _>>()?;
+        let redactions = user_config
+            .redactions
+            .unwrap_or_default()
+            .into_iter()
+            .map(RedactionConfig::from_user_redaction_config)
+            .collect::<Result<Vec<_>, _>>()?;
+        // Variables given directly override those in the env file.
+        let mut variables = match user_config.env_file {
+            Some(env_file) => load_variables(env_file.as_ref())?,
+            None => vec![],
+        };
+        for variable in user_config.env.unwrap_or_default() {
+            variables.push(parse_variable(&variable)?);
+        }
+        let environment = Environment {
+            working_directory: user_config.cwd.map(PathBuf::from),
+            variables,
+            clear: user_config.clear_env.unwrap_or(false),
+            stdin: user_config.stdin.map(|stdin| Stdin::parse(&stdin)),
+        };
         if *LATEST_CREDENTIALS_VERSION < version {
             Err(ConfigError::InvalidVersion(version))
         } else {
@@ -151,11 +379,132 @@ impl Config {
                 version,
                 desktop_notifications,
                 email,
+                webhook,
+                slack: user_config.slack,
+                discord: user_config.discord,
+                teams: user_config.teams,
                 validate: user_config.validate.unwrap_or(true),
                 timeout: user_config.timeout.map(Duration::from_secs),
+                output_lines: user_config
+                    .output_lines
+                    .filter(|&output_lines| output_lines > 0),
+                pty: user_config.pty.unwrap_or(false),
+                shell: user_config.shell.map(PathBuf::from),
+                environment,
+                retries: user_config.retries.unwrap_or(0),
+                retry_delay,
+                backoff: user_config.backoff.unwrap_or(Backoff::Constant),
+                notify_each_attempt: user_config
+                    .notify_each_attempt
+                    .unwrap_or(false),
+                success_codes,
+                heartbeat: heartbeat
+                    .filter(|heartbeat| *heartbeat > Duration::from_secs(0)),
+                stall_after: stall_after.filter(|stall_after| {
+                    *stall_after > Duration::from_secs(0)
+                }),
+                kill_on_stall: user_config.kill_on_stall.unwrap_or(false),
+                keep_going: user_config.keep_going.unwrap_or(false),
+                commands,
+                triggers,
+                redact_defaults: user_config.redact_defaults.unwrap_or(true),
+                redactions,
             })
         }
     }
+
+    /// Returns the exit codes that count as success for `incantation`, taken
+    /// from the first command config whose pattern matches it.
+    pub fn success_codes(&self, incantation: &Incantation) -> &[ExitCode] {
+        let command_line = incantation.to_string();
+        self.commands
+            .iter()
+            .filter(|command| command.pattern.is_match(&command_line))
+            .find_map(|command| command.success_codes.as_ref())
+            .unwrap_or(&self.success_codes)
+    }
+
+    /// Returns the shell that runs command lines: the configured one, else
+    /// the user's `$SHELL`, else `sh`.
+    pub fn shell_program(&self) -> OsString {
+        match self.shell {
+            Some(ref shell) => shell.clone().into(),
+            None => env::var_os("SHELL")
+                .filter(|shell| !shell.is_empty())
+                .unwrap_or_else(|| DEFAULT_SHELL.into()),
+        }
+    }
+}
+
+impl CommandConfig {
+    pub fn from_user_command_config(
+        user_command_config: UserCommandConfig,
+    ) -> Result<Self, ConfigError> {
+        let pattern = Regex::new(&user_command_config.pattern)?;
+        if let Some(ref success_codes) = user_command_config.success_codes {
+            validate_success_codes(success_codes)?;
+        }
+        Ok(CommandConfig {
+            pattern,
+            success_codes: user_command_config.success_codes,
+        })
+    }
+}
+
+impl PartialEq for CommandConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str() &&
+            self.success_codes == other.success_codes
+    }
+}
+
+impl TriggerConfig {
+    pub fn from_user_trigger_config(
+        user_trigger_config: UserTriggerConfig,
+    ) -> Result<Self, ConfigError> {
+        let rate_limit = match user_trigger_config.rate_limit {
+            Some(rate_limit) => parse_duration(&rate_limit)?,
+            None => DEFAULT_TRIGGER_RATE_LIMIT,
+        };
+        Ok(TriggerConfig {
+            pattern: Regex::new(&user_trigger_config.pattern)?,
+            rate_limit,
+        })
+    }
+}
+
+impl PartialEq for TriggerConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str() &&
+            self.rate_limit == other.rate_limit
+    }
+}
+
+impl RedactionConfig {
+    pub fn from_user_redaction_config(
+        user_redaction_config: UserRedactionConfig,
+    ) -> Result<Self, ConfigError> {
+        Ok(RedactionConfig {
+            pattern: Regex::new(&user_redaction_config.pattern)?,
+        })
+    }
+}
+
+impl PartialEq for RedactionConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str()
+    }
+}
+
+/// Exit codes are truncated to their least-significant eight bits, so codes
+/// outside of that range can never be matched.
+fn validate_success_codes(
+    success_codes: &[ExitCode],
+) -> Result<(), ConfigError> {
+    match success_codes.iter().find(|code| !(0..=255).contains(*code)) {
+        Some(&code) => Err(ConfigError::InvalidSuccessCode(code)),
+        None => Ok(()),
+    }
 }
 
 impl Credentials {
@@ -173,6 +522,10 @@ impl Credentials {
             Ok(Credentials {
                 version,
                 smtp: user_credentials.smtp,
+                webhook: user_credentials.webhook,
+                slack: user_credentials.slack,
+                discord: user_credentials.discord,
+                teams: user_credentials.teams,
             })
         }
     }
@@ -211,6 +564,24 @@ impl fmt::Display for ConfigError {
                  current version ({}).",
                 version, *LATEST_CREDENTIALS_VERSION
             ),
+            ConfigError::MalformedPattern(ref err) => err.fmt(formatter),
+            ConfigError::InvalidSuccessCode(code) => write!(
+                formatter,
+                "Success code {} is not an exit code between 0 and 255.",
+                code
+            ),
+            ConfigError::MalformedDuration(ref err) => err.fmt(formatter),
+            ConfigError::Environment(ref err) => err.fmt(formatter),
+            ConfigError::InvalidWebhookUrl(ref url) => write!(
+                formatter,
+                "Webhook URL {} does not start with http:// or https://.",
+                url
+            ),
+            ConfigError::InvalidWebhookMethod(ref method) => write!(
+                formatter,
+                "Webhook method {} is not one of POST, PUT or PATCH.",
+                method
+            ),
         }
     }
 }
@@ -229,9 +600,28 @@ impl From<Version> for ConfigError {
     }
 }
 
+impl From<regex::Error> for ConfigError {
+    fn from(err: regex::Error) -> Self {
+        ConfigError::MalformedPattern(err)
+    }
+}
+
+impl From<ParseDurationError> for ConfigError {
+    fn from(err: ParseDurationError) -> Self {
+        ConfigError::MalformedDuration(err)
+    }
+}
+
+impl From<EnvironmentError> for ConfigError {
+    fn from(err: EnvironmentError) -> Self {
+        ConfigError::Environment(err)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::notifications::webhook::DEFAULT_WEBHOOK_TIMEOUT;
     use crate::test::PROJECT_ROOT_PATH;
     use matches::assert_matches;
 
@@ -250,8 +640,42 @@ mod test {
                 version: LATEST_CONFIG_VERSION.clone(),
                 desktop_notifications: true,
                 email: None,
+                webhook: None,
+                slack: None,
+                discord: None,
+                teams: None,
                 validate: true,
                 timeout: None,
+                output_lines: None,
+                pty: false,
+                shell: None,
+                environment: Environment::default(),
+                retries: 0,
+                retry_delay: DEFAULT_RETRY_DELAY,
+                backoff: Backoff::Constant,
+                notify_each_attempt: false,
+                success_codes: vec![exit_code::SUCCESS],
+                heartbeat: None,
+                stall_after: None,
+                kill_on_stall: false,
+                keep_going: false,
+                commands: vec![],
+                triggers: vec![],
+                redact_defaults: true,
+                redactions: vec![],
+            }
+        }
+    }
+
+    impl Default for Credentials {
+        fn default() -> Self {
+            Credentials {
+                version: LATEST_CONFIG_VERSION.clone(),
+                smtp: None,
+                webhook: None,
+                slack: None,
+                discord: None,
+                teams: None,
             }
         }
     }
@@ -270,8 +694,59 @@ mod test {
             email: Some(EmailConfig {
                 recipients: vec!["someone@example.com".to_string()],
             }),
+            webhook: Some(UserWebhookConfig {
+                url: "https://example.com/hooks/diditrun".to_string(),
+                method: Some("PUT".to_string()),
+                headers: Some(
+                    vec![("X-Source".to_string(), "diditrun".to_string())]
+                        .into_iter()
+                        .collect(),
+                ),
+                timeout: Some("5s".to_string()),
+            }),
+            slack: Some(SlackConfig {
+                channel: Some("#builds".to_string()),
+                username: Some("diditrun".to_string()),
+                icon_emoji: None,
+            }),
+            discord: Some(DiscordConfig {
+                username: Some("diditrun".to_string()),
+                avatar_url: None,
+            }),
+            teams: Some(TeamsConfig {
+                full_width: Some(true),
+            }),
             validate: Some(true),
             timeout: Some(42),
+            output_lines: Some(20),
+            pty: Some(true),
+            shell: Some("/bin/bash".to_string()),
+            cwd: Some("/tmp".to_string()),
+            env: Some(vec!["RUST_LOG=debug".to_string()]),
+            env_file: None,
+            clear_env: Some(false),
+            stdin: Some("null".to_string()),
+            retries: Some(3),
+            retry_delay: Some("30s".to_string()),
+            backoff: Some(Backoff::Exponential),
+            notify_each_attempt: Some(true),
+            success_codes: Some(vec![0]),
+            heartbeat: Some("6h".to_string()),
+            stall_after: Some("20m".to_string()),
+            kill_on_stall: Some(true),
+            keep_going: Some(true),
+            commands: Some(vec![UserCommandConfig {
+                pattern: "^grep ".to_string(),
+                success_codes: Some(vec![0, 1]),
+            }]),
+            triggers: Some(vec![UserTriggerConfig {
+                pattern: "NaN loss".to_string(),
+                rate_limit: Some("10m".to_string()),
+            }]),
+            redact_defaults: Some(true),
+            redactions: Some(vec![UserRedactionConfig {
+                pattern: "--vault-token (?P<secret>\\S+)".to_string(),
+            }]),
         };
         assert_eq!(user_config.unwrap(), expected_user_config);
     }
@@ -287,8 +762,33 @@ mod test {
             email: Some(EmailConfig {
                 recipients: vec!["default@example.com".to_string()],
             }),
+            webhook: None,
+            slack: None,
+            discord: None,
+            teams: None,
             validate: Some(false),
             timeout: Some(30),
+            output_lines: None,
+            pty: None,
+            shell: None,
+            cwd: None,
+            env: None,
+            env_file: None,
+            clear_env: None,
+            stdin: None,
+            retries: None,
+            retry_delay: None,
+            backoff: None,
+            notify_each_attempt: None,
+            success_codes: None,
+            heartbeat: None,
+            stall_after: None,
+            kill_on_stall: None,
+            keep_going: None,
+            commands: None,
+            triggers: None,
+            redact_defaults: None,
+            redactions: None,
         };
         assert_eq!(user_config.unwrap(), expected_user_config);
     }
@@ -337,6 +837,29 @@ mod test {
             smtp: Some(SmtpCredentials::new(
                 "hostname", 587, "username", "password",
             )),
+            webhook: Some(WebhookCredentials {
+                headers: Some(
+                    vec![(
+                        "Authorization".to_string(),
+                        "Bearer token".to_string(),
+                    )]
+                    .into_iter()
+                    .collect(),
+                ),
+                secret: Some("secret".to_string()),
+            }),
+            slack: Some(SlackCredentials {
+                webhook_url: "https://hooks.slack.com/services/T/B/X"
+                    .to_string(),
+            }),
+            discord: Some(DiscordCredentials {
+                webhook_url: "https://discord.com/api/webhooks/1/token"
+                    .to_string(),
+            }),
+            teams: Some(TeamsCredentials {
+                webhook_url: "https://example.webhook.office.com/webhook"
+                    .to_string(),
+            }),
         };
         assert_eq!(user_credentials.unwrap(), expected_user_credentials);
     }
@@ -356,6 +879,7 @@ mod test {
                 "username",
                 "password",
             )),
+            ..Default::default()
         };
         assert_eq!(user_credentials.unwrap(), expected_user_credentials);
     }
@@ -409,8 +933,49 @@ mod test {
             email: Some(EmailConfig {
                 recipients: vec!["cli_config@example.com".to_string()],
             }),
+            webhook: Some(UserWebhookConfig {
+                url: "https://example.com/cli_config".to_string(),
+                ..Default::default()
+            }),
+            slack: Some(SlackConfig {
+                channel: Some("#cli_config".to_string()),
+                ..Default::default()
+            }),
+            discord: Some(DiscordConfig {
+                username: Some("cli_config".to_string()),
+                ..Default::default()
+            }),
+            teams: Some(TeamsConfig {
+                full_width: Some(true),
+            }),
             validate: Some(true),
             timeout: Some(10),
+            output_lines: Some(5),
+            pty: Some(true),
+            shell: Some("zsh".to_string()),
+            cwd: Some("cli".to_string()),
+            env: Some(vec!["SOURCE=cli".to_string()]),
+            env_file: Some("cli.env".to_string()),
+            clear_env: Some(true),
+            stdin: Some("null".to_string()),
+            retries: Some(2),
+            retry_delay: Some("1m".to_string()),
+            backoff: Some(Backoff::Exponential),
+            notify_each_attempt: Some(true),
+            success_codes: Some(vec![0, 1]),
+            heartbeat: Some("6h".to_string()),
+            stall_after: Some("20m".to_string()),
+            kill_on_stall: Some(true),
+            keep_going: Some(true),
+            commands: None,
+            triggers: Some(vec![UserTriggerConfig {
+                pattern: "error:".to_string(),
+                rate_limit: None,
+            }]),
+            redact_defaults: Some(false),
+            redactions: Some(vec![UserRedactionConfig {
+                pattern: "hunter2".to_string(),
+            }]),
         };
         let file_config: UserConfig = Default::default();
         let merged =
@@ -423,29 +988,128 @@ mod test {
             email: Some(EmailConfig {
                 recipients: vec!["file_config@example.com".to_string()],
             }),
+            webhook: Some(UserWebhookConfig {
+                url: "https://example.com/file_config".to_string(),
+                ..Default::default()
+            }),
+            slack: Some(SlackConfig {
+                channel: Some("#file_config".to_string()),
+                ..Default::default()
+            }),
+            discord: Some(DiscordConfig {
+                username: Some("file_config".to_string()),
+                ..Default::default()
+            }),
+            teams: Some(TeamsConfig {
+                full_width: Some(false),
+            }),
             validate: Some(false),
             timeout: Some(30),
+            output_lines: Some(50),
+            pty: Some(false),
+            shell: Some("bash".to_string()),
+            cwd: Some("file".to_string()),
+            env: Some(vec!["SOURCE=file".to_string()]),
+            env_file: Some("file.env".to_string()),
+            clear_env: Some(false),
+            stdin: Some("in.txt".to_string()),
+            retries: Some(5),
+            retry_delay: Some("5s".to_string()),
+            backoff: Some(Backoff::Constant),
+            notify_each_attempt: Some(false),
+            success_codes: Some(vec![0, 24]),
+            heartbeat: Some("1d".to_string()),
+            stall_after: Some("1h".to_string()),
+            kill_on_stall: Some(false),
+            keep_going: Some(false),
+            commands: Some(vec![UserCommandConfig {
+                pattern: "^diff ".to_string(),
+                success_codes: Some(vec![0, 1]),
+            }]),
+            triggers: Some(vec![UserTriggerConfig {
+                pattern: "warning:".to_string(),
+                rate_limit: Some("5m".to_string()),
+            }]),
+            redact_defaults: Some(true),
+            redactions: None,
         };
         let merged = merge(
             cli_config.clone(),
             file_config.clone(),
             MergeOptions::default(),
         );
-        assert_eq!(merged, cli_config);
+        let expected = UserConfig {
+            commands: Some(vec![UserCommandConfig {
+                pattern: "^diff ".to_string(),
+                success_codes: None,
+            }]),
+            ..cli_config.clone()
+        };
+        assert_eq!(merged, expected);
 
         let cli_config = UserConfig {
             version: Some(LATEST_CONFIG_VERSION.to_string()),
             desktop_notifications: Some(true),
             email: None,
+            webhook: None,
+            slack: None,
+            discord: None,
+            teams: None,
             validate: Some(true),
             timeout: None,
+            output_lines: None,
+            pty: None,
+            shell: None,
+            cwd: None,
+            env: None,
+            env_file: None,
+            clear_env: None,
+            stdin: None,
+            retries: None,
+            retry_delay: None,
+            backoff: None,
+            notify_each_attempt: None,
+            success_codes: None,
+            heartbeat: None,
+            stall_after: None,
+            kill_on_stall: None,
+            keep_going: None,
+            commands: None,
+            triggers: None,
+            redact_defaults: None,
+            redactions: None,
         };
         let expected = UserConfig {
             version: cli_config.version.clone(),
             desktop_notifications: cli_config.desktop_notifications,
             email: file_config.email.clone(),
+            webhook: file_config.webhook.clone(),
+            slack: file_config.slack.clone(),
+            discord: file_config.discord.clone(),
+            teams: file_config.teams.clone(),
             validate: cli_config.validate,
             timeout: file_config.timeout,
+            output_lines: file_config.output_lines,
+            pty: file_config.pty,
+            shell: file_config.shell.clone(),
+            cwd: file_config.cwd.clone(),
+            env: file_config.env.clone(),
+            env_file: file_config.env_file.clone(),
+            clear_env: file_config.clear_env,
+            stdin: file_config.stdin.clone(),
+            retries: file_config.retries,
+            retry_delay: file_config.retry_delay.clone(),
+            backoff: file_config.backoff,
+            notify_each_attempt: file_config.notify_each_attempt,
+            success_codes: file_config.success_codes.clone(),
+            heartbeat: file_config.heartbeat.clone(),
+            stall_after: file_config.stall_after.clone(),
+            kill_on_stall: file_config.kill_on_stall,
+            keep_going: file_config.keep_going,
+            commands: file_config.commands.clone(),
+            triggers: file_config.triggers.clone(),
+            redact_defaults: file_config.redact_defaults,
+            redactions: file_config.redactions.clone(),
         };
         let merged = merge(cli_config, file_config, MergeOptions::default());
         assert_eq!(merged, expected);
@@ -460,20 +1124,111 @@ mod test {
         let desktop_notifications = true;
         let validate = true;
         let timeout = 12;
+        let output_lines = 20;
+        let pty = true;
+        let env_file = CONFIG_FIXTURE_PATH.join("env");
+        let retries = 3;
+        let backoff = Backoff::Exponential;
+        let success_codes = vec![0, 1];
+        let command_config = UserCommandConfig {
+            pattern: "^rsync ".to_string(),
+            success_codes: Some(vec![0, 24]),
+        };
+        let trigger_config = UserTriggerConfig {
+            pattern: "error:".to_string(),
+            rate_limit: None,
+        };
+        let redaction_config = UserRedactionConfig {
+            pattern: "token=(?P<secret>\\w+)".to_string(),
+        };
         let user_config = UserConfig {
             version: Some(version.to_string()),
             desktop_notifications: Some(desktop_notifications),
             email: Some(email_config.clone()),
+            webhook: Some(UserWebhookConfig {
+                url: "http://localhost:8080/".to_string(),
+                ..Default::default()
+            }),
+            slack: Some(SlackConfig::default()),
+            discord: None,
+            teams: None,
             validate: Some(validate),
             timeout: Some(timeout),
+            output_lines: Some(output_lines),
+            pty: Some(pty),
+            shell: Some("fish".to_string()),
+            cwd: Some("/tmp".to_string()),
+            env: Some(vec!["RUST_LOG=trace".to_string()]),
+            env_file: Some(env_file.to_string_lossy().into_owned()),
+            clear_env: Some(true),
+            stdin: Some("in.txt".to_string()),
+            retries: Some(retries),
+            retry_delay: Some("1h30m".to_string()),
+            backoff: Some(backoff),
+            notify_each_attempt: Some(true),
+            success_codes: Some(success_codes.clone()),
+            heartbeat: Some("6h".to_string()),
+            stall_after: Some("20m".to_string()),
+            kill_on_stall: Some(true),
+            keep_going: Some(true),
+            commands: Some(vec![command_config.clone()]),
+            triggers: Some(vec![trigger_config.clone()]),
+            redact_defaults: Some(false),
+            redactions: Some(vec![redaction_config.clone()]),
         };
         let config = Config::from_user_config(user_config).unwrap();
         let expected_config = Config {
             version,
             desktop_notifications,
             email: Some(email_config),
+            webhook: Some(WebhookConfig {
+                url: "http://localhost:8080/".to_string(),
+                method: "POST".to_string(),
+                headers: Default::default(),
+                timeout: DEFAULT_WEBHOOK_TIMEOUT,
+            }),
+            slack: Some(SlackConfig::default()),
+            discord: None,
+            teams: None,
             validate,
             timeout: Some(Duration::from_secs(timeout)),
+            output_lines: Some(output_lines),
+            pty,
+            shell: Some(PathBuf::from("fish")),
+            environment: Environment {
+                working_directory: Some(PathBuf::from("/tmp")),
+                variables: vec![
+                    ("RUST_LOG".to_string(), "debug".to_string()),
+                    ("GREETING".to_string(), "hello world".to_string()),
+                    ("QUOTED".to_string(), "it's".to_string()),
+                    ("RUST_LOG".to_string(), "info".to_string()),
+                    ("RUST_LOG".to_string(), "trace".to_string()),
+                ],
+                clear: true,
+                stdin: Some(Stdin::File(PathBuf::from("in.txt"))),
+            },
+            retries,
+            retry_delay: Duration::from_secs(90 * 60),
+            backoff,
+            notify_each_attempt: true,
+            success_codes,
+            heartbeat: Some(Duration::from_secs(6 * 60 * 60)),
+            stall_after: Some(Duration::from_secs(20 * 60)),
+            kill_on_stall: true,
+            keep_going: true,
+            commands: vec![CommandConfig::from_user_command_config(
+                command_config,
+            )
+            .unwrap()],
+            triggers: vec![TriggerConfig::from_user_trigger_config(
+                trigger_config,
+            )
+            .unwrap()],
+            redact_defaults: false,
+            redactions: vec![RedactionConfig::from_user_redaction_config(
+                redaction_config,
+            )
+            .unwrap()],
         };
         assert_eq!(config, expected_config);
     }
@@ -500,20 +1255,242 @@ mod test {
         assert_eq!(config.unwrap().email, None);
     }
 
+    #[test]
+    fn config_disables_output_capture_with_zero_output_lines() {
+        let user_config = UserConfig {
+            output_lines: Some(0),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config);
+        assert!(config.is_ok());
+        assert_eq!(config.unwrap().output_lines, None);
+    }
+
+    #[test]
+    fn config_disables_heartbeat_with_zero_interval() {
+        let user_config = UserConfig {
+            heartbeat: Some("0s".to_string()),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config);
+        assert!(config.is_ok());
+        assert_eq!(config.unwrap().heartbeat, None);
+    }
+
+    #[test]
+    fn config_disables_stall_detection_with_zero_duration() {
+        let user_config = UserConfig {
+            stall_after: Some("0s".to_string()),
+            kill_on_stall: Some(true),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config);
+        assert!(config.is_ok());
+        assert_eq!(config.unwrap().stall_after, None);
+    }
+
+    #[test]
+    fn config_limits_trigger_rate_by_default() {
+        let user_config = UserConfig {
+            triggers: Some(vec![
+                UserTriggerConfig {
+                    pattern: "error:".to_string(),
+                    rate_limit: None,
+                },
+                UserTriggerConfig {
+                    pattern: "NaN loss".to_string(),
+                    rate_limit: Some("0s".to_string()),
+                },
+            ]),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config).unwrap();
+        let rate_limits = config
+            .triggers
+            .iter()
+            .map(|trigger| trigger.rate_limit)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rate_limits,
+            [DEFAULT_TRIGGER_RATE_LIMIT, Duration::from_secs(0)]
+        );
+    }
+
+    #[test]
+    fn config_rejects_malformed_trigger() {
+        let user_config = UserConfig {
+            triggers: Some(vec![UserTriggerConfig {
+                pattern: "error:(".to_string(),
+                rate_limit: None,
+            }]),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config);
+        assert_matches!(config.unwrap_err(), ConfigError::MalformedPattern(_));
+        let user_config = UserConfig {
+            triggers: Some(vec![UserTriggerConfig {
+                pattern: "error:".to_string(),
+                rate_limit: Some("never".to_string()),
+            }]),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config);
+        assert_matches!(config.unwrap_err(), ConfigError::MalformedDuration(_));
+    }
+
+    #[test]
+    fn config_rejects_malformed_environment() {
+        let user_config = UserConfig {
+            env: Some(vec!["RUST_LOG".to_string()]),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config);
+        assert_matches!(config.unwrap_err(), ConfigError::Environment(_));
+        let user_config = UserConfig {
+            env_file: Some("does/not/exist".to_string()),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config);
+        assert_matches!(config.unwrap_err(), ConfigError::Environment(_));
+    }
+
+    #[test]
+    fn config_does_not_retry_by_default() {
+        let config = Config::from_user_config(UserConfig::default()).unwrap();
+        assert_eq!(config.retries, 0);
+        assert_eq!(config.retry_delay, DEFAULT_RETRY_DELAY);
+        assert_eq!(config.backoff, Backoff::Constant);
+        assert!(!config.notify_each_attempt);
+    }
+
+    #[test]
+    fn config_rejects_malformed_retry_delay() {
+        let user_config = UserConfig {
+            retry_delay: Some("soon".to_string()),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config);
+        assert_matches!(config.unwrap_err(), ConfigError::MalformedDuration(_));
+    }
+
+    #[test]
+    fn config_defaults_to_success_exit_code() {
+        let config = Config::from_user_config(UserConfig::default()).unwrap();
+        assert_eq!(config.success_codes, [exit_code::SUCCESS]);
+    }
+
+    #[test]
+    fn config_rejects_out_of_range_success_codes() {
+        for &code in &[-1, 256] {
+            let user_config = UserConfig {
+                success_codes: Some(vec![0, code]),
+                ..Default::default()
+            };
+            let config = Config::from_user_config(user_config);
+            assert_matches!(
+                config.unwrap_err(),
+                ConfigError::InvalidSuccessCode(c) if c == code
+            );
+            let user_config = UserConfig {
+                commands: Some(vec![UserCommandConfig {
+                    pattern: "foo".to_string(),
+                    success_codes: Some(vec![code]),
+                }]),
+                ..Default::default()
+            };
+            let config = Config::from_user_config(user_config);
+            assert_matches!(
+                config.unwrap_err(),
+                ConfigError::InvalidSuccessCode(c) if c == code
+            );
+        }
+    }
+
+    #[test]
+    fn config_rejects_malformed_command_pattern() {
+        let user_config = UserConfig {
+            commands: Some(vec![UserCommandConfig {
+                pattern: "(unclosed".to_string(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config);
+        assert_matches!(config.unwrap_err(), ConfigError::MalformedPattern(_));
+    }
+
+    #[test]
+    fn chooses_success_codes_by_command_pattern() {
+        let user_config = UserConfig {
+            success_codes: Some(vec![0, 2]),
+            commands: Some(vec![
+                UserCommandConfig {
+                    pattern: "^grep ".to_string(),
+                    success_codes: None,
+                },
+                UserCommandConfig {
+                    pattern: "^(grep|diff) ".to_string(),
+                    success_codes: Some(vec![0, 1]),
+                },
+                UserCommandConfig {
+                    pattern: "^grep ".to_string(),
+                    success_codes: Some(vec![5]),
+                },
+            ]),
+            ..Default::default()
+        };
+        let config = Config::from_user_config(user_config).unwrap();
+        let grep = Incantation::new("grep", vec!["foo", "bar.txt"]);
+        assert_eq!(config.success_codes(&grep), [0, 1]);
+        let rsync = Incantation::new("rsync", vec!["-a", "src", "dst"]);
+        assert_eq!(config.success_codes(&rsync), [0, 2]);
+    }
+
+    #[test]
+    fn prefers_configured_shell() {
+        let config = Config {
+            shell: Some(PathBuf::from("/bin/zsh")),
+            ..Default::default()
+        };
+        assert_eq!(config.shell_program(), "/bin/zsh");
+        let shell = env::var_os("SHELL").filter(|shell| !shell.is_empty());
+        assert_eq!(
+            Config::default().shell_program(),
+            shell.unwrap_or_else(|| DEFAULT_SHELL.into())
+        );
+    }
+
     #[test]
     fn creates_credentials_from_user_credentials() {
         let version = LATEST_CONFIG_VERSION.clone();
         let smtp_credentials =
             SmtpCredentials::new("hostname", 1234, "username", "password");
+        let webhook_credentials = WebhookCredentials {
+            headers: Some(
+                vec![("Authorization".to_string(), "Bearer t0k".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            secret: Some("s3cret".to_string()),
+        };
+        let slack_credentials = SlackCredentials {
+            webhook_url: "https://hooks.slack.com/services/T/B/X".to_string(),
+        };
         let user_credentials = UserCredentials {
             version: Some(version.to_string()),
             smtp: Some(smtp_credentials.clone()),
+            webhook: Some(webhook_credentials.clone()),
+            slack: Some(slack_credentials.clone()),
+            ..Default::default()
         };
         let credentials =
             Credentials::from_user_credentials(user_credentials).unwrap();
         let expected_credentials = Credentials {
             version,
             smtp: Some(smtp_credentials),
+            webhook: Some(webhook_credentials),
+            slack: Some(slack_credentials),
+            ..Default::default()
         };
         assert_eq!(credentials, expected_credentials);
     }
@@ -525,12 +1502,13 @@ mod test {
         let user_credentials = UserCredentials {
             version: None,
             smtp: Some(smtp_credentials.clone()),
+            ..Default::default()
         };
         let credentials =
             Credentials::from_user_credentials(user_credentials).unwrap();
         let expected_credentials = Credentials {
-            version: LATEST_CONFIG_VERSION.clone(),
             smtp: Some(smtp_credentials),
+            ..Default::default()
         };
         assert_eq!(credentials, expected_credentials);
     }
@@ -574,7 +1552,7 @@ mod test {
 
     #[test]
     fn error_conversion() {
-        let io_err = io::Error::new(io::ErrorKind::Other, "oh no!");
+        let io_err = io::Error::other("oh no!");
         let load_config_err = LoadConfigError::from(io_err);
         assert_matches!(load_config_err, LoadConfigError::Io(_));
 
@@ -594,7 +1572,7 @@ mod test {
     // Hacks to minimize coverage report errors
     #[test]
     fn maximize_coverage() {
-        let io_err = io::Error::new(io::ErrorKind::Other, "oh no!");
+        let io_err = io::Error::other("oh no!");
         let load_config_err = LoadConfigError::Io(io_err);
         let _ = format!("{:?}", load_config_err);
 
@@ -609,5 +1587,10 @@ mod test {
         let version = Version::parse("1.2.3").unwrap();
         let config_err = ConfigError::InvalidVersion(version);
         let _ = format!("{:?}", config_err);
+
+        let config_err = ConfigError::InvalidWebhookUrl("oops".to_string());
+        let _ = format!("{:?} {}", config_err, config_err);
+        let config_err = ConfigError::InvalidWebhookMethod("GET".to_string());
+        let _ = format!("{:?} {}", config_err, config_err);
     }
 }
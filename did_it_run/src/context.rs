@@ -0,0 +1,251 @@
+use crate::environment::Environment;
+use crate::watch::Pid;
+use chrono::{DateTime, Local};
+use std::env;
+#[cfg(unix)]
+use std::ffi::CStr;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+/// How timestamps are shown, e.g., "2020-01-31 17:04:05 -08:00".
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S %:z";
+
+/// Where and when a command ran, to tell apart runs on different machines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunContext {
+    pub start_time: DateTime<Local>,
+    /// When the command ended, or `None` while it is still running.
+    pub end_time: Option<DateTime<Local>>,
+    pub hostname: Option<String>,
+    pub username: Option<String>,
+    pub working_directory: Option<PathBuf>,
+    /// The names of the variables set for the command, but not their values.
+    pub variables: Vec<String>,
+    /// Whether the command ran with only those variables.
+    pub cleared_environment: bool,
+    /// The PID of diditrun itself rather than of the command.
+    pub pid: Pid,
+}
+
+impl RunContext {
+    /// Describes a run starting now.
+    pub fn start() -> Self {
+        RunContext {
+            start_time: Local::now(),
+            end_time: None,
+            hostname: hostname(),
+            username: username(),
+            working_directory: env::current_dir().ok(),
+            variables: vec![],
+            cleared_environment: false,
+            pid: process::id(),
+        }
+    }
+
+    /// Describes the same run, of a command run in `environment`.
+    pub fn with_environment(self, environment: &Environment) -> Self {
+        let working_directory = match environment.working_directory {
+            Some(ref directory) => Some(match self.working_directory {
+                Some(ref current) => current.join(directory),
+                None => directory.clone(),
+            }),
+            None => self.working_directory,
+        };
+        RunContext {
+            working_directory,
+            variables: environment.variable_names(),
+            cleared_environment: environment.clear,
+            ..self
+        }
+    }
+
+    /// Describes a run that started `elapsed_time` ago.
+    pub fn started_ago(elapsed_time: Duration) -> Self {
+        let elapsed_time = chrono::Duration::from_std(elapsed_time)
+            .unwrap_or_else(|_| chrono::Duration::zero());
+        RunContext {
+            start_time: Local::now() - elapsed_time,
+            ..RunContext::start()
+        }
+    }
+
+    /// Describes the same run, ending now.
+    pub fn finish(&self) -> Self {
+        RunContext {
+            end_time: Some(Local::now()),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a line per known detail, e.g., "Host: build-3".
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines =
+            vec![format!("Started: {}", self.start_time.format(TIME_FORMAT))];
+        if let Some(end_time) = self.end_time {
+            lines.push(format!("Ended: {}", end_time.format(TIME_FORMAT)));
+        }
+        if let Some(ref hostname) = self.hostname {
+            lines.push(format!("Host: {}", hostname));
+        }
+        if let Some(ref username) = self.username {
+            lines.push(format!("User: {}", username));
+        }
+        if let Some(ref working_directory) = self.working_directory {
+            lines.push(format!("Directory: {}", working_directory.display()));
+        }
+        if self.cleared_environment {
+            lines.push("Environment: cleared".to_string());
+        }
+        if !self.variables.is_empty() {
+            lines.push(format!("Variables set: {}", self.variables.join(", ")));
+        }
+        lines.push(format!("diditrun PID: {}", self.pid));
+        lines
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buffer = [0 as libc::c_char; 256];
+    // Safe because the buffer's length is passed along with it, and it is
+    // terminated below in case the name was truncated.
+    unsafe {
+        if libc::gethostname(buffer.as_mut_ptr(), buffer.len()) != 0 {
+            return None;
+        }
+        buffer[buffer.len() - 1] = 0;
+        let name = CStr::from_ptr(buffer.as_ptr()).to_string_lossy();
+        Some(name.into_owned()).filter(|name| !name.is_empty())
+    }
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    env::var("COMPUTERNAME").ok()
+}
+
+/// Looks up the user diditrun runs as, falling back on the environment if
+/// they have no entry in the password database.
+#[cfg(unix)]
+fn username() -> Option<String> {
+    let mut buffer = vec![0 as libc::c_char; 4096];
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    // Safe because the entry and the strings it points to live in `passwd`
+    // and `buffer`, whose length is passed along with it, and are copied
+    // before either goes out of scope.
+    unsafe {
+        libc::getpwuid_r(
+            libc::getuid(),
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        );
+        if !result.is_null() {
+            let name = CStr::from_ptr(passwd.pw_name).to_string_lossy();
+            return Some(name.into_owned());
+        }
+    }
+    env::var("USER").ok()
+}
+
+#[cfg(not(unix))]
+fn username() -> Option<String> {
+    env::var("USERNAME").ok()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use lazy_static::lazy_static;
+    use regex::Regex;
+
+    lazy_static! {
+        pub static ref CONTEXT: RunContext = RunContext {
+            start_time: Local.timestamp_opt(1_580_519_045, 0).unwrap(),
+            end_time: Some(Local.timestamp_opt(1_580_521_505, 0).unwrap()),
+            hostname: Some("build-3".to_string()),
+            username: Some("ci".to_string()),
+            working_directory: Some(PathBuf::from("/home/ci/project")),
+            variables: vec![],
+            cleared_environment: false,
+            pid: 1234,
+        };
+    }
+
+    #[test]
+    fn describes_this_process() {
+        let context = RunContext::start();
+        assert_eq!(context.pid, process::id());
+        assert!(context.hostname.is_some());
+        assert_eq!(context.working_directory, env::current_dir().ok());
+        assert_eq!(context.end_time, None);
+        assert!(context.start_time <= context.finish().end_time.unwrap());
+    }
+
+    #[test]
+    fn starts_in_the_past() {
+        let context = RunContext::started_ago(Duration::from_secs(60));
+        assert!(
+            context.start_time <= Local::now() - chrono::Duration::minutes(1)
+        );
+    }
+
+    #[test]
+    fn lists_known_details() {
+        let lines = CONTEXT.lines();
+        // The times are shown in the local timezone, whichever it is.
+        let time = r"\d{4}-\d{2}-\d{2} \d{2}:\d{2}:05 [+-]\d{2}:\d{2}";
+        let started = Regex::new(&format!("^Started: {}$", time)).unwrap();
+        let ended = Regex::new(&format!("^Ended: {}$", time)).unwrap();
+        assert!(started.is_match(&lines[0]), "{}", lines[0]);
+        assert!(ended.is_match(&lines[1]), "{}", lines[1]);
+        let expected = [
+            "Host: build-3",
+            "User: ci",
+            "Directory: /home/ci/project",
+            "diditrun PID: 1234",
+        ];
+        assert_eq!(lines[2..], expected);
+        let context = RunContext {
+            end_time: None,
+            hostname: None,
+            username: None,
+            working_directory: None,
+            ..CONTEXT.clone()
+        };
+        assert_eq!(context.lines().len(), 2);
+    }
+
+    #[test]
+    fn describes_environment_of_command() {
+        let environment = Environment {
+            working_directory: Some(PathBuf::from("build")),
+            variables: vec![
+                ("CI".to_string(), "1".to_string()),
+                ("TOKEN".to_string(), "hunter2".to_string()),
+            ],
+            clear: true,
+            ..Default::default()
+        };
+        let context = CONTEXT.clone().with_environment(&environment);
+        assert_eq!(
+            context.working_directory,
+            Some(PathBuf::from("/home/ci/project/build"))
+        );
+        let lines = context.lines();
+        let expected = [
+            "Directory: /home/ci/project/build",
+            "Environment: cleared",
+            "Variables set: CI, TOKEN",
+            "diditrun PID: 1234",
+        ];
+        assert_eq!(lines[4..], expected);
+        assert!(!lines.iter().any(|line| line.contains("hunter2")));
+        let context = CONTEXT.clone().with_environment(&Default::default());
+        assert_eq!(context, *CONTEXT);
+    }
+}
@@ -1,3 +1,6 @@
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
 use std::time::Duration;
 
 const MILLISECONDS_PER_SECOND: u64 = 1_000;
@@ -41,6 +44,65 @@ pub fn duration_format(duration: &Duration) -> String {
     )
 }
 
+#[derive(Debug, PartialEq)]
+pub struct ParseDurationError {
+    input: String,
+}
+
+/// Parses a duration such as "30s", "5m", "1h30m" or "250ms". A number
+/// without a unit is a number of seconds.
+pub fn parse_duration(input: &str) -> Result<Duration, ParseDurationError> {
+    let error = || ParseDurationError {
+        input: input.to_string(),
+    };
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(error());
+    }
+    if let Ok(seconds) = input.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds));
+    }
+    let mut duration = Duration::from_secs(0);
+    let mut rest = input;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|character: char| !character.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let units = rest[digits..]
+            .find(|character: char| character.is_ascii_digit())
+            .map_or(rest.len(), |units| digits + units);
+        let value = rest[..digits].parse::<u64>().map_err(|_| error())?;
+        let unit = match &rest[digits..units] {
+            "ms" => Duration::from_millis(1),
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_secs(SECONDS_PER_MINUTE),
+            "h" => Duration::from_secs(SECONDS_PER_HOUR),
+            "d" => Duration::from_secs(SECONDS_PER_DAY),
+            _ => return Err(error()),
+        };
+        let value = u32::try_from(value).map_err(|_| error())?;
+        duration = unit
+            .checked_mul(value)
+            .and_then(|part| duration.checked_add(part))
+            .ok_or_else(error)?;
+        rest = &rest[units..];
+    }
+    Ok(duration)
+}
+
+impl fmt::Display for ParseDurationError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "Cannot parse duration \"{}\". Expected a value such as 30s, 5m \
+             or 1h30m.",
+            self.input
+        )
+    }
+}
+
+impl error::Error for ParseDurationError {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -88,4 +150,30 @@ mod test {
             "3d 14h 16m"
         );
     }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("45"), Ok(secs(45)));
+        assert_eq!(parse_duration("250ms"), Ok(millis(250)));
+        assert_eq!(parse_duration("30s"), Ok(secs(30)));
+        assert_eq!(parse_duration("20m"), Ok(secs(20 * SECONDS_PER_MINUTE)));
+        assert_eq!(parse_duration("6h"), Ok(secs(6 * SECONDS_PER_HOUR)));
+        assert_eq!(parse_duration("2d"), Ok(secs(2 * SECONDS_PER_DAY)));
+        assert_eq!(
+            parse_duration(" 1h30m "),
+            Ok(secs(SECONDS_PER_HOUR + 30 * SECONDS_PER_MINUTE))
+        );
+    }
+
+    #[test]
+    fn returns_error_with_bad_duration() {
+        for input in
+            &["", "s", "ten", "10x", "1.5h", "-3s", "5m30", "99999999999d"]
+        {
+            let expected = ParseDurationError {
+                input: input.to_string(),
+            };
+            assert_eq!(parse_duration(input), Err(expected));
+        }
+    }
 }
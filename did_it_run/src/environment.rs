@@ -0,0 +1,231 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// What `--stdin` takes to give the command no input at all.
+pub const NULL_STDIN: &str = "null";
+
+/// Where and with what the command runs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Environment {
+    /// The directory to run in instead of diditrun's.
+    pub working_directory: Option<PathBuf>,
+    /// Variables to set, in order, so that later ones win.
+    pub variables: Vec<(String, String)>,
+    /// Whether to start from no variables rather than diditrun's.
+    pub clear: bool,
+    /// Where the command's input comes from instead of diditrun's.
+    pub stdin: Option<Stdin>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stdin {
+    Null,
+    File(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum EnvironmentError {
+    MalformedVariable(String),
+    Io(PathBuf, io::Error),
+}
+
+impl Stdin {
+    /// Reads the value of `--stdin`: `null` or a file to read from.
+    pub fn parse(input: &str) -> Self {
+        if input == NULL_STDIN {
+            Stdin::Null
+        } else {
+            Stdin::File(PathBuf::from(input))
+        }
+    }
+}
+
+impl Environment {
+    /// Sets up `command` to run in this environment.
+    pub fn apply(&self, command: &mut Command) -> io::Result<()> {
+        if let Some(ref working_directory) = self.working_directory {
+            command.current_dir(working_directory);
+        }
+        if self.clear {
+            command.env_clear();
+        }
+        command.envs(self.variables.iter().cloned());
+        match self.stdin {
+            Some(Stdin::Null) => {
+                command.stdin(Stdio::null());
+            },
+            Some(Stdin::File(ref file)) => {
+                command.stdin(fs::File::open(file)?);
+            },
+            None => {},
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the variables set, each once, but not their
+    /// values, which may be secret.
+    pub fn variable_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        for (name, _) in &self.variables {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
+}
+
+/// Splits a variable such as `RUST_LOG=debug` into its name and value.
+pub fn parse_variable(
+    variable: &str,
+) -> Result<(String, String), EnvironmentError> {
+    match variable.split_once('=') {
+        Some((name, value))
+            if !name.is_empty() && !name.contains(char::is_whitespace) =>
+        {
+            Ok((name.to_string(), value.to_string()))
+        },
+        _ => Err(EnvironmentError::MalformedVariable(variable.to_string())),
+    }
+}
+
+/// Reads the variables in an env file, one `NAME=value` per line. Blank
+/// lines, comments starting with `#` and `export` before a name are skipped,
+/// and quotes around a value are removed.
+pub fn load_variables(
+    file: &Path,
+) -> Result<Vec<(String, String)>, EnvironmentError> {
+    let text = fs::read_to_string(file)
+        .map_err(|err| EnvironmentError::Io(file.to_path_buf(), err))?;
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (name, value) = parse_variable(line)?;
+            Ok((name, unquote(value.trim()).to_string()))
+        })
+        .collect()
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in &["\"", "'"] {
+        if value.len() >= 2 &&
+            value.starts_with(quote) &&
+            value.ends_with(quote)
+        {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+impl fmt::Display for EnvironmentError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EnvironmentError::MalformedVariable(ref variable) => write!(
+                formatter,
+                "Cannot parse variable \"{}\". Expected a value such as \
+                 NAME=value.",
+                variable
+            ),
+            EnvironmentError::Io(ref file, ref err) => write!(
+                formatter,
+                "Cannot read env file {}: {}.",
+                file.display(),
+                err
+            ),
+        }
+    }
+}
+
+impl error::Error for EnvironmentError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::PROJECT_ROOT_PATH;
+    use matches::assert_matches;
+
+    fn variables(variables: &[(&str, &str)]) -> Vec<(String, String)> {
+        variables
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_variables() {
+        assert_eq!(
+            parse_variable("RUST_LOG=debug").unwrap(),
+            ("RUST_LOG".to_string(), "debug".to_string())
+        );
+        assert_eq!(
+            parse_variable("EMPTY=").unwrap(),
+            ("EMPTY".to_string(), String::new())
+        );
+        assert_eq!(
+            parse_variable("A=b=c").unwrap(),
+            ("A".to_string(), "b=c".to_string())
+        );
+        for variable in &["NAME", "=value", "MY NAME=value"] {
+            assert_matches!(
+                parse_variable(variable),
+                Err(EnvironmentError::MalformedVariable(_))
+            );
+        }
+    }
+
+    #[test]
+    fn loads_variables_from_env_file() {
+        let file = PROJECT_ROOT_PATH.join("tests/fixtures/diditrun/env");
+        let expected = variables(&[
+            ("RUST_LOG", "debug"),
+            ("GREETING", "hello world"),
+            ("QUOTED", "it's"),
+            ("RUST_LOG", "info"),
+        ]);
+        assert_eq!(load_variables(&file).unwrap(), expected);
+        let result = load_variables(Path::new("does/not/exist"));
+        assert_matches!(result, Err(EnvironmentError::Io(..)));
+    }
+
+    #[test]
+    fn reads_stdin_argument() {
+        assert_eq!(Stdin::parse("null"), Stdin::Null);
+        assert_eq!(Stdin::parse("in.txt"), Stdin::File("in.txt".into()));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn applies_environment_to_command() {
+        let environment = Environment {
+            working_directory: Some(PathBuf::from("/")),
+            variables: variables(&[("A", "1"), ("B", "2"), ("A", "3")]),
+            clear: true,
+            stdin: Some(Stdin::Null),
+        };
+        assert_eq!(environment.variable_names(), ["A", "B"]);
+        let mut command = Command::new("/bin/sh");
+        command.args(["-c", "echo \"$PWD $A $B $HOME\"; cat"]);
+        environment.apply(&mut command).unwrap();
+        let output = command.output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "/ 3 2 \n");
+    }
+
+    // Hacks to minimize coverage report errors
+    #[test]
+    fn maximize_coverage() {
+        let err = EnvironmentError::Io(
+            PathBuf::from("env"),
+            io::Error::new(io::ErrorKind::NotFound, "oops"),
+        );
+        let _ = format!("{:?} {}", err, err);
+        let err = EnvironmentError::MalformedVariable("oops".to_string());
+        let _ = format!("{:?} {}", err, err);
+    }
+}
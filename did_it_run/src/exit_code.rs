@@ -1,6 +1,6 @@
 #![allow(dead_code)]
-/// Standard exit codes
-/// Source: `/usr/include/sysexits.h`
+//! Standard exit codes
+//! Source: `/usr/include/sysexits.h`
 // TODO(#17): Use std::process::ExitCode's SUCCESS and FAILURE when the API
 // stabilizes.
 
@@ -75,3 +75,10 @@ pub const NOPERM: ExitCode = 77;
 
 /// Something was found in an unconfigured or misconfigured state.
 pub const CONFIG: ExitCode = 78;
+
+/// The command timed out. Matches the exit code used by GNU `timeout`.
+pub const TIMEOUT: ExitCode = 124;
+
+/// Added to the number of the signal that killed a command to form the exit
+/// code, following the shell convention.
+pub const SIGNAL_BASE: ExitCode = 128;
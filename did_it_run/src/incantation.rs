@@ -1,15 +1,54 @@
+use crate::config::Config;
+use crate::environment::Environment;
+#[cfg(unix)]
+use crate::job_control;
+use crate::output::{CapturedOutput, OutputCapture};
+#[cfg(target_os = "linux")]
+use crate::proc;
+#[cfg(unix)]
+use crate::process_tree;
+#[cfg(unix)]
+use crate::process_tree::ProcessTree;
+use crate::process_tree::Straggler;
+#[cfg(unix)]
+use crate::pty::{Pty, RawMode};
+use crate::resource_usage::ResourceUsage;
+use crate::signal::{Interrupts, Signal};
+use crate::trigger::{OutputMatch, Triggers};
 use std::borrow::Cow;
 use std::convert::Into;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::io;
-use std::process::{Command, ExitStatus};
+#[cfg(unix)]
+use std::mem;
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::str;
+use std::thread;
 use std::time::{Duration, Instant};
 
+/// How often a running child is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long a timed out child, and every process it started, has to exit after
+/// being asked to terminate before it is killed.
+const TERMINATION_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How long to wait for the rest of a child's output once it has exited.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How a command line is passed to a shell, e.g., `sh -c 'a | b'`.
+const SHELL_COMMAND_OPTION: &str = "-c";
+
 #[derive(Clone, Debug)]
 pub struct Incantation {
     pub command: OsString,
     pub args: Vec<OsString>,
+    /// Whether `command` is a shell running the command line in the last of
+    /// `args`, which is what the incantation is then shown as.
+    pub shell: bool,
 }
 
 impl Incantation {
@@ -21,42 +60,696 @@ impl Incantation {
         Incantation {
             command: command.into(),
             args: args.into_iter().map(Into::into).collect(),
+            shell: false,
+        }
+    }
+
+    /// Runs the incantation's words, joined by spaces, as a command line
+    /// through `shell` instead, so that pipes and redirections work.
+    pub fn through_shell<S: Into<OsString>>(self, shell: S) -> Self {
+        let mut command_line = self.command;
+        for arg in self.args {
+            command_line.push(" ");
+            command_line.push(arg);
+        }
+        Incantation {
+            command: shell.into(),
+            args: vec![SHELL_COMMAND_OPTION.into(), command_line],
+            shell: true,
+        }
+    }
+
+    /// Returns what the incantation goes by in brief notifications: the
+    /// command, or the whole command line run through a shell.
+    pub fn name(&self) -> Cow<'_, str> {
+        match self.args.last() {
+            Some(command_line) if self.shell => command_line.to_string_lossy(),
+            _ => self.command.to_string_lossy(),
         }
     }
 }
 
+/// Shows the incantation the way it would be typed into a POSIX shell to run
+/// it again.
 impl fmt::Display for Incantation {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let args = self
-            .args
-            .iter()
-            .map(|arg| arg.to_string_lossy())
-            .collect::<Vec<Cow<str>>>();
-        write!(f, "{} {}", self.command.to_string_lossy(), args.join(" "))
+        if self.shell {
+            return write!(f, "{}", self.name());
+        }
+        write!(f, "{}", shell_quote(&self.command, true))?;
+        for arg in &self.args {
+            write!(f, " {}", shell_quote(arg, false))?;
+        }
+        Ok(())
     }
 }
 
+/// Quotes `word` so that a POSIX shell reads it back unchanged, leaving it as
+/// is when no character in it is special to the shell. An `=` is special in
+/// the command, which would otherwise be read as a variable assignment.
+fn shell_quote(word: &OsStr, is_command: bool) -> Cow<'_, str> {
+    let is_plain = |byte: &u8| {
+        byte.is_ascii_alphanumeric() ||
+            b"@%+:,./_-".contains(byte) ||
+            (*byte == b'=' && !is_command)
+    };
+    let bytes = os_str_bytes(word);
+    if !bytes.is_empty() && bytes.iter().all(is_plain) {
+        return word.to_string_lossy();
+    }
+    if let Some(word) = word.to_str() {
+        return single_quote(word).into();
+    }
+    // Bytes that are not UTF-8 cannot be written within quotes, so the shell
+    // is asked to print them instead, e.g., "$(printf '\351')".
+    let mut quoted = String::new();
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let (text, invalid) = match str::from_utf8(rest) {
+            Ok(text) => (text, 0),
+            Err(err) => (
+                str::from_utf8(&rest[..err.valid_up_to()]).unwrap_or_default(),
+                err.error_len().unwrap_or(rest.len() - err.valid_up_to()),
+            ),
+        };
+        if !text.is_empty() {
+            quoted.push_str(&single_quote(text));
+        }
+        rest = &rest[text.len()..];
+        if 0 < invalid {
+            let escapes = rest[..invalid]
+                .iter()
+                .map(|byte| format!("\\{:03o}", byte))
+                .collect::<String>();
+            quoted.push_str(&format!("\"$(printf '{}')\"", escapes));
+            rest = &rest[invalid..];
+        }
+    }
+    quoted.into()
+}
+
+fn single_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(unix)]
+fn os_str_bytes(word: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    word.as_bytes().into()
+}
+
+#[cfg(not(unix))]
+fn os_str_bytes(word: &OsStr) -> Cow<'_, [u8]> {
+    word.to_string_lossy().into_owned().into_bytes().into()
+}
+
+/// How a child terminated when it was killed by a signal rather than exiting.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Termination {
+    pub signal: Signal,
+    pub core_dumped: bool,
+}
+
 pub struct IncantationOutcome {
     pub result: io::Result<ExitStatus>,
     pub elapsed_time: Duration,
+    pub timed_out: bool,
+    /// Whether the child was terminated for writing no output for too long.
+    pub stalled: bool,
+    pub interruption: Option<Signal>,
+    pub termination: Option<Termination>,
+    pub output: Option<CapturedOutput>,
+    pub resource_usage: Option<ResourceUsage>,
+    /// The processes the child started that were still running after it was
+    /// terminated or interrupted, and were killed.
+    pub stragglers: Vec<Straggler>,
+}
+
+/// News about a command that is still running.
+pub enum Progress {
+    /// Another heartbeat interval has passed.
+    StillRunning {
+        elapsed_time: Duration,
+        output: Option<CapturedOutput>,
+        resource_usage: Option<ResourceUsage>,
+    },
+    /// A line of output set off a trigger.
+    OutputMatched {
+        elapsed_time: Duration,
+        output_match: OutputMatch,
+    },
+    /// The command has written no output for `idle_time`.
+    Stalled {
+        elapsed_time: Duration,
+        idle_time: Duration,
+        output: Option<CapturedOutput>,
+        resource_usage: Option<ResourceUsage>,
+    },
+}
+
+/// Runs the incantation to completion, calling `on_progress` with news about
+/// it while it runs.
+pub fn run<F>(
+    incantation: &Incantation,
+    config: &Config,
+    on_progress: F,
+) -> IncantationOutcome
+where
+    F: FnMut(Progress),
+{
+    run_prefixed(incantation, config, None, on_progress)
 }
 
-pub fn run(incantation: &Incantation) -> IncantationOutcome {
+/// Runs the incantation like `run`, starting every line of its output with
+/// `output_prefix` if given.
+pub fn run_prefixed<F>(
+    incantation: &Incantation,
+    config: &Config,
+    output_prefix: Option<&str>,
+    mut on_progress: F,
+) -> IncantationOutcome
+where
+    F: FnMut(Progress),
+{
     let now = Instant::now();
-    let result = Command::new(incantation.command.clone())
-        .args(incantation.args.clone())
-        .spawn()
-        .and_then(|mut child| child.wait());
+    let mut supervisor = Supervisor {
+        start: now,
+        // A timeout too long to fall on a representable time never expires.
+        deadline: config.timeout.and_then(|timeout| now.checked_add(timeout)),
+        // Commands run side by side leave the terminal to diditrun.
+        #[cfg(unix)]
+        terminal: output_prefix.is_none() && job_control::has_terminal(),
+        heartbeat: config.heartbeat,
+        // Beats that would fall past the end of time never come.
+        next_heartbeat: config
+            .heartbeat
+            .and_then(|heartbeat| now.checked_add(heartbeat)),
+        on_progress: &mut on_progress,
+        environment: &config.environment,
+        triggers: Triggers::new(&config.triggers),
+        stall_after: config.stall_after,
+        kill_on_stall: config.kill_on_stall,
+        last_output: now,
+        stall_reported: false,
+        output_lines: config.output_lines,
+        output_prefix: output_prefix.map(str::to_string),
+        use_pty: config.pty,
+        timed_out: false,
+        stalled: false,
+        interruption: None,
+        capture: None,
+        #[cfg(unix)]
+        pty: None,
+        #[cfg(unix)]
+        tree: None,
+        resource_usage: None,
+        stragglers: vec![],
+    };
+    let result = supervisor.run(incantation);
     let elapsed_time = now.elapsed();
+    let termination = result.as_ref().ok().and_then(termination);
+    // Output that arrives once the child has exited is not checked against
+    // the triggers since the final notification will include it anyway.
+    let output = supervisor
+        .capture
+        .map(|capture| capture.finish(OUTPUT_DRAIN_TIMEOUT))
+        .filter(|_| config.output_lines.is_some());
     IncantationOutcome {
         result,
         elapsed_time,
+        timed_out: supervisor.timed_out,
+        stalled: supervisor.stalled,
+        interruption: supervisor.interruption,
+        termination,
+        output,
+        resource_usage: supervisor.resource_usage,
+        stragglers: supervisor.stragglers,
     }
 }
 
+/// Watches over the child while it runs, enforcing the deadline, forwarding
+/// interrupts, capturing output and reporting progress.
+struct Supervisor<'a> {
+    start: Instant,
+    deadline: Option<Instant>,
+    /// Whether the child shares diditrun's terminal, and so is stopped and
+    /// continued along with diditrun.
+    #[cfg(unix)]
+    terminal: bool,
+    heartbeat: Option<Duration>,
+    next_heartbeat: Option<Instant>,
+    on_progress: &'a mut dyn FnMut(Progress),
+    environment: &'a Environment,
+    triggers: Triggers,
+    stall_after: Option<Duration>,
+    kill_on_stall: bool,
+    last_output: Instant,
+    /// Whether the current stall has been reported, so that it is reported
+    /// only once.
+    stall_reported: bool,
+    output_lines: Option<usize>,
+    /// Starts every line of the child's output, which is then never run in a
+    /// pseudo-terminal.
+    output_prefix: Option<String>,
+    use_pty: bool,
+    timed_out: bool,
+    stalled: bool,
+    interruption: Option<Signal>,
+    capture: Option<OutputCapture>,
+    #[cfg(unix)]
+    pty: Option<Pty>,
+    /// The processes last signaled, which must all exit by the deadline.
+    #[cfg(unix)]
+    tree: Option<(ProcessTree, Instant)>,
+    resource_usage: Option<ResourceUsage>,
+    stragglers: Vec<Straggler>,
+}
+
+impl<'a> Supervisor<'a> {
+    fn run(&mut self, incantation: &Incantation) -> io::Result<ExitStatus> {
+        // Listen before spawning so that no interrupt goes unforwarded.
+        let interrupts = Interrupts::listen()?;
+        let mut command = Command::new(incantation.command.clone());
+        command.args(incantation.args.clone());
+        self.environment.apply(&mut command)?;
+        let capture_lines = self.capture_lines();
+        // The pseudo-terminal stands in for ours, input included, so it is
+        // not used when the input comes from elsewhere.
+        #[cfg(unix)]
+        {
+            if self.use_pty &&
+                self.output_prefix.is_none() &&
+                self.environment.stdin.is_none()
+            {
+                return self.run_in_pty(
+                    command,
+                    capture_lines.unwrap_or(0),
+                    &interrupts,
+                );
+            }
+        }
+        if capture_lines.is_some() {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        // Only the command that has the terminal can read from it.
+        if self.output_prefix.is_some() && self.environment.stdin.is_none() {
+            command.stdin(Stdio::null());
+        }
+        #[cfg(unix)]
+        job_control::isolate(
+            &mut command,
+            self.terminal && job_control::in_foreground(),
+        );
+        let mut child = command.spawn()?;
+        if let Some(capture_lines) = capture_lines {
+            self.capture = Some(match self.output_prefix {
+                Some(ref prefix) => OutputCapture::start_prefixed(
+                    &mut child,
+                    capture_lines,
+                    prefix,
+                ),
+                None => OutputCapture::start(&mut child, capture_lines),
+            });
+        }
+        self.supervise(&mut child, &interrupts)
+    }
+
+    /// Returns how many lines of output to keep if output is captured at
+    /// all. Prefixing, triggers and stall detection need to see the output
+    /// even when none of it is kept.
+    fn capture_lines(&self) -> Option<usize> {
+        match self.output_lines {
+            Some(output_lines) => Some(output_lines),
+            None if self.output_prefix.is_some() ||
+                !self.triggers.is_empty() ||
+                self.stall_after.is_some() =>
+            {
+                Some(0)
+            },
+            None => None,
+        }
+    }
+
+    /// Runs the child in a pseudo-terminal that stands in for ours so that it
+    /// behaves as it would without capture.
+    #[cfg(unix)]
+    fn run_in_pty(
+        &mut self,
+        mut command: Command,
+        output_lines: usize,
+        interrupts: &Interrupts,
+    ) -> io::Result<ExitStatus> {
+        // The child has a terminal of its own.
+        self.terminal = false;
+        let mut pty = Pty::open()?;
+        pty.attach(&mut command)?;
+        let mut child = command.spawn()?;
+        // The command holds copies of the terminal that would otherwise keep
+        // its output open after the child exits.
+        drop(command);
+        pty.detach();
+        self.capture =
+            Some(OutputCapture::start_terminal(pty.output()?, output_lines));
+        let raw_mode = RawMode::enable()?;
+        pty.forward_input()?;
+        self.pty = Some(pty);
+        let status = self.supervise(&mut child, interrupts);
+        // Leave our stdin to whatever runs next.
+        self.pty = None;
+        // Interrupts typed and hangups on the user's terminal reach the child
+        // through its own terminal rather than diditrun while input is raw.
+        if let (Some(_), Ok(status)) = (raw_mode, &status) {
+            let signal = status.signal();
+            if signal == Some(libc::SIGINT) || signal == Some(libc::SIGHUP) {
+                self.interruption = signal;
+            }
+        }
+        status
+    }
+
+    fn supervise(
+        &mut self,
+        child: &mut Child,
+        interrupts: &Interrupts,
+    ) -> io::Result<ExitStatus> {
+        let status = match self.wait(child, interrupts)? {
+            Some(status) => status,
+            None => self.terminate(child)?,
+        };
+        #[cfg(unix)]
+        {
+            // Interrupts sent from the terminal reach the child's process
+            // group rather than diditrun while the child has the terminal.
+            if job_control::reclaim_terminal(child.id()) &&
+                status.signal() == Some(libc::SIGINT)
+            {
+                self.interruption = Some(libc::SIGINT);
+            }
+            if let Some((tree, deadline)) = self.tree.take() {
+                self.stragglers = tree.kill_stragglers(deadline);
+                process_tree::stop_adopting_orphans()?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Waits for the child to exit. Returns `None` if it is still running
+    /// once the deadline has passed or it has stalled and should be killed.
+    fn wait(
+        &mut self,
+        child: &mut Child,
+        interrupts: &Interrupts,
+    ) -> io::Result<Option<ExitStatus>> {
+        loop {
+            if let Some(status) = self.try_wait(child)? {
+                return Ok(Some(status));
+            }
+            for signal in interrupts.pending() {
+                #[cfg(unix)]
+                self.signal_tree(child, signal)?;
+                self.interruption = Some(signal);
+            }
+            if let Some(deadline) = self.deadline {
+                if deadline <= Instant::now() {
+                    self.timed_out = true;
+                    return Ok(None);
+                }
+            }
+            if let Some(stall_after) = self.stall_after {
+                let idle_time = self.last_output.elapsed();
+                if stall_after <= idle_time && !self.stall_reported {
+                    if self.kill_on_stall {
+                        self.stalled = true;
+                        return Ok(None);
+                    }
+                    self.stall_reported = true;
+                    let stalled = Progress::Stalled {
+                        elapsed_time: self.start.elapsed(),
+                        idle_time,
+                        output: self.output(),
+                        resource_usage: current_resource_usage(child),
+                    };
+                    (self.on_progress)(stalled);
+                }
+            }
+            if let Some(next_heartbeat) = self.next_heartbeat {
+                if next_heartbeat <= Instant::now() {
+                    self.beat(child);
+                }
+            }
+            #[cfg(unix)]
+            {
+                if let Some(ref pty) = self.pty {
+                    pty.sync_window_size();
+                }
+            }
+            match self.capture {
+                Some(ref mut capture) => {
+                    let lines = capture.receive(POLL_INTERVAL);
+                    if !lines.is_empty() {
+                        self.last_output = Instant::now();
+                        self.stall_reported = false;
+                    }
+                    for line in lines {
+                        let now = Instant::now();
+                        for output_match in self.triggers.check(&line, now) {
+                            (self.on_progress)(Progress::OutputMatched {
+                                elapsed_time: now - self.start,
+                                output_match,
+                            });
+                        }
+                    }
+                },
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Reports on the running child and schedules the next heartbeat.
+    fn beat(&mut self, child: &Child) {
+        let still_running = Progress::StillRunning {
+            elapsed_time: self.start.elapsed(),
+            output: self.output(),
+            resource_usage: current_resource_usage(child),
+        };
+        (self.on_progress)(still_running);
+        // Skip any beats missed while the callback ran.
+        let now = Instant::now();
+        if let Some(heartbeat) = self.heartbeat {
+            while let Some(next_heartbeat) = self.next_heartbeat {
+                if next_heartbeat > now {
+                    break;
+                }
+                self.next_heartbeat = next_heartbeat.checked_add(heartbeat);
+            }
+        }
+    }
+
+    /// Returns the output kept so far.
+    fn output(&self) -> Option<CapturedOutput> {
+        match self.output_lines {
+            Some(_) => self.capture.as_ref().map(OutputCapture::output),
+            None => None,
+        }
+    }
+
+    /// Waits for the child to exit. Returns `None` if it is still running
+    /// once `deadline` has passed.
+    #[cfg(unix)]
+    fn wait_until(
+        &mut self,
+        child: &mut Child,
+        deadline: Instant,
+    ) -> io::Result<Option<ExitStatus>> {
+        loop {
+            if let Some(status) = self.try_wait(child)? {
+                return Ok(Some(status));
+            }
+            if deadline <= Instant::now() {
+                return Ok(None);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Sends `signal` to the child and every process it started, which then
+    /// have `TERMINATION_GRACE_PERIOD` to exit.
+    #[cfg(unix)]
+    fn signal_tree(
+        &mut self,
+        child: &Child,
+        signal: libc::c_int,
+    ) -> io::Result<()> {
+        process_tree::adopt_orphans()?;
+        let tree = ProcessTree::find(child.id())?;
+        tree.signal(signal);
+        let deadline = Instant::now() + TERMINATION_GRACE_PERIOD;
+        self.tree = Some((tree, deadline));
+        Ok(())
+    }
+
+    /// Asks the child and every process it started to terminate, then kills
+    /// the child if it has not exited after `TERMINATION_GRACE_PERIOD`.
+    fn terminate(&mut self, child: &mut Child) -> io::Result<ExitStatus> {
+        #[cfg(unix)]
+        {
+            self.signal_tree(child, libc::SIGTERM)?;
+            let deadline = Instant::now() + TERMINATION_GRACE_PERIOD;
+            if let Some(status) = self.wait_until(child, deadline)? {
+                return Ok(status);
+            }
+        }
+        child.kill()?;
+        loop {
+            if let Some(status) = self.try_wait(child)? {
+                return Ok(status);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Reaps the child if it has exited, recording the resources it used. If
+    /// it was stopped instead, e.g., from the terminal, stops diditrun too
+    /// until both are continued.
+    #[cfg(unix)]
+    fn try_wait(
+        &mut self,
+        child: &mut Child,
+    ) -> io::Result<Option<ExitStatus>> {
+        let mut status = 0;
+        // Safe because `rusage` is plain data that `wait4` fills in.
+        let mut usage: libc::rusage = unsafe { mem::zeroed() };
+        // Safe because `wait4` only writes to `status` and `usage`. Reaping
+        // the child ourselves means that `Child::try_wait` must not be used.
+        let pid = unsafe {
+            libc::wait4(
+                child.id() as libc::pid_t,
+                &mut status,
+                libc::WNOHANG | libc::WUNTRACED,
+                &mut usage,
+            )
+        };
+        match pid {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    Ok(None)
+                } else {
+                    Err(err)
+                }
+            },
+            0 => Ok(None),
+            _ if libc::WIFSTOPPED(status) => {
+                if self.terminal {
+                    job_control::suspend(child.id());
+                }
+                Ok(None)
+            },
+            _ => {
+                self.resource_usage = Some(usage.into());
+                Ok(Some(ExitStatus::from_raw(status)))
+            },
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn try_wait(
+        &mut self,
+        child: &mut Child,
+    ) -> io::Result<Option<ExitStatus>> {
+        child.try_wait()
+    }
+}
+
+#[cfg(unix)]
+fn termination(status: &ExitStatus) -> Option<Termination> {
+    status.signal().map(|signal| Termination {
+        signal,
+        core_dumped: libc::WCOREDUMP(status.into_raw()),
+    })
+}
+
+#[cfg(not(unix))]
+fn termination(_: &ExitStatus) -> Option<Termination> {
+    None
+}
+
+/// Returns the resources the running child has used so far, if the platform
+/// can tell.
+#[cfg(target_os = "linux")]
+fn current_resource_usage(child: &Child) -> Option<ResourceUsage> {
+    proc::resource_usage(child.id()).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn current_resource_usage(_: &Child) -> Option<ResourceUsage> {
+    None
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::config::TriggerConfig;
+    use crate::environment::Stdin;
+    use crate::output::Stream;
+    use lazy_static::lazy_static;
+    use regex::Regex;
+
+    lazy_static! {
+        static ref CONFIG: Config = Config::default();
+    }
+
+    #[test]
+    fn displays_incantation_as_typed_into_shell() {
+        let display = |command, args: Vec<&str>| {
+            Incantation::new(command, args).to_string()
+        };
+        assert_eq!(display("make", vec![]), "make");
+        assert_eq!(display("rm", vec!["my file"]), "rm 'my file'");
+        assert_eq!(
+            display("cargo", vec!["test", "--features=a,b", "./src/*.rs"]),
+            "cargo test --features=a,b './src/*.rs'"
+        );
+        assert_eq!(display("echo", vec!["it's", ""]), "echo 'it'\\''s' ''");
+        assert_eq!(display("a=b", vec!["$HOME"]), "'a=b' '$HOME'");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn escapes_bytes_that_are_not_utf8() {
+        use std::os::unix::ffi::OsStringExt;
+        let arg = OsString::from_vec(b"caf\xe9 'n\\'".to_vec());
+        let incantation = Incantation {
+            command: "cat".into(),
+            args: vec![arg],
+            shell: false,
+        };
+        let quoted = incantation.to_string();
+        assert_eq!(quoted, "cat 'caf'\"$(printf '\\351')\"' '\\''n\\'\\'''");
+        let output = Command::new("sh")
+            .args(["-c", &quoted.replacen("cat", "printf %s", 1)])
+            .output()
+            .unwrap();
+        assert_eq!(output.stdout, b"caf\xe9 'n\\'");
+    }
+
+    #[test]
+    fn runs_command_line_through_shell() {
+        let incantation = Incantation::new("echo", vec!["a", "|", "wc", "-c"])
+            .through_shell("sh");
+        assert_eq!(incantation.command, "sh");
+        assert_eq!(incantation.args, ["-c", "echo a | wc -c"]);
+        assert_eq!(incantation.name(), "echo a | wc -c");
+        assert_eq!(incantation.to_string(), "echo a | wc -c");
+        assert_eq!(Incantation::new("sh", vec!["-c", "true"]).name(), "sh");
+        let config = Config {
+            output_lines: Some(1),
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |_| {});
+        assert!(outcome.result.unwrap().success());
+        assert_eq!(outcome.output.unwrap().stdout[0].trim(), "2");
+    }
 
     const EXIT_WITH_ARGUMENT_COUNT: &str = "exit $#";
     const EXIT_WITH_STATUS: &str = "exit $0";
@@ -70,7 +763,7 @@ mod test {
             let mut args = base_args.clone();
             args.push(&status_arg);
             let incantation = Incantation::new("bash", args.clone());
-            let outcome = run(&incantation);
+            let outcome = run(&incantation, &CONFIG, |_| {});
             // When a parent retrieves the exit status of its child, only the
             // least-significant eight bits are available.
             let status_code = status_code & 0xFF;
@@ -83,7 +776,7 @@ mod test {
         let mut args = vec!["-c", EXIT_WITH_ARGUMENT_COUNT, "bash"];
         for argument_count in 0..5 {
             let incantation = Incantation::new("bash", args.clone());
-            let outcome = run(&incantation);
+            let outcome = run(&incantation, &CONFIG, |_| {});
             assert_eq!(argument_count, outcome.result.unwrap().code().unwrap());
             args.push("another_arg");
         }
@@ -92,17 +785,324 @@ mod test {
     #[test]
     fn returns_error_with_bad_command() {
         let incantation = Incantation::new("wingardium-leviosa", vec![]);
-        let outcome = run(&incantation);
+        let outcome = run(&incantation, &CONFIG, |_| {});
         // It's leviOsa, not leviosA
         assert_eq!(io::ErrorKind::NotFound, outcome.result.unwrap_err().kind());
     }
 
+    #[test]
+    fn terminates_command_after_timeout() {
+        let incantation = Incantation::new("sleep", vec!["10"]);
+        let timeout = Duration::from_millis(100);
+        let config = Config {
+            timeout: Some(timeout),
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |_| {});
+        assert!(outcome.timed_out);
+        assert!(outcome.result.is_ok());
+        assert!(!outcome.result.unwrap().success());
+        #[cfg(unix)]
+        assert_eq!(outcome.termination.unwrap().signal, libc::SIGTERM);
+        assert!(timeout <= outcome.elapsed_time);
+        assert!(outcome.elapsed_time < TERMINATION_GRACE_PERIOD);
+    }
+
+    #[test]
+    fn does_not_time_out_command_that_finishes_in_time() {
+        let incantation = Incantation::new("bash", vec!["-c", "exit 3"]);
+        let config = Config {
+            timeout: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |_| {});
+        assert!(!outcome.timed_out);
+        assert_eq!(3, outcome.result.unwrap().code().unwrap());
+    }
+
+    #[test]
+    fn never_times_out_command_with_huge_timeout() {
+        let incantation = Incantation::new("true", vec![]);
+        let config = Config {
+            timeout: Some(Duration::from_secs(u64::MAX)),
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |_| {});
+        assert!(!outcome.timed_out);
+        assert!(outcome.result.unwrap().success());
+    }
+
+    #[test]
+    fn never_beats_with_huge_heartbeat() {
+        let incantation = Incantation::new("true", vec![]);
+        let config = Config {
+            heartbeat: Some(Duration::from_secs(u64::MAX)),
+            ..Default::default()
+        };
+        let mut beats = 0;
+        let outcome = run(&incantation, &config, |_| beats += 1);
+        assert!(outcome.result.unwrap().success());
+        assert_eq!(beats, 0);
+    }
+
+    #[test]
+    fn captures_last_lines_of_output() {
+        let script = "printf 'one\\ntwo\\nthree\\n'; echo error >&2";
+        let incantation = Incantation::new("bash", vec!["-c", script]);
+        let config = Config {
+            output_lines: Some(2),
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |_| {});
+        assert!(outcome.result.unwrap().success());
+        let expected = CapturedOutput {
+            stdout: vec!["two".to_string(), "three".to_string()],
+            stderr: vec!["error".to_string()],
+        };
+        assert_eq!(outcome.output, Some(expected));
+    }
+
+    #[test]
+    fn sends_heartbeats_while_command_runs() {
+        let script = "echo started; sleep 0.35";
+        let incantation = Incantation::new("bash", vec!["-c", script]);
+        let heartbeat = Duration::from_millis(100);
+        let config = Config {
+            output_lines: Some(2),
+            heartbeat: Some(heartbeat),
+            ..Default::default()
+        };
+        let mut heartbeats = vec![];
+        let outcome = run(&incantation, &config, |progress| {
+            if let Progress::StillRunning {
+                elapsed_time,
+                output,
+                resource_usage,
+            } = progress
+            {
+                heartbeats.push((elapsed_time, output, resource_usage));
+            }
+        });
+        assert!(outcome.result.unwrap().success());
+        assert_eq!(heartbeats.len(), 3);
+        for ((elapsed_time, ..), beat) in heartbeats.iter().zip(1..) {
+            assert!(heartbeat * beat <= *elapsed_time);
+        }
+        let (_, output, resource_usage) = heartbeats.pop().unwrap();
+        assert_eq!(output.unwrap().stdout, ["started"]);
+        assert_eq!(resource_usage.is_some(), cfg!(target_os = "linux"));
+    }
+
+    #[test]
+    fn reports_output_matching_triggers() {
+        let script = "echo compiling; echo 'error: oops' >&2; sleep 0.1";
+        let incantation = Incantation::new("bash", vec!["-c", script]);
+        let config = Config {
+            triggers: vec![TriggerConfig {
+                pattern: Regex::new("^error:").unwrap(),
+                rate_limit: Duration::from_secs(60),
+            }],
+            ..Default::default()
+        };
+        let mut matches = vec![];
+        let outcome = run(&incantation, &config, |progress| {
+            if let Progress::OutputMatched { output_match, .. } = progress {
+                matches.push(output_match);
+            }
+        });
+        assert!(outcome.result.unwrap().success());
+        // Output is captured for the triggers but not kept.
+        assert_eq!(outcome.output, None);
+        let expected = OutputMatch {
+            pattern: "^error:".to_string(),
+            stream: Stream::Stderr,
+            line: "error: oops".to_string(),
+            context: vec![],
+            suppressed: 0,
+        };
+        assert_eq!(matches, [expected]);
+    }
+
+    #[test]
+    fn reports_each_stall() {
+        let script = "echo one; sleep 0.3; echo two; sleep 0.3";
+        let incantation = Incantation::new("bash", vec!["-c", script]);
+        let stall_after = Duration::from_millis(150);
+        let config = Config {
+            stall_after: Some(stall_after),
+            ..Default::default()
+        };
+        let mut idle_times = vec![];
+        let outcome = run(&incantation, &config, |progress| {
+            if let Progress::Stalled { idle_time, .. } = progress {
+                idle_times.push(idle_time);
+            }
+        });
+        assert!(outcome.result.unwrap().success());
+        assert!(!outcome.stalled);
+        assert_eq!(idle_times.len(), 2);
+        assert!(idle_times.iter().all(|&idle_time| stall_after <= idle_time));
+    }
+
+    #[test]
+    fn terminates_command_that_stalls() {
+        let incantation =
+            Incantation::new("bash", vec!["-c", "echo waiting; sleep 10"]);
+        let config = Config {
+            stall_after: Some(Duration::from_millis(100)),
+            kill_on_stall: true,
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |progress| {
+            if let Progress::Stalled { .. } = progress {
+                panic!("Stall reported instead of terminated.");
+            }
+        });
+        assert!(outcome.stalled);
+        assert!(!outcome.timed_out);
+        assert!(!outcome.result.unwrap().success());
+        assert!(outcome.elapsed_time < TERMINATION_GRACE_PERIOD);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn captures_output_from_pty() {
+        let script = "[ -t 1 ] && printf '\\033[31mtty\\033[0m\\n'";
+        let incantation = Incantation::new("bash", vec!["-c", script]);
+        let config = Config {
+            output_lines: Some(2),
+            pty: true,
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |_| {});
+        assert!(outcome.result.unwrap().success());
+        let expected = CapturedOutput {
+            stdout: vec!["tty".to_string()],
+            stderr: vec![],
+        };
+        assert_eq!(outcome.output, Some(expected));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn runs_in_pty_without_capturing_output() {
+        let incantation = Incantation::new("sh", vec!["-c", "[ -t 1 ]"]);
+        let config = Config {
+            pty: true,
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |_| {});
+        assert!(outcome.result.unwrap().success());
+        assert_eq!(outcome.output, None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reports_resource_usage() {
+        let script = "i=0; while [ $i -lt 100000 ]; do i=$((i + 1)); done";
+        let incantation = Incantation::new("bash", vec!["-c", script]);
+        let outcome = run(&incantation, &CONFIG, |_| {});
+        assert!(outcome.result.unwrap().success());
+        let usage = outcome.resource_usage.unwrap();
+        assert!(Duration::from_secs(0) < usage.user_time + usage.system_time);
+        assert!(0 < usage.max_rss);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn terminates_processes_started_by_command() {
+        let script = "sleep 10 & echo $!; wait";
+        let incantation = Incantation::new("sh", vec!["-c", script]);
+        let config = Config {
+            timeout: Some(Duration::from_millis(500)),
+            output_lines: Some(1),
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |_| {});
+        assert!(outcome.timed_out);
+        assert!(outcome.stragglers.is_empty());
+        let pid = outcome.output.unwrap().stdout[0].parse().unwrap();
+        assert!(proc::status(pid).ok().flatten().is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reports_resource_usage_of_terminated_command() {
+        let incantation = Incantation::new("sleep", vec!["10"]);
+        let config = Config {
+            timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |_| {});
+        assert!(outcome.timed_out);
+        assert!(outcome.resource_usage.is_some());
+    }
+
+    #[test]
+    fn captures_output_without_prefix() {
+        let incantation = Incantation::new("bash", vec!["-c", "echo output"]);
+        let config = Config {
+            output_lines: Some(1),
+            ..Default::default()
+        };
+        let outcome = run_prefixed(&incantation, &config, Some("[1] "), |_| {});
+        assert_eq!(outcome.output.unwrap().stdout, ["output"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn runs_command_in_configured_environment() {
+        let incantation =
+            Incantation::new("/bin/sh", vec!["-c", "echo \"$PWD $GREETING\""]);
+        let config = Config {
+            output_lines: Some(1),
+            pty: true,
+            environment: Environment {
+                working_directory: Some("/".into()),
+                variables: vec![("GREETING".into(), "hello".into())],
+                clear: true,
+                stdin: Some(Stdin::Null),
+            },
+            ..Default::default()
+        };
+        let outcome = run(&incantation, &config, |_| {});
+        assert_eq!(outcome.output.unwrap().stdout, ["/ hello"]);
+    }
+
+    #[test]
+    fn does_not_capture_output_by_default() {
+        let incantation = Incantation::new("bash", vec!["-c", "echo output"]);
+        let outcome = run(&incantation, &CONFIG, |_| {});
+        assert_eq!(outcome.output, None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reports_signal_that_killed_command() {
+        let incantation = Incantation::new("bash", vec!["-c", "kill -KILL $$"]);
+        let outcome = run(&incantation, &CONFIG, |_| {});
+        assert_eq!(outcome.result.unwrap().code(), None);
+        let expected = Termination {
+            signal: libc::SIGKILL,
+            core_dumped: false,
+        };
+        assert_eq!(outcome.termination, Some(expected));
+    }
+
+    #[test]
+    fn reports_no_signal_for_command_that_exits() {
+        let incantation = Incantation::new("bash", vec!["-c", "exit 1"]);
+        let outcome = run(&incantation, &CONFIG, |_| {});
+        assert_eq!(outcome.termination, None);
+    }
+
     // Hacks to minimize coverage report errors
     #[test]
     fn maximize_coverage() {
         let incantation = Incantation {
             command: OsString::from("command"),
             args: vec![OsString::from("foo"), OsString::from("bar")],
+            shell: false,
         };
         let _ = format!("{:?}", incantation);
     }
@@ -0,0 +1,145 @@
+use crate::config::{load_file, LoadConfigError};
+use crate::exit_code::ExitCode;
+use crate::incantation::Incantation;
+use serde_derive::Deserialize;
+use std::error;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A file listing commands to run one after the other.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct UserJob {
+    pub steps: Vec<String>,
+}
+
+/// How one step of a job went.
+#[derive(Clone, Debug)]
+pub struct StepReport {
+    pub incantation: Incantation,
+    pub outcome: StepOutcome,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepOutcome {
+    Finished {
+        /// The exit code diditrun would have exited with for this step alone.
+        exit_code: ExitCode,
+        succeeded: bool,
+        elapsed_time: Duration,
+    },
+    /// An earlier step failed, so this one was not run.
+    Skipped,
+}
+
+#[derive(Debug)]
+pub enum JobError {
+    Load(LoadConfigError),
+    MalformedStep(String, shell_words::ParseError),
+    EmptyStep,
+    NoSteps,
+}
+
+/// Reads the steps of the job in `file`.
+pub fn load_steps(file: PathBuf) -> Result<Vec<Incantation>, JobError> {
+    let job: UserJob = load_file(Some(file), vec![])?;
+    if job.steps.is_empty() {
+        return Err(JobError::NoSteps);
+    }
+    job.steps.iter().map(|step| parse_step(step)).collect()
+}
+
+/// Splits a step such as `make test` into its command and arguments the way
+/// a POSIX shell would.
+pub fn parse_step(step: &str) -> Result<Incantation, JobError> {
+    let mut words = shell_words::split(step)
+        .map_err(|err| JobError::MalformedStep(step.to_string(), err))?;
+    if words.is_empty() {
+        return Err(JobError::EmptyStep);
+    }
+    let command = words.remove(0);
+    Ok(Incantation::new(command, words))
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JobError::Load(ref err) => err.fmt(formatter),
+            JobError::MalformedStep(ref step, ref err) => {
+                write!(formatter, "Cannot parse step \"{}\": {}.", step, err)
+            },
+            JobError::EmptyStep => write!(formatter, "A step is empty."),
+            JobError::NoSteps => write!(formatter, "The job has no steps."),
+        }
+    }
+}
+
+impl error::Error for JobError {}
+
+impl From<LoadConfigError> for JobError {
+    fn from(err: LoadConfigError) -> Self {
+        JobError::Load(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::PROJECT_ROOT_PATH;
+    use matches::assert_matches;
+
+    #[test]
+    fn loads_steps_from_job_file() {
+        let file = PROJECT_ROOT_PATH.join("tests/fixtures/diditrun/job.toml");
+        let steps = load_steps(file).unwrap();
+        let commands = steps
+            .iter()
+            .map(|step| (step.command.clone(), step.args.clone()))
+            .collect::<Vec<_>>();
+        let expected = [
+            ("make".into(), vec![]),
+            ("make".into(), vec!["test".into()]),
+            ("./deploy.sh".into(), vec!["staging".into()]),
+        ];
+        assert_eq!(commands, expected);
+    }
+
+    #[test]
+    fn returns_error_if_job_file_does_not_exist() {
+        let result = load_steps(PathBuf::from("does/not/exist.toml"));
+        assert_matches!(result.unwrap_err(), JobError::Load(_));
+    }
+
+    #[test]
+    fn parses_quoted_arguments() {
+        let incantation =
+            parse_step("grep -r 'two words' \"src dir\"").unwrap();
+        assert_eq!(incantation.command, "grep");
+        assert_eq!(incantation.args, ["-r", "two words", "src dir"]);
+    }
+
+    #[test]
+    fn rejects_malformed_steps() {
+        assert_matches!(
+            parse_step("echo 'unterminated").unwrap_err(),
+            JobError::MalformedStep(_, _)
+        );
+        assert_matches!(parse_step("  ").unwrap_err(), JobError::EmptyStep);
+    }
+
+    // Hacks to minimize coverage report errors
+    #[test]
+    fn maximize_coverage() {
+        let errors = [
+            JobError::Load(LoadConfigError::Io(std::io::Error::from(
+                std::io::ErrorKind::NotFound,
+            ))),
+            parse_step("'").unwrap_err(),
+            JobError::EmptyStep,
+            JobError::NoSteps,
+        ];
+        for error in &errors {
+            let _ = format!("{} {:?}", error, error);
+        }
+    }
+}
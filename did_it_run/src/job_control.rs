@@ -0,0 +1,108 @@
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+/// Starts the command in a process group of its own, which can then be
+/// signaled as a whole without signaling diditrun. The group is put in the
+/// foreground of the terminal if `foreground`, so that the command can still
+/// read from the terminal and be interrupted and stopped from it.
+pub fn isolate(command: &mut Command, foreground: bool) {
+    // Safe because the closure only makes async-signal-safe system calls.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setpgid(0, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            if foreground {
+                give_terminal(libc::getpgrp());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Returns whether diditrun reads from a terminal.
+pub fn has_terminal() -> bool {
+    // Safe because `isatty` does not access memory.
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+}
+
+/// Returns whether diditrun is in the foreground of its terminal, and so can
+/// hand the terminal to the command.
+pub fn in_foreground() -> bool {
+    // Safe because `tcgetpgrp` and `getpgrp` do not access memory.
+    unsafe {
+        has_terminal() && libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp()
+    }
+}
+
+/// Takes the terminal back from the command's process group, if it has it.
+/// Returns whether it did.
+pub fn reclaim_terminal(group: u32) -> bool {
+    // Safe because `tcgetpgrp` and `getpgrp` do not access memory.
+    unsafe {
+        if libc::tcgetpgrp(libc::STDIN_FILENO) != group as libc::pid_t {
+            return false;
+        }
+        give_terminal(libc::getpgrp());
+    }
+    true
+}
+
+/// Stops diditrun, whose command was stopped, e.g., from the terminal, so
+/// that the shell gets the terminal back. Once diditrun is continued,
+/// continues the command too, giving it the terminal if diditrun was
+/// continued in the foreground.
+pub fn suspend(group: u32) {
+    reclaim_terminal(group);
+    // Safe because `kill` does not access memory. Stopping diditrun's whole
+    // process group is what the terminal would have done, and diditrun is
+    // stopped before `kill` returns.
+    unsafe { libc::kill(0, libc::SIGTSTP) };
+    if in_foreground() {
+        give_terminal(group as libc::pid_t);
+    }
+    // Safe because `kill` does not access memory.
+    unsafe { libc::kill(-(group as libc::pid_t), libc::SIGCONT) };
+}
+
+/// Puts `group` in the foreground of the terminal.
+fn give_terminal(group: libc::pid_t) {
+    // Processes outside the foreground are stopped for changing it unless
+    // they ignore SIGTTOU, which diditrun otherwise never handles.
+    // Safe because `signal` and `tcsetpgrp` do not access memory, and are
+    // async-signal-safe.
+    unsafe {
+        let handler = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        libc::tcsetpgrp(libc::STDIN_FILENO, group);
+        libc::signal(libc::SIGTTOU, handler);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn starts_command_in_process_group_of_its_own() {
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        isolate(&mut command, false);
+        let mut child = command.spawn().unwrap();
+        let pid = child.id() as libc::pid_t;
+        // Safe because `getpgid` and `getpgrp` do not access memory.
+        unsafe {
+            assert_eq!(libc::getpgid(pid), pid);
+            assert_ne!(libc::getpgrp(), pid);
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn does_not_reclaim_terminal_held_by_others() {
+        // Safe because `getpid` does not access memory.
+        let pid = unsafe { libc::getpid() } as u32;
+        assert!(!reclaim_terminal(pid + 1));
+    }
+}
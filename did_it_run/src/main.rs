@@ -2,17 +2,47 @@
 
 mod cli;
 mod config;
+mod context;
 mod duration_format;
+mod environment;
 mod exit_code;
 mod incantation;
+mod job;
+#[cfg(unix)]
+mod job_control;
 mod notifications;
+mod output;
+mod parallel;
+#[cfg(target_os = "linux")]
+mod proc;
+mod process_tree;
+#[cfg(unix)]
+mod pty;
+mod redact;
+mod resource_usage;
+mod retry;
+mod signal;
+mod signature;
+mod trigger;
+mod watch;
 
-use crate::config::{DEFAULT_CONFIG_FILES, DEFAULT_CREDENTIALS_FILES};
+use crate::config::{
+    Config, Credentials, DEFAULT_CONFIG_FILES, DEFAULT_CREDENTIALS_FILES,
+};
+use crate::context::RunContext;
 use crate::exit_code::ExitCode;
-use crate::notifications::Notifier;
+use crate::incantation::{Incantation, IncantationOutcome, Progress};
+use crate::job::{StepOutcome, StepReport};
+use crate::notifications::{Event, Notifier};
+use crate::parallel::Parallel;
+use crate::retry::Attempt;
+use crate::signature::{SignatureCheck, SignatureError};
+use crate::watch::{Process, WatchTarget};
 use std::env;
 use std::fmt::Display;
+use std::io::{self, Read};
 use std::process;
+use std::time::Instant;
 
 const DID_IT_RUN_NAME: &str = "Did it Run?";
 const DID_IT_RUN_EMAIL: &str = "notifications@didit.run";
@@ -40,23 +70,41 @@ fn main() {
     let credentials =
         config::Credentials::from_user_credentials(user_credentials)
             .unwrap_or_else(|err| exit(err, exit_code::CONFIG));
-    let mut notifier = Notifier::new(config, credentials)
+    if let Some(check) = options.signature_check {
+        verify_signature(&check, &credentials)
+            .unwrap_or_else(|err| exit(err, exit_code::FAILURE));
+        process::exit(exit_code::SUCCESS);
+    }
+    let mut notifier = Notifier::new(config.clone(), credentials)
         .unwrap_or_else(|err| exit(err, exit_code::FAILURE));
 
-    let outcome = incantation::run(&options.incantation);
-    let incantation_exit_code = match outcome.result {
-        Ok(status) => status.code().unwrap_or(exit_code::SUCCESS),
-        Err(err) => {
-            eprintln!("{}", err);
-            err.raw_os_error().unwrap_or(exit_code::FAILURE)
-        },
-    };
-    let event = notifications::Event::Finished {
-        incantation: options.incantation,
-        exit_code: incantation_exit_code,
-        elapsed_time: outcome.elapsed_time,
+    let steps = match options.job_file {
+        Some(file) => job::load_steps(file)
+            .unwrap_or_else(|err| exit(err, exit_code::CONFIG)),
+        None => options.steps,
     };
 
+    let (event, incantation_exit_code) =
+        match (options.watch, options.parallel, steps.len()) {
+            (Some(target), ..) => watch_process(&target),
+            (None, Some(parallel), _) => {
+                run_parallel(parallel, &config, &mut notifier)
+            },
+            // Clap requires a command unless a process is watched or steps or
+            // parallel commands are run.
+            (None, None, 0) => {
+                let mut incantation = options.incantation.unwrap();
+                if options.shell {
+                    incantation =
+                        incantation.through_shell(config.shell_program());
+                }
+                run(incantation, &config, None, &mut |event| {
+                    notify_progress(&mut notifier, event)
+                })
+            },
+            (None, None, _) => run_steps(steps, &config, &mut notifier),
+        };
+
     notifier
         .notify(event)
         .unwrap_or_else(|err| exit(err, exit_code::FAILURE));
@@ -64,6 +112,334 @@ fn main() {
     process::exit(incantation_exit_code);
 }
 
+/// Checks the signature of the webhook request on standard input.
+fn verify_signature(
+    check: &SignatureCheck,
+    credentials: &Credentials,
+) -> Result<(), SignatureError> {
+    let secret = credentials
+        .webhook
+        .as_ref()
+        .and_then(|webhook| webhook.secret.as_ref())
+        .ok_or(SignatureError::MissingSecret)?;
+    let mut body = vec![];
+    io::stdin().read_to_end(&mut body)?;
+    signature::verify(secret, check, &body, signature::timestamp())
+}
+
+/// Runs the incantation, retrying it as configured, and describes how it
+/// ended. `notify` is called with news about the incantation before then.
+fn run(
+    incantation: Incantation,
+    config: &Config,
+    output_prefix: Option<&str>,
+    notify: &mut dyn FnMut(Event),
+) -> (Event, ExitCode) {
+    let mut attempts = vec![];
+    loop {
+        let context = RunContext::start().with_environment(&config.environment);
+        let outcome = incantation::run_prefixed(
+            &incantation,
+            config,
+            output_prefix,
+            |progress| {
+                let incantation = incantation.clone();
+                notify(progress_event(incantation, progress, context.clone()))
+            },
+        );
+        let (event, exit_code) = outcome_event(
+            incantation.clone(),
+            outcome,
+            config,
+            &mut attempts,
+            context.finish(),
+        );
+        let retry = attempts.len() as u32;
+        if config.retries < retry || !is_retryable(&event) {
+            return (event, exit_code);
+        }
+        let delay = config.backoff.delay(config.retry_delay, retry);
+        if config.notify_each_attempt {
+            notify(Event::Retrying {
+                failure: Box::new(event.clone()),
+                retry,
+                retries: config.retries,
+                delay,
+            });
+        }
+        match retry::wait(delay) {
+            Ok(None) => {},
+            // Being interrupted while waiting means giving up on the command.
+            Ok(Some(_)) => return (event, exit_code),
+            Err(err) => {
+                eprintln!("{}", err);
+                return (event, exit_code);
+            },
+        }
+    }
+}
+
+/// Runs each step in turn, skipping the rest once one fails unless configured
+/// to keep going, and describes how every step went.
+fn run_steps(
+    steps: Vec<Incantation>,
+    config: &Config,
+    notifier: &mut Notifier,
+) -> (Event, ExitCode) {
+    let context = RunContext::start().with_environment(&config.environment);
+    let start = Instant::now();
+    let mut reports = vec![];
+    let mut failure: Option<(Event, ExitCode)> = None;
+    let mut interrupted = false;
+    for incantation in steps {
+        if interrupted || (failure.is_some() && !config.keep_going) {
+            reports.push(StepReport {
+                incantation,
+                outcome: StepOutcome::Skipped,
+            });
+            continue;
+        }
+        let step_start = Instant::now();
+        let (event, exit_code) =
+            run(incantation.clone(), config, None, &mut |event| {
+                notify_progress(notifier, event)
+            });
+        let succeeded = match event {
+            Event::Finished { succeeded, .. } => succeeded,
+            // An interrupt comes from the user, who wants the whole job to
+            // stop.
+            Event::Interrupted { .. } => {
+                interrupted = true;
+                false
+            },
+            _ => false,
+        };
+        reports.push(StepReport {
+            incantation,
+            outcome: StepOutcome::Finished {
+                exit_code,
+                succeeded,
+                elapsed_time: step_start.elapsed(),
+            },
+        });
+        if !succeeded && failure.is_none() {
+            failure = Some((event, exit_code));
+        }
+    }
+    let (failure, exit_code) = match failure {
+        Some((event, exit_code)) => (Some(Box::new(event)), exit_code),
+        None => (None, exit_code::SUCCESS),
+    };
+    let event = Event::JobFinished {
+        steps: reports,
+        elapsed_time: start.elapsed(),
+        failure,
+        context: context.finish(),
+    };
+    (event, exit_code)
+}
+
+/// Runs the commands side by side and describes how they all went.
+fn run_parallel(
+    parallel: Parallel,
+    config: &Config,
+    notifier: &mut Notifier,
+) -> (Event, ExitCode) {
+    let config = config.clone();
+    parallel::run(
+        parallel,
+        move |incantation, output_prefix, notify| {
+            run(incantation, &config, Some(output_prefix), notify)
+        },
+        |event| notify_progress(notifier, event),
+    )
+}
+
+/// Sends a notification before the final one, reporting rather than failing
+/// on errors so that the command keeps being watched.
+fn notify_progress(notifier: &mut Notifier, event: Event) {
+    if let Err(err) = notifier.notify(event) {
+        eprintln!("{}", err);
+    }
+}
+
+/// Waits for a process that diditrun did not start to exit.
+fn watch_process(target: &WatchTarget) -> (Event, ExitCode) {
+    let process = Process::find(target)
+        .unwrap_or_else(|err| exit(err, exit_code::NOINPUT));
+    let pid = process.pid;
+    let incantation = process.incantation.clone();
+    let elapsed_time = process
+        .wait()
+        .unwrap_or_else(|err| exit(err, exit_code::FAILURE));
+    let event = Event::Exited {
+        incantation,
+        pid,
+        elapsed_time,
+        context: RunContext::started_ago(elapsed_time).finish(),
+    };
+    (event, exit_code::SUCCESS)
+}
+
+/// Describes how the incantation ended, recording it in `attempts`, and
+/// chooses the exit code diditrun should exit with.
+fn outcome_event(
+    incantation: Incantation,
+    outcome: IncantationOutcome,
+    config: &Config,
+    attempts: &mut Vec<Attempt>,
+    context: RunContext,
+) -> (Event, ExitCode) {
+    let elapsed_time = outcome.elapsed_time;
+    let mut record = |exit_code| {
+        attempts.push(Attempt {
+            exit_code,
+            elapsed_time,
+        });
+        attempts.clone()
+    };
+    if let (Some(timeout), true) = (config.timeout, outcome.timed_out) {
+        let event = Event::TimedOut {
+            incantation,
+            timeout,
+            output: outcome.output,
+            resource_usage: outcome.resource_usage,
+            stragglers: outcome.stragglers,
+            attempts: record(exit_code::TIMEOUT),
+            context,
+        };
+        return (event, exit_code::TIMEOUT);
+    }
+    if let (Some(stall_after), true) = (config.stall_after, outcome.stalled) {
+        // A stall is a timeout on output.
+        let event = Event::Stalled {
+            incantation,
+            idle_time: stall_after,
+            elapsed_time,
+            terminated: true,
+            output: outcome.output,
+            resource_usage: outcome.resource_usage,
+            stragglers: outcome.stragglers,
+            attempts: record(exit_code::TIMEOUT),
+            context,
+        };
+        return (event, exit_code::TIMEOUT);
+    }
+    if let Some(signal) = outcome.interruption {
+        let exit_code = exit_code::SIGNAL_BASE + signal;
+        let event = Event::Interrupted {
+            incantation,
+            signal,
+            elapsed_time,
+            output: outcome.output,
+            resource_usage: outcome.resource_usage,
+            stragglers: outcome.stragglers,
+            attempts: record(exit_code),
+            context,
+        };
+        return (event, exit_code);
+    }
+    if let Some(termination) = outcome.termination {
+        let exit_code = exit_code::SIGNAL_BASE + termination.signal;
+        let event = Event::Killed {
+            incantation,
+            signal: termination.signal,
+            core_dumped: termination.core_dumped,
+            elapsed_time,
+            output: outcome.output,
+            resource_usage: outcome.resource_usage,
+            attempts: record(exit_code),
+            context,
+        };
+        return (event, exit_code);
+    }
+    let (exit_code, succeeded) = match outcome.result {
+        Ok(status) => {
+            let exit_code = status.code().unwrap_or(exit_code::FAILURE);
+            let success_codes = config.success_codes(&incantation);
+            (exit_code, success_codes.contains(&exit_code))
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            (err.raw_os_error().unwrap_or(exit_code::FAILURE), false)
+        },
+    };
+    let event = Event::Finished {
+        incantation,
+        exit_code,
+        succeeded,
+        elapsed_time,
+        output: outcome.output,
+        resource_usage: outcome.resource_usage,
+        attempts: record(exit_code),
+        context,
+    };
+    (event, exit_code)
+}
+
+/// Describes news about the incantation while it runs.
+fn progress_event(
+    incantation: Incantation,
+    progress: Progress,
+    context: RunContext,
+) -> Event {
+    match progress {
+        Progress::StillRunning {
+            elapsed_time,
+            output,
+            resource_usage,
+        } => Event::StillRunning {
+            incantation,
+            elapsed_time,
+            output,
+            resource_usage,
+            context,
+        },
+        Progress::OutputMatched {
+            elapsed_time,
+            output_match,
+        } => Event::OutputMatched {
+            incantation,
+            elapsed_time,
+            output_match,
+            context,
+        },
+        Progress::Stalled {
+            elapsed_time,
+            idle_time,
+            output,
+            resource_usage,
+        } => Event::Stalled {
+            incantation,
+            idle_time,
+            elapsed_time,
+            terminated: false,
+            output,
+            resource_usage,
+            stragglers: vec![],
+            attempts: vec![],
+            context,
+        },
+    }
+}
+
+/// Whether an attempt failed in a way that running the command again might
+/// fix. Interrupts come from the user, so they are never retried.
+fn is_retryable(event: &Event) -> bool {
+    match *event {
+        Event::Finished { succeeded, .. } => !succeeded,
+        Event::Stalled { terminated, .. } => terminated,
+        Event::Killed { .. } | Event::TimedOut { .. } => true,
+        Event::Interrupted { .. } |
+        Event::JobFinished { .. } |
+        Event::ParallelFinished { .. } |
+        Event::Exited { .. } |
+        Event::StillRunning { .. } |
+        Event::OutputMatched { .. } |
+        Event::Retrying { .. } => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -71,7 +447,7 @@ mod test {
     use std::collections::HashSet;
     use std::ffi::OsString;
     use std::fs;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
     lazy_static! {
         static ref PROJECT_ROOT_NAME: OsString = OsString::from("did-it-run");
@@ -93,7 +469,7 @@ mod test {
                 if files.is_superset(&root_folder_files_sample) {
                     break;
                 }
-                if path == PathBuf::from("/") {
+                if path == Path::new("/") {
                     panic!("Test not run inside project directory.");
                 }
                 path.pop();
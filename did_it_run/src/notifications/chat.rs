@@ -0,0 +1,192 @@
+use crate::duration_format::duration_format;
+use crate::notifications::webhook::{Payload, DEFAULT_WEBHOOK_TIMEOUT};
+use crate::notifications::Event;
+use serde_json::Value;
+
+/// Opens and closes a code block in Markdown.
+pub const FENCE: &str = "```";
+
+/// Returns an agent that gives up on chat services as webhooks do.
+pub fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(DEFAULT_WEBHOOK_TIMEOUT)
+        .build()
+}
+
+/// Posts `message` to an incoming webhook of a chat service.
+pub fn post_json(
+    agent: &ureq::Agent,
+    url: &str,
+    message: &Value,
+) -> Result<(), Box<ureq::Error>> {
+    agent
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&message.to_string())?;
+    Ok(())
+}
+
+/// Returns the name and value of each fact shown about the event, in order.
+pub fn facts(event: &Event, payload: &Payload) -> Vec<(&'static str, String)> {
+    let mut facts = vec![];
+    if let Some(exit_code) = payload.exit_code {
+        facts.push(("Exit code", exit_code.to_string()));
+    }
+    facts.push(("Duration", duration_format(&event.elapsed_time())));
+    if let Some(ref host) = payload.context.host {
+        facts.push(("Host", host.clone()));
+    }
+    facts
+}
+
+/// Returns the name and lines of each output stream that the command wrote
+/// to, if output was captured.
+pub fn output_streams(event: &Event) -> Vec<(&'static str, String)> {
+    let output = match event.output() {
+        Some(output) => output,
+        None => return vec![],
+    };
+    [("stdout", &output.stdout), ("stderr", &output.stderr)]
+        .iter()
+        .filter(|&&(_, lines)| !lines.is_empty())
+        .map(|&(name, lines)| (name, lines.join("\n")))
+        .collect()
+}
+
+/// Breaks up fences in `text`, which would end the code block it is put in
+/// early, with a zero-width space.
+pub fn break_fences(text: &str) -> String {
+    text.replace(FENCE, "``\u{200B}`")
+}
+
+/// Returns the start of `text` whose characters take up at most `room`, as
+/// measured by `width`, so that no character is cut.
+pub fn head_by_width(
+    text: &str,
+    mut room: usize,
+    width: impl Fn(char) -> usize,
+) -> &str {
+    let mut end = 0;
+    for (index, character) in text.char_indices() {
+        let width = width(character);
+        if width > room {
+            break;
+        }
+        room -= width;
+        end = index + character.len_utf8();
+    }
+    &text[..end]
+}
+
+/// Returns the end of `text` that is at most `length` characters long.
+pub fn tail(text: &str, length: usize) -> &str {
+    tail_by_width(text, length, |_| 1)
+}
+
+/// Returns the end of `text` whose characters take up at most `room`, as
+/// measured by `width`, so that no character is cut.
+pub fn tail_by_width(
+    text: &str,
+    mut room: usize,
+    width: impl Fn(char) -> usize,
+) -> &str {
+    let mut start = text.len();
+    for (index, character) in text.char_indices().rev() {
+        let width = width(character);
+        if width > room {
+            break;
+        }
+        room -= width;
+        start = index;
+    }
+    &text[start..]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::context::test::CONTEXT;
+    use crate::incantation::Incantation;
+    use crate::notifications::notification_info::test::NOTIFICATION_INFO;
+    use crate::notifications::test::EVENT_FINISHED;
+    use crate::notifications::webhook::test::receive_one;
+    use crate::output::CapturedOutput;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn posts_json_to_webhook_url() {
+        let (url, receiver) = receive_one(200);
+        let message = json!({ "text": "hello" });
+        post_json(&agent(), &url, &message).unwrap();
+        let received = receiver.join().unwrap();
+        assert_eq!(received.method, "POST");
+        assert_eq!(received.url, "/hooks/diditrun");
+        assert_eq!(received.headers["content-type"], "application/json");
+        assert_eq!(received.body, message.to_string());
+        let (url, receiver) = receive_one(404);
+        assert!(post_json(&agent(), &url, &message).is_err());
+        receiver.join().unwrap();
+    }
+
+    #[test]
+    fn lists_facts_about_event() {
+        let payload = Payload::new(&EVENT_FINISHED, NOTIFICATION_INFO.clone());
+        assert_eq!(
+            facts(&EVENT_FINISHED, &payload),
+            [
+                ("Exit code", "0".to_string()),
+                ("Duration", "2s".to_string()),
+                ("Host", "build-3".to_string()),
+            ]
+        );
+        let event = Event::TimedOut {
+            incantation: Incantation::new("make", vec![]),
+            timeout: Duration::from_secs(90),
+            output: None,
+            resource_usage: None,
+            stragglers: vec![],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let payload = Payload::new(&event, NOTIFICATION_INFO.clone());
+        let facts = facts(&event, &payload);
+        assert_eq!(facts[0], ("Duration", "1m 30s".to_string()));
+    }
+
+    #[test]
+    fn lists_streams_written_to() {
+        let event = Event::StillRunning {
+            incantation: Incantation::new("make", vec![]),
+            elapsed_time: Duration::from_secs(1),
+            output: Some(CapturedOutput {
+                stdout: vec![],
+                stderr: vec!["a".to_string(), "b".to_string()],
+            }),
+            resource_usage: None,
+            context: CONTEXT.clone(),
+        };
+        assert_eq!(output_streams(&event), [("stderr", "a\nb".to_string())]);
+        assert_eq!(output_streams(&EVENT_FINISHED), []);
+    }
+
+    #[test]
+    fn keeps_end_of_text() {
+        assert_eq!(tail("abcdef", 3), "def");
+        assert_eq!(tail("ab", 3), "ab");
+        assert_eq!(tail("añb", 2), "ñb");
+        let width = |character| if character == '&' { 5 } else { 1 };
+        assert_eq!(tail_by_width("a&b", 6, width), "&b");
+        assert_eq!(tail_by_width("a&b", 5, width), "b");
+        assert_eq!(break_fences("```"), "``\u{200B}`");
+    }
+
+    #[test]
+    fn keeps_start_of_text() {
+        let width = |character| if character == '&' { 5 } else { 1 };
+        assert_eq!(head_by_width("añb", 2, width), "añ");
+        assert_eq!(head_by_width("a&b", 6, width), "a&");
+        assert_eq!(head_by_width("a&b", 5, width), "a");
+        assert_eq!(head_by_width("ab", 3, width), "ab");
+    }
+}
@@ -1,4 +1,6 @@
-use crate::notifications::{Dispatcher, NotificationInfo, NotifierError};
+use crate::notifications::{
+    Dispatcher, Event, NotificationInfo, NotifierError,
+};
 use crate::DID_IT_RUN_NAME;
 use std::convert::From;
 use std::error;
@@ -71,6 +73,7 @@ impl Dispatcher for DesktopNotifier {
     #[cfg(all(target_os = "linux", not(test)))]
     fn dispatch_notification(
         &mut self,
+        _: &Event,
         info: NotificationInfo,
     ) -> Result<(), NotifierError> {
         let summary = DID_IT_RUN_NAME;
@@ -83,6 +86,7 @@ impl Dispatcher for DesktopNotifier {
     #[cfg(all(target_os = "macos", not(test)))]
     fn dispatch_notification(
         &mut self,
+        _: &Event,
         info: NotificationInfo,
     ) -> Result<(), NotifierError> {
         let title = DID_IT_RUN_NAME;
@@ -98,6 +102,7 @@ impl Dispatcher for DesktopNotifier {
     #[cfg(all(target_os = "windows", not(test)))]
     fn dispatch_notification(
         &mut self,
+        _: &Event,
         info: NotificationInfo,
     ) -> Result<(), NotifierError> {
         // TODO(#30): Replace `ok_or` with the `?` shorthand and
@@ -148,6 +153,7 @@ impl Dispatcher for DesktopNotifier {
     #[cfg(test)]
     fn dispatch_notification(
         &mut self,
+        _: &Event,
         _: NotificationInfo,
     ) -> Result<(), NotifierError> {
         Ok(())
@@ -167,7 +173,7 @@ impl error::Error for DesktopError {}
 
 impl fmt::Display for DesktopError {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        write!(formatter, "{}", self)
+        write!(formatter, "{:?}", self)
     }
 }
 
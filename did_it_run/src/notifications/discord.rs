@@ -0,0 +1,346 @@
+use crate::notifications::chat::{self, FENCE};
+use crate::notifications::webhook::Payload;
+use crate::notifications::{
+    Dispatcher, Event, NotificationInfo, NotifierError,
+};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use std::error;
+use std::fmt;
+
+/// Discord's limits on the parts of an embed, in characters.
+const MAX_TITLE_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+const MAX_FIELD_LENGTH: usize = 1024;
+/// The limit on the title, description and field names and values together.
+const MAX_EMBED_LENGTH: usize = 6000;
+
+const SUCCESS_COLOR: u32 = 0x2E_CC_71;
+const FAILURE_COLOR: u32 = 0xE7_4C_3C;
+/// For events about commands that have not finished yet.
+const NEUTRAL_COLOR: u32 = 0x95_A5_A6;
+
+/// Overrides for who messages are posted as.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct DiscordConfig {
+    pub username: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct DiscordCredentials {
+    pub webhook_url: String,
+}
+
+pub struct Discord {
+    agent: ureq::Agent,
+    config: DiscordConfig,
+    webhook_url: String,
+}
+
+#[derive(Debug)]
+pub enum DiscordError {
+    MissingCredentials,
+    Http(Box<ureq::Error>),
+}
+
+impl Discord {
+    pub fn new(
+        config: DiscordConfig,
+        credentials: DiscordCredentials,
+        validate: bool,
+    ) -> Result<Self, DiscordError> {
+        let agent = chat::agent();
+        if validate {
+            // Discord describes the webhook in answer to a GET, and refuses
+            // if it does not exist, without posting anything.
+            agent.get(&credentials.webhook_url).call()?;
+        }
+        Ok(Discord {
+            agent,
+            config,
+            webhook_url: credentials.webhook_url,
+        })
+    }
+
+    /// Builds the message for the event as an embed colored by its outcome,
+    /// cutting the output before the commands to fit Discord's limits.
+    fn message(&self, event: &Event, info: NotificationInfo) -> Value {
+        let payload = Payload::new(event, info);
+        let color = match payload.succeeded {
+            Some(true) => SUCCESS_COLOR,
+            Some(false) => FAILURE_COLOR,
+            None => NEUTRAL_COLOR,
+        };
+        let title = truncate(&payload.brief, MAX_TITLE_LENGTH);
+        let facts = chat::facts(event, &payload);
+        let streams = chat::output_streams(event);
+        let mut room = MAX_EMBED_LENGTH -
+            length(&title) -
+            facts
+                .iter()
+                .map(|&(name, ref value)| length(name) + length(value))
+                .sum::<usize>() -
+            streams.iter().map(|&(name, _)| length(name)).sum::<usize>();
+        let mut embed = json!({
+            "title": title,
+            "color": color,
+            "timestamp": payload.context.started_at,
+        });
+        let command_lines = payload.command_lines();
+        if !command_lines.is_empty() {
+            let description = code_block(
+                &command_lines.join("\n"),
+                MAX_DESCRIPTION_LENGTH.min(room),
+            );
+            room -= length(&description);
+            embed["description"] = json!(description);
+        }
+        let mut fields: Vec<_> = facts
+            .into_iter()
+            .map(|(name, value)| field(name, &value, true))
+            .collect();
+        for (index, &(name, ref text)) in streams.iter().enumerate() {
+            // The streams share what room is left.
+            let share = room / (streams.len() - index);
+            let code = code_block(text, share.min(MAX_FIELD_LENGTH));
+            room -= length(&code);
+            fields.push(field(name, &code, false));
+        }
+        embed["fields"] = json!(fields);
+        let mut message = json!({ "embeds": [embed] });
+        if let Some(ref username) = self.config.username {
+            message["username"] = json!(username);
+        }
+        if let Some(ref avatar_url) = self.config.avatar_url {
+            message["avatar_url"] = json!(avatar_url);
+        }
+        message
+    }
+}
+
+impl Dispatcher for Discord {
+    fn dispatch_notification(
+        &mut self,
+        event: &Event,
+        info: NotificationInfo,
+    ) -> Result<(), NotifierError> {
+        let message = self.message(event, info);
+        chat::post_json(&self.agent, &self.webhook_url, &message)
+            .map_err(DiscordError::Http)?;
+        Ok(())
+    }
+}
+
+fn field(name: &str, value: &str, inline: bool) -> Value {
+    json!({ "name": name, "value": value, "inline": inline })
+}
+
+fn length(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Keeps the start of `text`, marking where it was cut, if it is longer than
+/// `length` characters.
+fn truncate(text: &str, length: usize) -> String {
+    if self::length(text) > length {
+        let mut text: String = text.chars().take(length - 1).collect();
+        text.push('…');
+        text
+    } else {
+        text.to_string()
+    }
+}
+
+/// Puts as much of the end of `text` in a code block of at most `length`
+/// characters as fits.
+fn code_block(text: &str, length: usize) -> String {
+    let text = chat::break_fences(text);
+    let room = length.saturating_sub(2 * (FENCE.len() + 1));
+    format!("{}\n{}\n{}", FENCE, chat::tail(&text, room), FENCE)
+}
+
+impl error::Error for DiscordError {}
+
+impl fmt::Display for DiscordError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DiscordError::MissingCredentials => write!(
+                formatter,
+                "Cannot post to Discord without a webhook_url under [discord] \
+                 in the credentials file."
+            ),
+            DiscordError::Http(ref err) => {
+                write!(formatter, "Discord request failed: {}", err)
+            },
+        }
+    }
+}
+
+impl From<ureq::Error> for DiscordError {
+    fn from(err: ureq::Error) -> Self {
+        DiscordError::Http(Box::new(err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::context::test::CONTEXT;
+    use crate::incantation::Incantation;
+    use crate::notifications::notification_info::test::NOTIFICATION_INFO;
+    use crate::notifications::test::EVENT_FINISHED;
+    use crate::notifications::webhook::test::{receive, receive_one};
+    use crate::output::CapturedOutput;
+    use matches::assert_matches;
+    use std::time::Duration;
+
+    /// Nothing listens on the discard port.
+    const UNUSED_URL: &str = "http://127.0.0.1:9/";
+
+    fn discord(webhook_url: &str, config: DiscordConfig) -> Discord {
+        let credentials = DiscordCredentials {
+            webhook_url: webhook_url.to_string(),
+        };
+        Discord::new(config, credentials, false).unwrap()
+    }
+
+    fn killed(incantation: Incantation, output: CapturedOutput) -> Event {
+        Event::Killed {
+            incantation,
+            signal: 9,
+            core_dumped: false,
+            elapsed_time: Duration::from_secs(3),
+            output: Some(output),
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        }
+    }
+
+    #[test]
+    fn checks_webhook_then_posts_embed() {
+        let (url, receiver) = receive(vec![200, 204]);
+        let credentials = DiscordCredentials {
+            webhook_url: url.clone(),
+        };
+        let mut discord =
+            Discord::new(DiscordConfig::default(), credentials, true).unwrap();
+        discord
+            .dispatch_notification(&EVENT_FINISHED, NOTIFICATION_INFO.clone())
+            .unwrap();
+        let received = receiver.join().unwrap();
+        assert_eq!(received[0].method, "GET");
+        assert_eq!(received[1].method, "POST");
+        let message: Value = serde_json::from_str(&received[1].body).unwrap();
+        assert_eq!(message.get("username"), None);
+        let embed = &message["embeds"][0];
+        assert_eq!(embed["title"], "Notification summary");
+        assert_eq!(embed["color"], SUCCESS_COLOR);
+        assert_eq!(embed["description"], "```\nfoo bar baz\n```");
+        assert_eq!(embed["timestamp"], CONTEXT.start_time.to_rfc3339());
+        assert_eq!(
+            embed["fields"],
+            json!([
+                { "name": "Exit code", "value": "0", "inline": true },
+                { "name": "Duration", "value": "2s", "inline": true },
+                { "name": "Host", "value": "build-3", "inline": true },
+            ])
+        );
+    }
+
+    #[test]
+    fn returns_error_if_webhook_does_not_exist() {
+        let (url, receiver) = receive_one(404);
+        let credentials = DiscordCredentials { webhook_url: url };
+        let discord = Discord::new(DiscordConfig::default(), credentials, true);
+        receiver.join().unwrap();
+        assert_matches!(discord.err(), Some(DiscordError::Http(_)));
+    }
+
+    #[test]
+    fn overrides_username_and_avatar() {
+        let config = DiscordConfig {
+            username: Some("diditrun".to_string()),
+            avatar_url: Some("https://example.com/avatar.png".to_string()),
+        };
+        let message = discord(UNUSED_URL, config)
+            .message(&EVENT_FINISHED, NOTIFICATION_INFO.clone());
+        assert_eq!(message["username"], "diditrun");
+        assert_eq!(message["avatar_url"], "https://example.com/avatar.png");
+    }
+
+    #[test]
+    fn truncates_embed_to_discord_limits() {
+        let output = CapturedOutput {
+            stdout: vec![],
+            stderr: vec!["e".repeat(MAX_FIELD_LENGTH), "```".to_string()],
+        };
+        let event = killed(Incantation::new("make", vec![]), output);
+        let info = NotificationInfo {
+            brief: "b".repeat(MAX_TITLE_LENGTH + 1),
+            ..NOTIFICATION_INFO.clone()
+        };
+        let message =
+            discord(UNUSED_URL, DiscordConfig::default()).message(&event, info);
+        let embed = &message["embeds"][0];
+        assert_eq!(embed["color"], FAILURE_COLOR);
+        let title = embed["title"].as_str().unwrap();
+        assert_eq!(title.chars().count(), MAX_TITLE_LENGTH);
+        assert!(title.ends_with("b…"));
+        let fields = embed["fields"].as_array().unwrap();
+        assert_eq!(fields[0]["name"], "Duration");
+        let stderr = &fields[2];
+        assert_eq!(stderr["name"], "stderr");
+        assert_eq!(stderr["inline"], false);
+        let value = stderr["value"].as_str().unwrap();
+        assert_eq!(value.chars().count(), MAX_FIELD_LENGTH);
+        assert!(value.starts_with("```\neee"));
+        assert!(value.ends_with("e\n``\u{200B}`\n```"));
+    }
+
+    #[test]
+    fn cuts_output_before_command_to_fit_embed() {
+        let long_line = "x".repeat(MAX_FIELD_LENGTH);
+        let output = CapturedOutput {
+            stdout: vec![long_line.clone()],
+            stderr: vec![long_line],
+        };
+        let event = killed(Incantation::new("c".repeat(5000), vec![]), output);
+        let info = NotificationInfo {
+            brief: "b".repeat(MAX_TITLE_LENGTH),
+            ..NOTIFICATION_INFO.clone()
+        };
+        let message =
+            discord(UNUSED_URL, DiscordConfig::default()).message(&event, info);
+        let embed = &message["embeds"][0];
+        let length = |value: &Value| value.as_str().unwrap().chars().count();
+        let description = length(&embed["description"]);
+        assert_eq!(description, MAX_DESCRIPTION_LENGTH);
+        let fields = embed["fields"].as_array().unwrap();
+        let fields_length: usize = fields
+            .iter()
+            .map(|field| length(&field["name"]) + length(&field["value"]))
+            .sum();
+        let total = MAX_TITLE_LENGTH + description + fields_length;
+        assert!(total <= MAX_EMBED_LENGTH, "{}", total);
+        let stdout = length(&fields[1]["value"]);
+        let stderr = length(&fields[2]["value"]);
+        assert!(stdout < MAX_FIELD_LENGTH && stderr < MAX_FIELD_LENGTH);
+        assert!(MAX_EMBED_LENGTH - total < 2);
+    }
+
+    #[test]
+    fn colors_unfinished_commands_neutrally() {
+        let event = Event::StillRunning {
+            incantation: Incantation::new("make", vec![]),
+            elapsed_time: Duration::from_secs(1),
+            output: None,
+            resource_usage: None,
+            context: CONTEXT.clone(),
+        };
+        let message = discord(UNUSED_URL, DiscordConfig::default())
+            .message(&event, NOTIFICATION_INFO.clone());
+        assert_eq!(message["embeds"][0]["color"], NEUTRAL_COLOR);
+    }
+}
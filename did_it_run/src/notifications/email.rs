@@ -1,5 +1,7 @@
 use crate::config::Config;
-use crate::notifications::{Dispatcher, NotificationInfo, NotifierError};
+use crate::notifications::{
+    Dispatcher, Event, NotificationInfo, NotifierError,
+};
 use crate::{DID_IT_RUN_EMAIL, DID_IT_RUN_NAME};
 use common::types::Port;
 use lettre::smtp::authentication::Mechanism;
@@ -103,6 +105,7 @@ impl Mailer {
 impl Dispatcher for Mailer {
     fn dispatch_notification(
         &mut self,
+        _: &Event,
         info: NotificationInfo,
     ) -> Result<(), NotifierError> {
         let subject = info.brief;
@@ -175,6 +178,7 @@ impl From<native_tls::Error> for MailerError {
 mod test {
     use super::*;
     use crate::notifications::notification_info::test::NOTIFICATION_INFO;
+    use crate::notifications::test::EVENT_FINISHED;
     use crate::test::PROJECT_ROOT_PATH;
     use lazy_static::lazy_static;
     use mailin_embedded::{
@@ -372,7 +376,8 @@ mod test {
         let mut mailer = Mailer::new(config.clone(), credentials).unwrap();
         assert!(auth_success_receiver.iter().next().unwrap());
 
-        let result = mailer.dispatch_notification(NOTIFICATION_INFO.clone());
+        let result = mailer
+            .dispatch_notification(&EVENT_FINISHED, NOTIFICATION_INFO.clone());
         assert!(auth_success_receiver.iter().next().unwrap());
         assert!(result.is_ok());
 
@@ -492,7 +497,8 @@ mod test {
         let handle = server.serve().unwrap();
 
         let mut mailer = Mailer::new(config, credentials).unwrap();
-        let result = mailer.dispatch_notification(NOTIFICATION_INFO.clone());
+        let result = mailer
+            .dispatch_notification(&EVENT_FINISHED, NOTIFICATION_INFO.clone());
         handle.stop();
 
         assert!(!auth_success_receiver.iter().next().unwrap());
@@ -1,13 +1,32 @@
+mod chat;
 pub mod desktop;
+pub mod discord;
 pub mod email;
 mod notification_info;
+pub mod slack;
+pub mod teams;
+pub mod webhook;
 
 use crate::config::{Config, Credentials};
+use crate::context::RunContext;
 use crate::exit_code::ExitCode;
 use crate::incantation::Incantation;
+use crate::job::StepReport;
 use crate::notifications::desktop::{DesktopError, DesktopNotifier};
+use crate::notifications::discord::{Discord, DiscordError};
 use crate::notifications::email::{Mailer, MailerError};
 use crate::notifications::notification_info::NotificationInfo;
+use crate::notifications::slack::{Slack, SlackError};
+use crate::notifications::teams::{Teams, TeamsError};
+use crate::notifications::webhook::{Webhook, WebhookError};
+use crate::output::CapturedOutput;
+use crate::process_tree::Straggler;
+use crate::redact::Redactor;
+use crate::resource_usage::ResourceUsage;
+use crate::retry::Attempt;
+use crate::signal::Signal;
+use crate::trigger::OutputMatch;
+use crate::watch::Pid;
 use std::error;
 use std::fmt;
 use std::time::Duration;
@@ -17,23 +36,214 @@ pub enum Event {
     Finished {
         incantation: Incantation,
         exit_code: ExitCode,
+        /// Whether the exit code is one of the command's success codes.
+        succeeded: bool,
         elapsed_time: Duration,
+        output: Option<CapturedOutput>,
+        resource_usage: Option<ResourceUsage>,
+        /// Every run of the command so far, ending with this one.
+        attempts: Vec<Attempt>,
+        /// Where and when the command ran.
+        context: RunContext,
+    },
+    Killed {
+        incantation: Incantation,
+        signal: Signal,
+        core_dumped: bool,
+        elapsed_time: Duration,
+        output: Option<CapturedOutput>,
+        resource_usage: Option<ResourceUsage>,
+        attempts: Vec<Attempt>,
+        context: RunContext,
+    },
+    Interrupted {
+        incantation: Incantation,
+        signal: Signal,
+        elapsed_time: Duration,
+        output: Option<CapturedOutput>,
+        resource_usage: Option<ResourceUsage>,
+        /// Processes the command started that had to be killed.
+        stragglers: Vec<Straggler>,
+        attempts: Vec<Attempt>,
+        context: RunContext,
+    },
+    TimedOut {
+        incantation: Incantation,
+        timeout: Duration,
+        output: Option<CapturedOutput>,
+        resource_usage: Option<ResourceUsage>,
+        /// Processes the command started that had to be killed.
+        stragglers: Vec<Straggler>,
+        attempts: Vec<Attempt>,
+        context: RunContext,
+    },
+    /// Every step of a job has run, or one failed and the rest were skipped.
+    JobFinished {
+        steps: Vec<StepReport>,
+        elapsed_time: Duration,
+        /// How the first step that failed ended.
+        failure: Option<Box<Event>>,
+        context: RunContext,
+    },
+    /// Every command run side by side has finished, or was never started
+    /// because one was interrupted.
+    ParallelFinished {
+        commands: Vec<StepReport>,
+        elapsed_time: Duration,
+        context: RunContext,
+    },
+    /// A process that diditrun did not start, and so cannot know the exit
+    /// code of, has exited.
+    Exited {
+        incantation: Incantation,
+        pid: Pid,
+        elapsed_time: Duration,
+        context: RunContext,
+    },
+    /// The command has been running for another heartbeat interval.
+    StillRunning {
+        incantation: Incantation,
+        elapsed_time: Duration,
+        output: Option<CapturedOutput>,
+        resource_usage: Option<ResourceUsage>,
+        context: RunContext,
+    },
+    /// The command has written no output for `idle_time`, and was terminated
+    /// for it if `terminated`.
+    Stalled {
+        incantation: Incantation,
+        idle_time: Duration,
+        elapsed_time: Duration,
+        terminated: bool,
+        output: Option<CapturedOutput>,
+        resource_usage: Option<ResourceUsage>,
+        /// Processes the command started that had to be killed.
+        stragglers: Vec<Straggler>,
+        attempts: Vec<Attempt>,
+        context: RunContext,
+    },
+    /// A line of the command's output matched a trigger.
+    OutputMatched {
+        incantation: Incantation,
+        elapsed_time: Duration,
+        output_match: OutputMatch,
+        context: RunContext,
+    },
+    /// An attempt failed and the command will be run again after `delay`.
+    Retrying {
+        failure: Box<Event>,
+        retry: u32,
+        retries: u32,
+        delay: Duration,
     },
 }
 
+impl Event {
+    /// Returns where and when the command the event is about ran.
+    pub fn context(&self) -> &RunContext {
+        match *self {
+            Event::Finished { ref context, .. } |
+            Event::Killed { ref context, .. } |
+            Event::Interrupted { ref context, .. } |
+            Event::TimedOut { ref context, .. } |
+            Event::JobFinished { ref context, .. } |
+            Event::ParallelFinished { ref context, .. } |
+            Event::Exited { ref context, .. } |
+            Event::StillRunning { ref context, .. } |
+            Event::Stalled { ref context, .. } |
+            Event::OutputMatched { ref context, .. } => context,
+            Event::Retrying { ref failure, .. } => failure.context(),
+        }
+    }
+
+    /// Returns the tail of the output of the command the event is about, if
+    /// it was captured.
+    pub fn output(&self) -> Option<&CapturedOutput> {
+        match *self {
+            Event::Finished { ref output, .. } |
+            Event::Killed { ref output, .. } |
+            Event::Interrupted { ref output, .. } |
+            Event::TimedOut { ref output, .. } |
+            Event::StillRunning { ref output, .. } |
+            Event::Stalled { ref output, .. } => output.as_ref(),
+            Event::JobFinished { .. } |
+            Event::ParallelFinished { .. } |
+            Event::Exited { .. } |
+            Event::OutputMatched { .. } => None,
+            Event::Retrying { ref failure, .. } => failure.output(),
+        }
+    }
+
+    /// Returns the resources the command the event is about has used, if the
+    /// platform can tell.
+    pub fn resource_usage(&self) -> Option<&ResourceUsage> {
+        match *self {
+            Event::Finished {
+                ref resource_usage, ..
+            } |
+            Event::Killed {
+                ref resource_usage, ..
+            } |
+            Event::Interrupted {
+                ref resource_usage, ..
+            } |
+            Event::TimedOut {
+                ref resource_usage, ..
+            } |
+            Event::StillRunning {
+                ref resource_usage, ..
+            } |
+            Event::Stalled {
+                ref resource_usage, ..
+            } => resource_usage.as_ref(),
+            Event::JobFinished { .. } |
+            Event::ParallelFinished { .. } |
+            Event::Exited { .. } |
+            Event::OutputMatched { .. } => None,
+            Event::Retrying { ref failure, .. } => failure.resource_usage(),
+        }
+    }
+
+    /// Returns how long the command the event is about has run for, which is
+    /// its timeout if it timed out.
+    pub fn elapsed_time(&self) -> Duration {
+        match *self {
+            Event::Finished { elapsed_time, .. } |
+            Event::Killed { elapsed_time, .. } |
+            Event::Interrupted { elapsed_time, .. } |
+            Event::JobFinished { elapsed_time, .. } |
+            Event::ParallelFinished { elapsed_time, .. } |
+            Event::Exited { elapsed_time, .. } |
+            Event::StillRunning { elapsed_time, .. } |
+            Event::Stalled { elapsed_time, .. } |
+            Event::OutputMatched { elapsed_time, .. } => elapsed_time,
+            Event::TimedOut { timeout, .. } => timeout,
+            Event::Retrying { ref failure, .. } => failure.elapsed_time(),
+        }
+    }
+}
+
 pub struct Notifier {
     dispatchers: Vec<Box<dyn Dispatcher>>,
+    redactor: Redactor,
 }
 
 #[derive(Debug)]
 pub enum NotifierError {
     Desktop(DesktopError),
     Email(MailerError),
+    Webhook(WebhookError),
+    Slack(SlackError),
+    Discord(DiscordError),
+    Teams(TeamsError),
+    /// Several dispatchers failed to send the same notification.
+    Several(Vec<NotifierError>),
 }
 
 trait Dispatcher {
     fn dispatch_notification(
         &mut self,
+        event: &Event,
         info: NotificationInfo,
     ) -> Result<(), NotifierError>;
 }
@@ -43,33 +253,105 @@ impl Notifier {
         config: Config,
         credentials: Credentials,
     ) -> Result<Self, NotifierError> {
+        let redactor =
+            Redactor::new(&config.redactions, config.redact_defaults);
         let mut dispatchers: Vec<Box<dyn Dispatcher>> = vec![];
         if config.desktop_notifications {
             let desktop_notifier = DesktopNotifier::new()?;
             dispatchers.push(Box::new(desktop_notifier));
         }
 
+        // Email is sent before the webhooks, which may be slow to answer or
+        // time out.
         if config.email.is_some() && credentials.smtp.is_none() {
             return Err(MailerError::MissingCredentials.into());
         }
         if let (Some(_), Some(credentials)) = (&config.email, credentials.smtp)
         {
-            let mailer = Mailer::new(config, credentials)?;
+            let mailer = Mailer::new(config.clone(), credentials)?;
             dispatchers.push(Box::new(mailer));
         }
 
-        Ok(Notifier { dispatchers })
+        if let Some(ref webhook_config) = config.webhook {
+            let webhook =
+                Webhook::new(webhook_config.clone(), credentials.webhook);
+            dispatchers.push(Box::new(webhook));
+        }
+
+        if config.slack.is_some() && credentials.slack.is_none() {
+            return Err(SlackError::MissingCredentials.into());
+        }
+        if let (Some(slack_config), Some(credentials)) =
+            (&config.slack, credentials.slack)
+        {
+            let slack = Slack::new(slack_config.clone(), credentials);
+            dispatchers.push(Box::new(slack));
+        }
+
+        if config.discord.is_some() && credentials.discord.is_none() {
+            return Err(DiscordError::MissingCredentials.into());
+        }
+        if let (Some(discord_config), Some(credentials)) =
+            (&config.discord, credentials.discord)
+        {
+            let discord = Discord::new(
+                discord_config.clone(),
+                credentials,
+                config.validate,
+            )?;
+            dispatchers.push(Box::new(discord));
+        }
+
+        if config.teams.is_some() && credentials.teams.is_none() {
+            return Err(TeamsError::MissingCredentials.into());
+        }
+        if let (Some(teams_config), Some(credentials)) =
+            (&config.teams, credentials.teams)
+        {
+            let teams = Teams::new(teams_config.clone(), credentials);
+            dispatchers.push(Box::new(teams));
+        }
+
+        Ok(Notifier {
+            dispatchers,
+            redactor,
+        })
     }
 
+    /// Sends the event to every dispatcher, even if some of them fail.
     pub fn notify(&mut self, event: Event) -> Result<(), NotifierError> {
-        let info = NotificationInfo::from(event);
+        let event = self.redacted(event);
+        let info = notification_info(event.clone());
+        let mut errors = vec![];
         for dispatcher in &mut self.dispatchers {
-            dispatcher.dispatch_notification(info.clone())?;
+            if let Err(err) =
+                dispatcher.dispatch_notification(&event, info.clone())
+            {
+                errors.push(err);
+            }
+        }
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(NotifierError::Several(errors)),
         }
-        Ok(())
+    }
+
+    /// Returns the event with secrets redacted, as every dispatcher sees it.
+    fn redacted(&self, mut event: Event) -> Event {
+        self.redactor.redact_event(&mut event);
+        event
     }
 }
 
+/// Describes the event the way every dispatcher sends it to people.
+fn notification_info(event: Event) -> NotificationInfo {
+    // Events nested in others share their context, so it is added once for
+    // the whole event.
+    let context = event.context().clone();
+    NotificationInfo::from(event).with_context(context)
+}
+
 impl error::Error for NotifierError {}
 
 impl fmt::Display for NotifierError {
@@ -77,6 +359,15 @@ impl fmt::Display for NotifierError {
         match *self {
             NotifierError::Desktop(ref err) => err.fmt(formatter),
             NotifierError::Email(ref err) => err.fmt(formatter),
+            NotifierError::Webhook(ref err) => err.fmt(formatter),
+            NotifierError::Slack(ref err) => err.fmt(formatter),
+            NotifierError::Discord(ref err) => err.fmt(formatter),
+            NotifierError::Teams(ref err) => err.fmt(formatter),
+            NotifierError::Several(ref errors) => {
+                let errors: Vec<String> =
+                    errors.iter().map(ToString::to_string).collect();
+                write!(formatter, "{}", errors.join("\n"))
+            },
         }
     }
 }
@@ -93,12 +384,43 @@ impl From<MailerError> for NotifierError {
     }
 }
 
+impl From<WebhookError> for NotifierError {
+    fn from(err: WebhookError) -> Self {
+        NotifierError::Webhook(err)
+    }
+}
+
+impl From<SlackError> for NotifierError {
+    fn from(err: SlackError) -> Self {
+        NotifierError::Slack(err)
+    }
+}
+
+impl From<DiscordError> for NotifierError {
+    fn from(err: DiscordError) -> Self {
+        NotifierError::Discord(err)
+    }
+}
+
+impl From<TeamsError> for NotifierError {
+    fn from(err: TeamsError) -> Self {
+        NotifierError::Teams(err)
+    }
+}
+
 #[cfg(test)]
-mod test {
+pub mod test {
     use super::*;
-    use crate::config::LATEST_CONFIG_VERSION;
+    use crate::context::test::CONTEXT;
     use crate::exit_code::SUCCESS;
+    use crate::notifications::discord::{DiscordConfig, DiscordCredentials};
     use crate::notifications::email::{EmailConfig, SmtpCredentials};
+    use crate::notifications::slack::{SlackConfig, SlackCredentials};
+    use crate::notifications::teams::{TeamsConfig, TeamsCredentials};
+    use crate::notifications::webhook::test::receive_one;
+    use crate::notifications::webhook::{
+        Payload, WebhookConfig, WebhookCredentials, DEFAULT_WEBHOOK_TIMEOUT,
+    };
     use lazy_static::lazy_static;
     use matches::assert_matches;
 
@@ -106,7 +428,12 @@ mod test {
         pub static ref EVENT_FINISHED: Event = Event::Finished {
             incantation: Incantation::new("foo", vec!["bar", "baz"]),
             exit_code: SUCCESS,
+            succeeded: true,
             elapsed_time: Duration::from_secs(2),
+            output: None,
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
         };
     }
 
@@ -119,15 +446,113 @@ mod test {
     #[test]
     fn dispatches_notifications() {
         let config = Config::default();
-        let credentials = Credentials {
-            version: LATEST_CONFIG_VERSION.clone(),
-            smtp: None,
-        };
+        let credentials = Credentials::default();
         let mut notifier = Notifier::new(config, credentials).unwrap();
         let result = notifier.notify(EVENT_FINISHED.clone());
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn keeps_secrets_out_of_notifications() {
+        let config = Config {
+            desktop_notifications: false,
+            ..Default::default()
+        };
+        let credentials = Credentials::default();
+        let notifier = Notifier::new(config, credentials).unwrap();
+        let secret = "hunter2";
+        let incantation = Incantation::new(
+            "curl",
+            vec![
+                "-H",
+                "Authorization: Bearer hunter2",
+                "https://example.com/?token=hunter2",
+            ],
+        );
+        let timed_out = Event::TimedOut {
+            incantation: incantation.clone(),
+            timeout: Duration::from_secs(60),
+            output: Some(CapturedOutput {
+                stdout: vec!["> Authorization: Bearer hunter2".to_string()],
+                stderr: vec!["--password=hunter2 is wrong".to_string()],
+            }),
+            resource_usage: None,
+            stragglers: vec![Straggler {
+                pid: 1234,
+                incantation: Incantation::new(
+                    "cat",
+                    vec!["--password", secret],
+                ),
+            }],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let retrying = Event::Retrying {
+            failure: Box::new(timed_out.clone()),
+            retry: 1,
+            retries: 3,
+            delay: Duration::from_secs(1),
+        };
+        let still_running = Event::StillRunning {
+            incantation,
+            elapsed_time: Duration::from_secs(1),
+            output: None,
+            resource_usage: None,
+            context: CONTEXT.clone(),
+        };
+        // The brief and details make up the whole of every notification,
+        // including the subject and bodies of emails, and webhooks are sent
+        // the redacted event.
+        for event in [timed_out, retrying, still_running].iter().cloned() {
+            let event = notifier.redacted(event);
+            let payload =
+                Payload::new(&event, notification_info(event.clone()));
+            let json = serde_json::to_string(&payload).unwrap();
+            assert!(!json.contains(secret), "{}", json);
+            let info = notification_info(event);
+            assert!(!info.brief.contains(secret), "{}", info.brief);
+            assert!(!info.details.contains(secret), "{}", info.details);
+            assert!(
+                !info.html_details.contains(secret),
+                "{}",
+                info.html_details
+            );
+        }
+    }
+
+    #[test]
+    fn finds_output_of_retried_failure() {
+        let output = CapturedOutput {
+            stdout: vec!["building".to_string()],
+            stderr: vec![],
+        };
+        let event = Event::Retrying {
+            failure: Box::new(Event::StillRunning {
+                incantation: Incantation::new("make", vec![]),
+                elapsed_time: Duration::from_secs(1),
+                output: Some(output.clone()),
+                resource_usage: None,
+                context: CONTEXT.clone(),
+            }),
+            retry: 1,
+            retries: 3,
+            delay: Duration::from_secs(1),
+        };
+        assert_eq!(event.output(), Some(&output));
+        assert_eq!(EVENT_FINISHED.output(), None);
+    }
+
+    #[test]
+    fn finds_context_of_retried_failure() {
+        let event = Event::Retrying {
+            failure: Box::new(EVENT_FINISHED.clone()),
+            retry: 1,
+            retries: 3,
+            delay: Duration::from_secs(1),
+        };
+        assert_eq!(event.context(), &*CONTEXT);
+    }
+
     #[test]
     fn adds_mailer_to_dispatchers() {
         let config = Config {
@@ -138,18 +563,164 @@ mod test {
             ..Default::default()
         };
         let credentials = Credentials {
-            version: LATEST_CONFIG_VERSION.clone(),
             smtp: Some(SmtpCredentials::new(
                 "example.com",
                 1234,
                 "username",
                 "password",
             )),
+            ..Default::default()
         };
         let notifier = Notifier::new(config, credentials);
         assert!(notifier.is_ok());
     }
 
+    #[test]
+    fn adds_webhook_to_dispatchers() {
+        let (url, receiver) = receive_one(200);
+        let config = Config {
+            desktop_notifications: false,
+            webhook: Some(WebhookConfig {
+                url,
+                method: "POST".to_string(),
+                headers: Default::default(),
+                timeout: DEFAULT_WEBHOOK_TIMEOUT,
+            }),
+            ..Default::default()
+        };
+        let credentials = Credentials {
+            webhook: Some(WebhookCredentials {
+                headers: Some(
+                    vec![("Authorization".to_string(), "Bearer t0k".into())]
+                        .into_iter()
+                        .collect(),
+                ),
+                secret: None,
+            }),
+            ..Default::default()
+        };
+        let mut notifier = Notifier::new(config, credentials).unwrap();
+        notifier.notify(EVENT_FINISHED.clone()).unwrap();
+        let received = receiver.join().unwrap();
+        assert_eq!(received.headers["authorization"], "Bearer t0k");
+        assert!(received.body.contains("\"event\":\"finished\""));
+    }
+
+    #[test]
+    fn adds_slack_to_dispatchers() {
+        let (url, receiver) = receive_one(200);
+        let config = Config {
+            desktop_notifications: false,
+            slack: Some(SlackConfig::default()),
+            ..Default::default()
+        };
+        let credentials = Credentials {
+            slack: Some(SlackCredentials { webhook_url: url }),
+            ..Default::default()
+        };
+        let mut notifier = Notifier::new(config, credentials).unwrap();
+        notifier.notify(EVENT_FINISHED.clone()).unwrap();
+        let received = receiver.join().unwrap();
+        assert!(received.body.contains("\"blocks\""));
+        let config = Config {
+            desktop_notifications: false,
+            slack: Some(SlackConfig::default()),
+            ..Default::default()
+        };
+        let credentials = Credentials::default();
+        assert_matches!(
+            Notifier::new(config, credentials),
+            Err(NotifierError::Slack(SlackError::MissingCredentials))
+        );
+    }
+
+    #[test]
+    fn dispatches_to_all_despite_errors() {
+        let (webhook_url, webhook_receiver) = receive_one(500);
+        let (slack_url, slack_receiver) = receive_one(500);
+        let (teams_url, teams_receiver) = receive_one(200);
+        let config = Config {
+            desktop_notifications: false,
+            webhook: Some(WebhookConfig {
+                url: webhook_url,
+                method: "POST".to_string(),
+                headers: Default::default(),
+                timeout: DEFAULT_WEBHOOK_TIMEOUT,
+            }),
+            slack: Some(SlackConfig::default()),
+            teams: Some(TeamsConfig::default()),
+            ..Default::default()
+        };
+        let credentials = Credentials {
+            webhook: Some(WebhookCredentials {
+                headers: None,
+                secret: None,
+            }),
+            slack: Some(SlackCredentials {
+                webhook_url: slack_url,
+            }),
+            teams: Some(TeamsCredentials {
+                webhook_url: teams_url,
+            }),
+            ..Default::default()
+        };
+        let mut notifier = Notifier::new(config, credentials).unwrap();
+        let result = notifier.notify(EVENT_FINISHED.clone());
+        webhook_receiver.join().unwrap();
+        slack_receiver.join().unwrap();
+        teams_receiver.join().unwrap();
+        match result {
+            Err(NotifierError::Several(errors)) => {
+                assert_matches!(
+                    errors[..],
+                    [NotifierError::Webhook(_), NotifierError::Slack(_)]
+                );
+                let err = NotifierError::Several(errors);
+                assert_eq!(err.to_string().lines().count(), 2);
+            },
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn adds_discord_to_dispatchers() {
+        let config = Config {
+            desktop_notifications: false,
+            discord: Some(DiscordConfig::default()),
+            validate: false,
+            ..Default::default()
+        };
+        let mut credentials = Credentials::default();
+        assert_matches!(
+            Notifier::new(config.clone(), credentials.clone()),
+            Err(NotifierError::Discord(DiscordError::MissingCredentials))
+        );
+        credentials.discord = Some(DiscordCredentials {
+            webhook_url: "https://discord.com/api/webhooks/1/t".to_string(),
+        });
+        let notifier = Notifier::new(config, credentials).unwrap();
+        assert_eq!(notifier.dispatchers.len(), 1);
+    }
+
+    #[test]
+    fn adds_teams_to_dispatchers() {
+        let config = Config {
+            desktop_notifications: false,
+            teams: Some(TeamsConfig::default()),
+            ..Default::default()
+        };
+        let mut credentials = Credentials::default();
+        assert_matches!(
+            Notifier::new(config.clone(), credentials.clone()),
+            Err(NotifierError::Teams(TeamsError::MissingCredentials))
+        );
+        credentials.teams = Some(TeamsCredentials {
+            webhook_url: "https://example.webhook.office.com/1".to_string(),
+        });
+        let notifier = Notifier::new(config, credentials).unwrap();
+        assert_eq!(notifier.dispatchers.len(), 1);
+    }
+
     #[test]
     fn returns_error_with_missing_credentials() {
         let config = Config {
@@ -159,10 +730,7 @@ mod test {
             ])),
             ..Default::default()
         };
-        let credentials = Credentials {
-            version: LATEST_CONFIG_VERSION.clone(),
-            smtp: None,
-        };
+        let credentials = Credentials::default();
         let notifier = Notifier::new(config, credentials);
         assert!(notifier.is_err());
         assert_matches!(
@@ -180,10 +748,7 @@ mod test {
         let error = NotifierError::Email(MailerError::MissingCredentials);
         let _ = format!("{:?}", error);
 
-        let credentials = Credentials {
-            version: LATEST_CONFIG_VERSION.clone(),
-            smtp: None,
-        };
+        let credentials = Credentials::default();
         let notifier = Notifier::new(Config::default(), credentials).unwrap();
         let _ = format!("{:?}", notifier);
 
@@ -1,6 +1,14 @@
+use crate::context::RunContext;
 use crate::duration_format::duration_format;
 use crate::exit_code;
+use crate::job::{StepOutcome, StepReport};
 use crate::notifications::Event;
+use crate::output::CapturedOutput;
+use crate::process_tree::Straggler;
+use crate::resource_usage::ResourceUsage;
+use crate::retry::Attempt;
+use crate::signal::signal_name;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct NotificationInfo {
@@ -9,65 +17,643 @@ pub struct NotificationInfo {
     pub html_details: String,
 }
 
+/// Describes the event without its context, which `with_context` adds, so
+/// that events nested in others do not repeat it.
 impl From<Event> for NotificationInfo {
     fn from(event: Event) -> Self {
         match event {
             Event::Finished {
                 incantation,
                 exit_code,
+                succeeded,
                 elapsed_time,
+                output,
+                resource_usage,
+                attempts,
+                ..
             } => {
-                let command = incantation.command.to_string_lossy();
-                let brief = if exit_code == exit_code::SUCCESS {
-                    format!("`{}` succeeded", command)
+                let command = incantation.name();
+                let outcome = if succeeded { "succeeded" } else { "failed" };
+                let brief = format!("`{}` {}", command, outcome);
+                // Mention the exit code unless it goes without saying.
+                let exit_code = if succeeded && exit_code == exit_code::SUCCESS
+                {
+                    String::new()
                 } else {
-                    format!("`{}` failed", command)
+                    format!(" with exit code {}", exit_code)
                 };
-                let details = if exit_code == exit_code::SUCCESS {
-                    format!(
-                        "`{}` succeeded in {}.",
-                        incantation,
-                        duration_format(&elapsed_time)
-                    )
-                } else {
-                    format!(
-                        "`{}` failed with exit code {} in {}.",
-                        incantation,
-                        exit_code,
-                        duration_format(&elapsed_time)
-                    )
-                };
-                let html_details = if exit_code == exit_code::SUCCESS {
-                    format!(
-                        "<code>{}</code> succeeded in {}.",
-                        incantation,
-                        duration_format(&elapsed_time)
+                let details = format!(
+                    "`{}` {}{} in {}.",
+                    incantation,
+                    outcome,
+                    exit_code,
+                    duration_format(&elapsed_time)
+                );
+                let html_details = format!(
+                    "<code>{}</code> {}{} in {}.",
+                    escape_html(&incantation.to_string()),
+                    outcome,
+                    exit_code,
+                    duration_format(&elapsed_time)
+                );
+                NotificationInfo {
+                    brief,
+                    details,
+                    html_details,
+                }
+                .with_attempts(attempts)
+                .with_resource_usage(resource_usage)
+                .with_output(output)
+            },
+            Event::Killed {
+                incantation,
+                signal,
+                core_dumped,
+                elapsed_time,
+                output,
+                resource_usage,
+                attempts,
+                ..
+            } => {
+                let command = incantation.name();
+                let signal = signal_name(signal);
+                let core_dumped =
+                    if core_dumped { " (core dumped)" } else { "" };
+                let brief = format!("`{}` was killed by {}", command, signal);
+                let details = format!(
+                    "`{}` was killed by {}{} after {}.",
+                    incantation,
+                    signal,
+                    core_dumped,
+                    duration_format(&elapsed_time)
+                );
+                let html_details = format!(
+                    "<code>{}</code> was killed by {}{} after {}.",
+                    escape_html(&incantation.to_string()),
+                    signal,
+                    core_dumped,
+                    duration_format(&elapsed_time)
+                );
+                NotificationInfo {
+                    brief,
+                    details,
+                    html_details,
+                }
+                .with_attempts(attempts)
+                .with_resource_usage(resource_usage)
+                .with_output(output)
+            },
+            Event::Interrupted {
+                incantation,
+                signal,
+                elapsed_time,
+                output,
+                resource_usage,
+                stragglers,
+                attempts,
+                ..
+            } => {
+                let command = incantation.name();
+                let signal = signal_name(signal);
+                let brief = format!("`{}` was interrupted", command);
+                let details = format!(
+                    "`{}` was interrupted by {} after {}.",
+                    incantation,
+                    signal,
+                    duration_format(&elapsed_time)
+                );
+                let html_details = format!(
+                    "<code>{}</code> was interrupted by {} after {}.",
+                    escape_html(&incantation.to_string()),
+                    signal,
+                    duration_format(&elapsed_time)
+                );
+                NotificationInfo {
+                    brief,
+                    details,
+                    html_details,
+                }
+                .with_stragglers(stragglers)
+                .with_attempts(attempts)
+                .with_resource_usage(resource_usage)
+                .with_output(output)
+            },
+            Event::TimedOut {
+                incantation,
+                timeout,
+                output,
+                resource_usage,
+                stragglers,
+                attempts,
+                ..
+            } => {
+                let command = incantation.name();
+                let brief = format!("`{}` timed out", command);
+                let details = format!(
+                    "`{}` timed out after {}.",
+                    incantation,
+                    duration_format(&timeout)
+                );
+                let html_details = format!(
+                    "<code>{}</code> timed out after {}.",
+                    escape_html(&incantation.to_string()),
+                    duration_format(&timeout)
+                );
+                NotificationInfo {
+                    brief,
+                    details,
+                    html_details,
+                }
+                .with_stragglers(stragglers)
+                .with_attempts(attempts)
+                .with_resource_usage(resource_usage)
+                .with_output(output)
+            },
+            Event::JobFinished {
+                steps,
+                elapsed_time,
+                failure,
+                ..
+            } => NotificationInfo::from_steps(steps, elapsed_time, failure),
+            Event::ParallelFinished {
+                commands,
+                elapsed_time,
+                ..
+            } => NotificationInfo::from_parallel(commands, elapsed_time),
+            Event::Exited {
+                incantation,
+                pid,
+                elapsed_time,
+                ..
+            } => {
+                let command = incantation.name();
+                let brief = format!("`{}` exited", command);
+                let details = format!(
+                    "`{}` (PID {}) exited after {}. Its exit code is unknown \
+                     because diditrun did not start it.",
+                    incantation,
+                    pid,
+                    duration_format(&elapsed_time)
+                );
+                let html_details = format!(
+                    "<code>{}</code> (PID {}) exited after {}. Its exit code \
+                     is unknown because diditrun did not start it.",
+                    escape_html(&incantation.to_string()),
+                    pid,
+                    duration_format(&elapsed_time)
+                );
+                NotificationInfo {
+                    brief,
+                    details,
+                    html_details,
+                }
+            },
+            Event::StillRunning {
+                incantation,
+                elapsed_time,
+                output,
+                resource_usage,
+                ..
+            } => {
+                let command = incantation.name();
+                let brief = format!("`{}` is still running", command);
+                let details = format!(
+                    "`{}` has been running for {}.",
+                    incantation,
+                    duration_format(&elapsed_time)
+                );
+                let html_details = format!(
+                    "<code>{}</code> has been running for {}.",
+                    escape_html(&incantation.to_string()),
+                    duration_format(&elapsed_time)
+                );
+                NotificationInfo {
+                    brief,
+                    details,
+                    html_details,
+                }
+                .with_resource_usage(resource_usage)
+                .with_output(output)
+            },
+            Event::Stalled {
+                incantation,
+                idle_time,
+                elapsed_time,
+                terminated,
+                output,
+                resource_usage,
+                stragglers,
+                attempts,
+                ..
+            } => {
+                let command = incantation.name();
+                let (brief, outcome) = if terminated {
+                    (
+                        format!("`{}` stalled and was terminated", command),
+                        "was terminated after writing",
                     )
                 } else {
-                    format!(
-                        "<code>{}</code> failed with exit code {} in {}.",
-                        incantation,
-                        exit_code,
-                        duration_format(&elapsed_time)
-                    )
+                    (format!("`{}` has stalled", command), "has written")
                 };
+                let stall = format!(
+                    "{} no output for {}. It ran for {}.",
+                    outcome,
+                    duration_format(&idle_time),
+                    duration_format(&elapsed_time)
+                );
+                NotificationInfo {
+                    brief,
+                    details: format!("`{}` {}", incantation, stall),
+                    html_details: format!(
+                        "<code>{}</code> {}",
+                        escape_html(&incantation.to_string()),
+                        stall
+                    ),
+                }
+                .with_stragglers(stragglers)
+                .with_attempts(attempts)
+                .with_resource_usage(resource_usage)
+                .with_output(output)
+            },
+            Event::OutputMatched {
+                incantation,
+                elapsed_time,
+                output_match,
+                ..
+            } => {
+                let command = incantation.name();
+                let brief = format!(
+                    "`{}` output matched \"{}\"",
+                    command, output_match.pattern
+                );
+                let mut lines = output_match.context;
+                lines.push(output_match.line);
+                let text = lines.join("\n");
+                let mut details = format!(
+                    "`{}` wrote a line matching \"{}\" to {} after {}:\n\n{}",
+                    incantation,
+                    output_match.pattern,
+                    output_match.stream,
+                    duration_format(&elapsed_time),
+                    text
+                );
+                let mut html_details = format!(
+                    "<code>{}</code> wrote a line matching <code>{}</code> to \
+                     {} after {}:<pre>{}</pre>",
+                    escape_html(&incantation.to_string()),
+                    escape_html(&output_match.pattern),
+                    output_match.stream,
+                    duration_format(&elapsed_time),
+                    escape_html(&text)
+                );
+                if 0 < output_match.suppressed {
+                    let suppressed = match output_match.suppressed {
+                        1 => "1 earlier match was".to_string(),
+                        count => format!("{} earlier matches were", count),
+                    };
+                    let note = format!(
+                        "{} not notified to limit the rate of notifications.",
+                        suppressed
+                    );
+                    details.push_str(&format!("\n\n{}", note));
+                    html_details.push_str(&format!("<p>{}</p>", note));
+                }
                 NotificationInfo {
                     brief,
                     details,
                     html_details,
                 }
             },
+            Event::Retrying {
+                failure,
+                retry,
+                retries,
+                delay,
+            } => {
+                let info = NotificationInfo::from(*failure);
+                let retrying = format!(
+                    "Retrying in {} (retry {} of {}).",
+                    duration_format(&delay),
+                    retry,
+                    retries
+                );
+                NotificationInfo {
+                    brief: format!("{}, retrying", info.brief),
+                    details: format!("{}\n\n{}", info.details, retrying),
+                    html_details: format!(
+                        "{}<p>{}</p>",
+                        info.html_details, retrying
+                    ),
+                }
+            },
         }
     }
 }
 
+impl NotificationInfo {
+    /// Lists every step of a job, followed by how the first failed one ended.
+    fn from_steps(
+        steps: Vec<StepReport>,
+        elapsed_time: Duration,
+        failure: Option<Box<Event>>,
+    ) -> Self {
+        let failed_step = steps.iter().position(|step| match step.outcome {
+            StepOutcome::Finished { succeeded, .. } => !succeeded,
+            StepOutcome::Skipped => false,
+        });
+        let failure = failure.map(|failure| NotificationInfo::from(*failure));
+        let brief = match (failed_step, &failure) {
+            (Some(index), Some(failure)) => format!(
+                "{} (step {} of {})",
+                failure.brief,
+                index + 1,
+                steps.len()
+            ),
+            _ if steps.len() == 1 => "1 step succeeded".to_string(),
+            _ => format!("All {} steps succeeded", steps.len()),
+        };
+        let summary = format!(
+            "Ran {} in {}:",
+            count(steps.len(), "step"),
+            duration_format(&elapsed_time)
+        );
+        let (list, table) = list_commands("Step", &steps);
+        let mut details = format!("{}\n{}", summary, list);
+        let mut html_details = format!("<p>{}</p>{}", summary, table);
+        if let (Some(index), Some(failure)) = (failed_step, failure) {
+            details.push_str(&format!(
+                "\n\nStep {}: {}",
+                index + 1,
+                failure.details
+            ));
+            html_details.push_str(&format!(
+                "<p>Step {}:</p>{}",
+                index + 1,
+                failure.html_details
+            ));
+        }
+        NotificationInfo {
+            brief,
+            details,
+            html_details,
+        }
+    }
+
+    /// Lists every command run side by side, leading with the first that
+    /// failed.
+    fn from_parallel(
+        commands: Vec<StepReport>,
+        elapsed_time: Duration,
+    ) -> Self {
+        let mut failures =
+            commands.iter().filter_map(|command| match command.outcome {
+                StepOutcome::Finished {
+                    exit_code,
+                    succeeded: false,
+                    elapsed_time,
+                } => Some((&command.incantation, exit_code, elapsed_time)),
+                _ => None,
+            });
+        let succeeded = commands
+            .iter()
+            .filter(|command| match command.outcome {
+                StepOutcome::Finished { succeeded, .. } => succeeded,
+                StepOutcome::Skipped => false,
+            })
+            .count();
+        let mut brief = format!("{}/{} succeeded", succeeded, commands.len());
+        if let Some((incantation, exit_code, elapsed_time)) = failures.next() {
+            brief.push_str(&format!(
+                ", `{}` failed with {} after {}",
+                incantation,
+                exit_code,
+                duration_format(&elapsed_time)
+            ));
+            let more_failures = failures.count();
+            if 0 < more_failures {
+                brief.push_str(&format!(" and {} more failed", more_failures));
+            }
+        }
+        let summary = format!(
+            "Ran {} in parallel in {}:",
+            count(commands.len(), "command"),
+            duration_format(&elapsed_time)
+        );
+        let (list, table) = list_commands("#", &commands);
+        NotificationInfo {
+            brief,
+            details: format!("{}\n{}", summary, list),
+            html_details: format!("<p>{}</p>{}", summary, table),
+        }
+    }
+
+    /// Appends where and when the command ran to the details.
+    pub fn with_context(mut self, context: RunContext) -> Self {
+        let lines = context.lines();
+        self.details.push_str(&format!("\n\n{}", lines.join("\n")));
+        let items = lines
+            .iter()
+            .map(|line| format!("<li>{}</li>", escape_html(line)))
+            .collect::<String>();
+        self.html_details.push_str(&format!("<ul>{}</ul>", items));
+        self
+    }
+
+    /// Lists the processes the command started that had to be killed.
+    fn with_stragglers(mut self, stragglers: Vec<Straggler>) -> Self {
+        if stragglers.is_empty() {
+            return self;
+        }
+        let heading =
+            "These processes it started were still running and were killed:";
+        let lines = stragglers
+            .iter()
+            .map(|straggler| {
+                format!("- PID {}: `{}`", straggler.pid, straggler.incantation)
+            })
+            .collect::<Vec<_>>();
+        self.details.push_str(&format!(
+            "\n\n{}\n{}",
+            heading,
+            lines.join("\n")
+        ));
+        let items = stragglers
+            .iter()
+            .map(|straggler| {
+                format!(
+                    "<li>PID {}: <code>{}</code></li>",
+                    straggler.pid,
+                    escape_html(&straggler.incantation.to_string())
+                )
+            })
+            .collect::<String>();
+        self.html_details
+            .push_str(&format!("<p>{}</p><ul>{}</ul>", heading, items));
+        self
+    }
+
+    /// Lists every attempt in the details if the command was retried.
+    fn with_attempts(mut self, attempts: Vec<Attempt>) -> Self {
+        if attempts.len() < 2 {
+            return self;
+        }
+        let attempts = attempts
+            .iter()
+            .map(|attempt| {
+                format!(
+                    "exit code {} after {}",
+                    attempt.exit_code,
+                    duration_format(&attempt.elapsed_time)
+                )
+            })
+            .collect::<Vec<_>>();
+        let lines = attempts
+            .iter()
+            .enumerate()
+            .map(|(index, attempt)| format!("{}. {}", index + 1, attempt))
+            .collect::<Vec<_>>();
+        self.details
+            .push_str(&format!("\n\nAttempts:\n{}", lines.join("\n")));
+        let items = attempts
+            .iter()
+            .map(|attempt| format!("<li>{}</li>", attempt))
+            .collect::<String>();
+        self.html_details
+            .push_str(&format!("<p>Attempts:</p><ol>{}</ol>", items));
+        self
+    }
+
+    /// Appends the resources the command used to the details.
+    fn with_resource_usage(
+        mut self,
+        resource_usage: Option<ResourceUsage>,
+    ) -> Self {
+        let resource_usage = match resource_usage {
+            Some(resource_usage) => resource_usage,
+            None => return self,
+        };
+        self.details
+            .push_str(&format!("\n\nResource usage:\n{}", resource_usage));
+        let items = resource_usage
+            .lines()
+            .iter()
+            .map(|line| format!("<li>{}</li>", escape_html(line)))
+            .collect::<String>();
+        self.html_details
+            .push_str(&format!("<p>Resource usage:</p><ul>{}</ul>", items));
+        self
+    }
+
+    /// Appends the command's captured output to the details.
+    fn with_output(mut self, output: Option<CapturedOutput>) -> Self {
+        let output = match output {
+            Some(output) => output,
+            None => return self,
+        };
+        let streams = [("stdout", output.stdout), ("stderr", output.stderr)];
+        for (name, lines) in streams.iter() {
+            if lines.is_empty() {
+                continue;
+            }
+            let heading = format!("Last lines of {}:", name);
+            let text = lines.join("\n");
+            self.details.push_str(&format!("\n\n{}\n{}", heading, text));
+            self.html_details.push_str(&format!(
+                "<p>{}</p><pre>{}</pre>",
+                heading,
+                escape_html(&text)
+            ));
+        }
+        self
+    }
+}
+
+/// Lists how each command went, numbered from 1, as text and as an HTML table
+/// whose first column is headed `number_heading`.
+fn list_commands(
+    number_heading: &str,
+    commands: &[StepReport],
+) -> (String, String) {
+    let mut lines = vec![];
+    let mut rows = vec![];
+    for (index, command) in commands.iter().enumerate() {
+        let (outcome, exit_code, duration) = match command.outcome {
+            StepOutcome::Finished {
+                exit_code,
+                succeeded,
+                elapsed_time,
+            } => (
+                if succeeded { "succeeded" } else { "failed" },
+                exit_code.to_string(),
+                duration_format(&elapsed_time),
+            ),
+            StepOutcome::Skipped => ("skipped", String::new(), String::new()),
+        };
+        lines.push(match command.outcome {
+            StepOutcome::Finished { .. } => format!(
+                "{}. `{}` {} with exit code {} in {}",
+                index + 1,
+                command.incantation,
+                outcome,
+                exit_code,
+                duration
+            ),
+            StepOutcome::Skipped => {
+                format!("{}. `{}` {}", index + 1, command.incantation, outcome)
+            },
+        });
+        rows.push(format!(
+            "<tr><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</\
+             td><td>{}</td></tr>",
+            index + 1,
+            escape_html(&command.incantation.to_string()),
+            outcome,
+            exit_code,
+            duration
+        ));
+    }
+    let table = format!(
+        "<table><tr><th>{}</th><th>Command</th><th>Outcome</th><th>Exit \
+         code</th><th>Duration</th></tr>{}</table>",
+        number_heading,
+        rows.join("")
+    );
+    (lines.join("\n"), table)
+}
+
+/// Counts `count` of `noun`, e.g., "1 step" or "3 steps".
+fn count(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
+    use crate::context::test::CONTEXT;
     use crate::exit_code::{FAILURE, SUCCESS};
     use crate::incantation::Incantation;
+    use crate::output::Stream;
+    use crate::trigger::OutputMatch;
     use lazy_static::lazy_static;
-    use std::time::Duration;
 
     lazy_static! {
         pub static ref NOTIFICATION_INFO: NotificationInfo = NotificationInfo {
@@ -82,7 +668,12 @@ pub mod test {
         let event = Event::Finished {
             incantation: Incantation::new("foo", vec!["bar", "baz"]),
             exit_code: SUCCESS,
+            succeeded: true,
             elapsed_time: Duration::from_secs(2),
+            output: None,
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
         };
         let info: NotificationInfo = event.into();
         assert_eq!(info.brief, "`foo` succeeded");
@@ -99,7 +690,12 @@ pub mod test {
         let event = Event::Finished {
             incantation: Incantation::new("foo", vec!["bar", "baz"]),
             exit_code,
+            succeeded: false,
             elapsed_time: Duration::from_secs(2),
+            output: None,
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
         };
         let info: NotificationInfo = event.into();
         assert_eq!(info.brief, "`foo` failed");
@@ -112,4 +708,661 @@ pub mod test {
         );
         assert_eq!(info.html_details, failure_message);
     }
+
+    #[test]
+    fn creates_info_from_event_finish_with_custom_success_code() {
+        let event = Event::Finished {
+            incantation: Incantation::new("grep", vec!["foo", "bar.txt"]),
+            exit_code: 1,
+            succeeded: true,
+            elapsed_time: Duration::from_secs(2),
+            output: None,
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`grep` succeeded");
+        assert_eq!(
+            info.details,
+            "`grep foo bar.txt` succeeded with exit code 1 in 2s."
+        );
+        assert_eq!(
+            info.html_details,
+            "<code>grep foo bar.txt</code> succeeded with exit code 1 in 2s."
+        );
+    }
+
+    #[test]
+    fn creates_info_from_event_finish_with_unexpected_success_code() {
+        let event = Event::Finished {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            exit_code: SUCCESS,
+            succeeded: false,
+            elapsed_time: Duration::from_secs(2),
+            output: None,
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`foo` failed");
+        assert_eq!(
+            info.details,
+            "`foo bar baz` failed with exit code 0 in 2s."
+        );
+    }
+
+    #[test]
+    fn creates_info_from_event_killed() {
+        let event = Event::Killed {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            signal: 11,
+            core_dumped: true,
+            elapsed_time: Duration::from_secs(2),
+            output: None,
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        let signal = signal_name(11);
+        assert_eq!(info.brief, format!("`foo` was killed by {}", signal));
+        let killed_message = format!(
+            "`foo bar baz` was killed by {} (core dumped) after 2s.",
+            signal
+        );
+        assert_eq!(info.details, killed_message);
+        let killed_message = format!(
+            "<code>foo bar baz</code> was killed by {} (core dumped) after 2s.",
+            signal
+        );
+        assert_eq!(info.html_details, killed_message);
+    }
+
+    #[test]
+    fn creates_info_from_event_killed_without_core_dump() {
+        let event = Event::Killed {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            signal: 9,
+            core_dumped: false,
+            elapsed_time: Duration::from_secs(2),
+            output: None,
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        let killed_message =
+            format!("`foo bar baz` was killed by {} after 2s.", signal_name(9));
+        assert_eq!(info.details, killed_message);
+    }
+
+    #[test]
+    fn creates_info_from_event_interrupted() {
+        let event = Event::Interrupted {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            signal: 2,
+            elapsed_time: Duration::from_secs(5 * 60 + 3),
+            output: None,
+            resource_usage: None,
+            stragglers: vec![],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        let signal = signal_name(2);
+        assert_eq!(info.brief, "`foo` was interrupted");
+        let interrupted_message =
+            format!("`foo bar baz` was interrupted by {} after 5m 3s.", signal);
+        assert_eq!(info.details, interrupted_message);
+        let interrupted_message = format!(
+            "<code>foo bar baz</code> was interrupted by {} after 5m 3s.",
+            signal
+        );
+        assert_eq!(info.html_details, interrupted_message);
+    }
+
+    #[test]
+    fn creates_info_from_event_timed_out() {
+        let event = Event::TimedOut {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            timeout: Duration::from_secs(2 * 60 * 60),
+            output: None,
+            resource_usage: None,
+            stragglers: vec![],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`foo` timed out");
+        assert_eq!(info.details, "`foo bar baz` timed out after 2h 0m 0s.");
+        assert_eq!(
+            info.html_details,
+            "<code>foo bar baz</code> timed out after 2h 0m 0s."
+        );
+    }
+
+    #[test]
+    fn lists_stragglers_in_details() {
+        let event = Event::TimedOut {
+            incantation: Incantation::new("foo", vec![]),
+            timeout: Duration::from_secs(60),
+            output: None,
+            resource_usage: None,
+            stragglers: vec![Straggler {
+                pid: 1234,
+                incantation: Incantation::new("sleep", vec!["<forever>"]),
+            }],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(
+            info.details,
+            "`foo` timed out after 1m 0s.\n\nThese processes it started were \
+             still running and were killed:\n- PID 1234: `sleep '<forever>'`"
+        );
+        assert_eq!(
+            info.html_details,
+            "<code>foo</code> timed out after 1m 0s.<p>These processes it \
+             started were still running and were killed:</p><ul><li>PID 1234: \
+             <code>sleep &#39;&lt;forever&gt;&#39;</code></li></ul>"
+        );
+    }
+
+    #[test]
+    fn includes_captured_output_in_details() {
+        let event = Event::Finished {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            exit_code: FAILURE,
+            succeeded: false,
+            elapsed_time: Duration::from_secs(2),
+            output: Some(CapturedOutput {
+                stdout: vec!["one".to_string(), "two".to_string()],
+                stderr: vec!["error: <oops> & \"more\"".to_string()],
+            }),
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`foo` failed");
+        let expected_details = format!(
+            "`foo bar baz` failed with exit code {} in 2s.\n\nLast lines of \
+             stdout:\none\ntwo\n\nLast lines of stderr:\nerror: <oops> & \
+             \"more\"",
+            FAILURE
+        );
+        assert_eq!(info.details, expected_details);
+        let expected_html_details = format!(
+            "<code>foo bar baz</code> failed with exit code {} in 2s.<p>Last \
+             lines of stdout:</p><pre>one\ntwo</pre><p>Last lines of \
+             stderr:</p><pre>error: &lt;oops&gt; &amp; &quot;more&quot;</pre>",
+            FAILURE
+        );
+        assert_eq!(info.html_details, expected_html_details);
+    }
+
+    #[test]
+    fn includes_resource_usage_in_details() {
+        let event = Event::Finished {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            exit_code: SUCCESS,
+            succeeded: true,
+            elapsed_time: Duration::from_secs(2),
+            output: Some(CapturedOutput {
+                stdout: vec!["done".to_string()],
+                stderr: vec![],
+            }),
+            resource_usage: Some(ResourceUsage {
+                user_time: Duration::from_secs(1),
+                system_time: Duration::from_millis(20),
+                max_rss: 1024 * 1024,
+                block_reads: 0,
+                block_writes: 16,
+                voluntary_context_switches: 5,
+                involuntary_context_switches: 2,
+            }),
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        let expected_details = "`foo bar baz` succeeded in 2s.\n\nResource \
+                                usage:\nCPU time: 1s user, 20ms system\nMax \
+                                memory: 1.0 MiB\nBlock I/O: 0 reads, 16 \
+                                writes\nContext switches: 5 voluntary, 2 \
+                                involuntary\n\nLast lines of stdout:\ndone";
+        assert_eq!(info.details, expected_details);
+        let expected_html_details =
+            "<code>foo bar baz</code> succeeded in 2s.<p>Resource \
+             usage:</p><ul><li>CPU time: 1s user, 20ms system</li><li>Max \
+             memory: 1.0 MiB</li><li>Block I/O: 0 reads, 16 \
+             writes</li><li>Context switches: 5 voluntary, 2 \
+             involuntary</li></ul><p>Last lines of stdout:</p><pre>done</pre>";
+        assert_eq!(info.html_details, expected_html_details);
+    }
+
+    #[test]
+    fn creates_info_from_event_job_finished() {
+        let steps = vec![
+            StepReport {
+                incantation: Incantation::new("make", vec![]),
+                outcome: StepOutcome::Finished {
+                    exit_code: SUCCESS,
+                    succeeded: true,
+                    elapsed_time: Duration::from_secs(120),
+                },
+            },
+            StepReport {
+                incantation: Incantation::new("make", vec!["test"]),
+                outcome: StepOutcome::Finished {
+                    exit_code: 2,
+                    succeeded: false,
+                    elapsed_time: Duration::from_secs(3),
+                },
+            },
+            StepReport {
+                incantation: Incantation::new("./deploy.sh", vec!["<env>"]),
+                outcome: StepOutcome::Skipped,
+            },
+        ];
+        let failure = Event::Finished {
+            incantation: Incantation::new("make", vec!["test"]),
+            exit_code: 2,
+            succeeded: false,
+            elapsed_time: Duration::from_secs(3),
+            output: None,
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let event = Event::JobFinished {
+            steps,
+            elapsed_time: Duration::from_secs(123),
+            failure: Some(Box::new(failure)),
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`make` failed (step 2 of 3)");
+        assert_eq!(
+            info.details,
+            "Ran 3 steps in 2m 3s:\n1. `make` succeeded with exit code 0 in \
+             2m 0s\n2. `make test` failed with exit code 2 in 3s\n3. \
+             `./deploy.sh '<env>'` skipped\n\nStep 2: `make test` failed with \
+             exit code 2 in 3s."
+        );
+        assert_eq!(
+            info.html_details,
+            "<p>Ran 3 steps in 2m \
+             3s:</p><table><tr><th>Step</th><th>Command</th><th>Outcome</\
+             th><th>Exit \
+             code</th><th>Duration</th></tr><tr><td>1</td><td><code>make</\
+             code></td><td>succeeded</td><td>0</td><td>2m \
+             0s</td></tr><tr><td>2</td><td><code>make \
+             test</code></td><td>failed</td><td>2</td><td>3s</td></\
+             tr><tr><td>3</td><td><code>./deploy.sh \
+             &#39;&lt;env&gt;&#39;</code></td><td>skipped</td><td></td><td></\
+             td></tr></table><p>Step 2:</p><code>make test</code> failed with \
+             exit code 2 in 3s."
+        );
+    }
+
+    #[test]
+    fn creates_info_from_event_job_finished_successfully() {
+        let steps = vec![StepReport {
+            incantation: Incantation::new("make", vec![]),
+            outcome: StepOutcome::Finished {
+                exit_code: SUCCESS,
+                succeeded: true,
+                elapsed_time: Duration::from_secs(120),
+            },
+        }];
+        let event = Event::JobFinished {
+            steps,
+            elapsed_time: Duration::from_secs(120),
+            failure: None,
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "1 step succeeded");
+        assert_eq!(
+            info.details,
+            "Ran 1 step in 2m 0s:\n1. `make` succeeded with exit code 0 in 2m \
+             0s"
+        );
+    }
+
+    #[test]
+    fn creates_info_from_event_parallel_finished() {
+        let finished = |shard: &str, exit_code, seconds| StepReport {
+            incantation: Incantation::new("./shard.sh", vec![shard]),
+            outcome: StepOutcome::Finished {
+                exit_code,
+                succeeded: exit_code == SUCCESS,
+                elapsed_time: Duration::from_secs(seconds),
+            },
+        };
+        let commands = vec![
+            finished("1", SUCCESS, 60),
+            finished("2", 2, 2460),
+            finished("3", 1, 5),
+        ];
+        let event = Event::ParallelFinished {
+            commands,
+            elapsed_time: Duration::from_secs(2461),
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(
+            info.brief,
+            "1/3 succeeded, `./shard.sh 2` failed with 2 after 41m 0s and 1 \
+             more failed"
+        );
+        assert_eq!(
+            info.details,
+            "Ran 3 commands in parallel in 41m 1s:\n1. `./shard.sh 1` \
+             succeeded with exit code 0 in 1m 0s\n2. `./shard.sh 2` failed \
+             with exit code 2 in 41m 0s\n3. `./shard.sh 3` failed with exit \
+             code 1 in 5s"
+        );
+        assert!(info.html_details.starts_with(
+            "<p>Ran 3 commands in parallel in 41m \
+             1s:</p><table><tr><th>#</th><th>Command</th>"
+        ));
+    }
+
+    #[test]
+    fn creates_info_from_event_parallel_finished_successfully() {
+        let commands = vec![StepReport {
+            incantation: Incantation::new("true", vec![]),
+            outcome: StepOutcome::Finished {
+                exit_code: SUCCESS,
+                succeeded: true,
+                elapsed_time: Duration::from_secs(1),
+            },
+        }];
+        let event = Event::ParallelFinished {
+            commands,
+            elapsed_time: Duration::from_secs(1),
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "1/1 succeeded");
+    }
+
+    #[test]
+    fn creates_info_from_event_exited() {
+        let event = Event::Exited {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            pid: 1234,
+            elapsed_time: Duration::from_secs(3 * 60 * 60),
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`foo` exited");
+        assert_eq!(
+            info.details,
+            "`foo bar baz` (PID 1234) exited after 3h 0m 0s. Its exit code is \
+             unknown because diditrun did not start it."
+        );
+        assert_eq!(
+            info.html_details,
+            "<code>foo bar baz</code> (PID 1234) exited after 3h 0m 0s. Its \
+             exit code is unknown because diditrun did not start it."
+        );
+    }
+
+    #[test]
+    fn creates_info_from_event_still_running() {
+        let event = Event::StillRunning {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            elapsed_time: Duration::from_secs(6 * 60 * 60),
+            output: Some(CapturedOutput {
+                stdout: vec!["epoch 12".to_string()],
+                stderr: vec![],
+            }),
+            resource_usage: None,
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`foo` is still running");
+        assert_eq!(
+            info.details,
+            "`foo bar baz` has been running for 6h 0m 0s.\n\nLast lines of \
+             stdout:\nepoch 12"
+        );
+        assert_eq!(
+            info.html_details,
+            "<code>foo bar baz</code> has been running for 6h 0m 0s.<p>Last \
+             lines of stdout:</p><pre>epoch 12</pre>"
+        );
+    }
+
+    #[test]
+    fn creates_info_from_event_stalled() {
+        let event = Event::Stalled {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            idle_time: Duration::from_secs(20 * 60),
+            elapsed_time: Duration::from_secs(60 * 60),
+            terminated: false,
+            output: None,
+            resource_usage: None,
+            stragglers: vec![],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`foo` has stalled");
+        assert_eq!(
+            info.details,
+            "`foo bar baz` has written no output for 20m 0s. It ran for 1h 0m \
+             0s."
+        );
+        assert_eq!(
+            info.html_details,
+            "<code>foo bar baz</code> has written no output for 20m 0s. It \
+             ran for 1h 0m 0s."
+        );
+    }
+
+    #[test]
+    fn creates_info_from_event_stalled_and_terminated() {
+        let event = Event::Stalled {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            idle_time: Duration::from_secs(20 * 60),
+            elapsed_time: Duration::from_secs(60 * 60),
+            terminated: true,
+            output: None,
+            resource_usage: None,
+            stragglers: vec![],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`foo` stalled and was terminated");
+        assert_eq!(
+            info.details,
+            "`foo bar baz` was terminated after writing no output for 20m 0s. \
+             It ran for 1h 0m 0s."
+        );
+    }
+
+    #[test]
+    fn creates_info_from_event_output_matched() {
+        let event = Event::OutputMatched {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            elapsed_time: Duration::from_secs(90),
+            output_match: OutputMatch {
+                pattern: "^error:".to_string(),
+                stream: Stream::Stderr,
+                line: "error: <oops>".to_string(),
+                context: vec!["compiling".to_string()],
+                suppressed: 0,
+            },
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`foo` output matched \"^error:\"");
+        assert_eq!(
+            info.details,
+            "`foo bar baz` wrote a line matching \"^error:\" to stderr after \
+             1m 30s:\n\ncompiling\nerror: <oops>"
+        );
+        assert_eq!(
+            info.html_details,
+            "<code>foo bar baz</code> wrote a line matching \
+             <code>^error:</code> to stderr after 1m \
+             30s:<pre>compiling\nerror: &lt;oops&gt;</pre>"
+        );
+    }
+
+    #[test]
+    fn mentions_suppressed_output_matches() {
+        for &(suppressed, note) in
+            &[(1, "1 earlier match was"), (3, "3 earlier matches were")]
+        {
+            let event = Event::OutputMatched {
+                incantation: Incantation::new("foo", vec!["bar"]),
+                elapsed_time: Duration::from_secs(2),
+                output_match: OutputMatch {
+                    pattern: "NaN".to_string(),
+                    stream: Stream::Stdout,
+                    line: "loss: NaN".to_string(),
+                    context: vec![],
+                    suppressed,
+                },
+                context: CONTEXT.clone(),
+            };
+            let info: NotificationInfo = event.into();
+            let note = format!(
+                "{} not notified to limit the rate of notifications.",
+                note
+            );
+            assert!(info.details.ends_with(&format!("\n\n{}", note)));
+            assert!(info.html_details.ends_with(&format!("<p>{}</p>", note)));
+        }
+    }
+
+    #[test]
+    fn lists_attempts_of_retried_command() {
+        let attempts = vec![
+            Attempt {
+                exit_code: 1,
+                elapsed_time: Duration::from_secs(2),
+            },
+            Attempt {
+                exit_code: 124,
+                elapsed_time: Duration::from_secs(60),
+            },
+            Attempt {
+                exit_code: SUCCESS,
+                elapsed_time: Duration::from_secs(3),
+            },
+        ];
+        let event = Event::Finished {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            exit_code: SUCCESS,
+            succeeded: true,
+            elapsed_time: Duration::from_secs(3),
+            output: None,
+            resource_usage: None,
+            attempts,
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`foo` succeeded");
+        let expected_details =
+            "`foo bar baz` succeeded in 3s.\n\nAttempts:\n1. exit code 1 \
+             after 2s\n2. exit code 124 after 1m 0s\n3. exit code 0 after 3s";
+        assert_eq!(info.details, expected_details);
+        let expected_html_details = "<code>foo bar baz</code> succeeded in \
+                                     3s.<p>Attempts:</p><ol><li>exit code 1 \
+                                     after 2s</li><li>exit code 124 after 1m \
+                                     0s</li><li>exit code 0 after 3s</li></ol>";
+        assert_eq!(info.html_details, expected_html_details);
+    }
+
+    #[test]
+    fn creates_info_from_event_retrying() {
+        let failure = Event::Finished {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            exit_code: FAILURE,
+            succeeded: false,
+            elapsed_time: Duration::from_secs(2),
+            output: None,
+            resource_usage: None,
+            attempts: vec![Attempt {
+                exit_code: FAILURE,
+                elapsed_time: Duration::from_secs(2),
+            }],
+            context: CONTEXT.clone(),
+        };
+        let event = Event::Retrying {
+            failure: Box::new(failure),
+            retry: 1,
+            retries: 3,
+            delay: Duration::from_secs(30),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`foo` failed, retrying");
+        let expected_details = format!(
+            "`foo bar baz` failed with exit code {} in 2s.\n\nRetrying in 30s \
+             (retry 1 of 3).",
+            FAILURE
+        );
+        assert_eq!(info.details, expected_details);
+        let expected_html_details = format!(
+            "<code>foo bar baz</code> failed with exit code {} in \
+             2s.<p>Retrying in 30s (retry 1 of 3).</p>",
+            FAILURE
+        );
+        assert_eq!(info.html_details, expected_html_details);
+    }
+
+    #[test]
+    fn omits_empty_captured_output() {
+        let event = Event::TimedOut {
+            incantation: Incantation::new("foo", vec!["bar", "baz"]),
+            timeout: Duration::from_secs(2),
+            output: Some(CapturedOutput::default()),
+            resource_usage: None,
+            stragglers: vec![],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.details, "`foo bar baz` timed out after 2s.");
+    }
+
+    #[test]
+    fn names_shell_command_by_its_command_line() {
+        let event = Event::TimedOut {
+            incantation: Incantation::new("make", vec!["|", "tee", "log"])
+                .through_shell("bash"),
+            timeout: Duration::from_secs(2),
+            output: None,
+            resource_usage: None,
+            stragglers: vec![],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info: NotificationInfo = event.into();
+        assert_eq!(info.brief, "`make | tee log` timed out");
+        assert_eq!(info.details, "`make | tee log` timed out after 2s.");
+    }
+
+    #[test]
+    fn appends_context_to_details() {
+        let info = NOTIFICATION_INFO.clone().with_context(CONTEXT.clone());
+        let lines = CONTEXT.lines();
+        assert_eq!(
+            info.details,
+            format!("Notification details\n\n{}", lines.join("\n"))
+        );
+        assert!(info.html_details.ends_with(
+            "<li>Host: build-3</li><li>User: ci</li><li>Directory: \
+             /home/ci/project</li><li>diditrun PID: 1234</li></ul>"
+        ));
+    }
 }
@@ -0,0 +1,383 @@
+use crate::notifications::chat::{self, FENCE};
+use crate::notifications::webhook::Payload;
+use crate::notifications::{
+    Dispatcher, Event, NotificationInfo, NotifierError,
+};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use std::error;
+use std::fmt;
+
+/// The most characters Slack shows in the text of a section.
+const MAX_TEXT_LENGTH: usize = 3000;
+
+/// The most characters shown in a code block, leaving room in its section for
+/// the fences and a title.
+const MAX_CODE_LENGTH: usize = MAX_TEXT_LENGTH - 100;
+
+/// The most characters of the brief shown in the first section, leaving room
+/// for the status emoji and the bold markers.
+const MAX_BRIEF_LENGTH: usize = MAX_TEXT_LENGTH - 100;
+
+/// Overrides for where and as whom messages are posted, which only webhooks
+/// of legacy Slack apps honor.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct SlackConfig {
+    pub channel: Option<String>,
+    pub username: Option<String>,
+    pub icon_emoji: Option<String>,
+}
+
+/// The incoming webhook URL, which is enough to post to the channel and so is
+/// kept secret.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct SlackCredentials {
+    pub webhook_url: String,
+}
+
+pub struct Slack {
+    agent: ureq::Agent,
+    config: SlackConfig,
+    webhook_url: String,
+}
+
+#[derive(Debug)]
+pub enum SlackError {
+    MissingCredentials,
+    Http(Box<ureq::Error>),
+}
+
+impl Slack {
+    pub fn new(config: SlackConfig, credentials: SlackCredentials) -> Self {
+        Slack {
+            agent: chat::agent(),
+            config,
+            webhook_url: credentials.webhook_url,
+        }
+    }
+
+    /// Builds the message for the event as Block Kit blocks, along with the
+    /// brief as the text shown where blocks are not, e.g., in notifications.
+    fn message(&self, event: &Event, info: NotificationInfo) -> Value {
+        let brief = info.brief.clone();
+        let payload = Payload::new(event, info);
+        let mut blocks =
+            vec![section(&headline(status_emoji(&payload), &brief))];
+        let command_lines = payload.command_lines();
+        if !command_lines.is_empty() {
+            blocks.push(section(&code_block(&command_lines.join("\n"))));
+        }
+        let fields: Vec<_> = chat::facts(event, &payload)
+            .into_iter()
+            .map(|(name, value)| field(name, &value))
+            .collect();
+        blocks.push(json!({ "type": "section", "fields": fields }));
+        for (name, text) in chat::output_streams(event) {
+            let code = code_block(&text);
+            blocks.push(section(&format!("*{}*\n{}", name, code)));
+        }
+        let mut message = json!({ "text": brief, "blocks": blocks });
+        let overrides = [
+            ("channel", &self.config.channel),
+            ("username", &self.config.username),
+            ("icon_emoji", &self.config.icon_emoji),
+        ];
+        for &(name, value) in &overrides {
+            if let Some(ref value) = *value {
+                message[name] = json!(value);
+            }
+        }
+        message
+    }
+}
+
+impl Dispatcher for Slack {
+    fn dispatch_notification(
+        &mut self,
+        event: &Event,
+        info: NotificationInfo,
+    ) -> Result<(), NotifierError> {
+        let message = self.message(event, info);
+        chat::post_json(&self.agent, &self.webhook_url, &message)
+            .map_err(SlackError::Http)?;
+        Ok(())
+    }
+}
+
+fn status_emoji(payload: &Payload) -> &'static str {
+    match (payload.event, payload.succeeded) {
+        ("retrying", _) => ":repeat:",
+        ("still_running", _) => ":hourglass_flowing_sand:",
+        ("output_matched", _) => ":mag:",
+        ("stalled", None) => ":warning:",
+        (_, Some(true)) => ":white_check_mark:",
+        (_, Some(false)) => ":x:",
+        (_, None) => ":checkered_flag:",
+    }
+}
+
+fn section(text: &str) -> Value {
+    json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": text },
+    })
+}
+
+/// Shows the brief in bold after the emoji, keeping as much of its start as
+/// fits once escaped.
+fn headline(emoji: &str, brief: &str) -> String {
+    let head = chat::head_by_width(brief, MAX_BRIEF_LENGTH, escaped_length);
+    let mut text = escape(head);
+    if head.len() < brief.len() {
+        text.push('…');
+    }
+    format!("{} *{}*", emoji, text)
+}
+
+fn field(name: &str, value: &str) -> Value {
+    let text = format!("*{}*\n{}", name, escape(value));
+    json!({ "type": "mrkdwn", "text": text })
+}
+
+/// Puts as much of the end of `text` in a code block as fits once escaped.
+fn code_block(text: &str) -> String {
+    let text = chat::break_fences(text);
+    let text = chat::tail_by_width(&text, MAX_CODE_LENGTH, escaped_length);
+    format!("{}\n{}\n{}", FENCE, escape(text), FENCE)
+}
+
+/// Escapes the characters Slack reads as markup in message text.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escaped_length(character: char) -> usize {
+    match character {
+        '&' => "&amp;".len(),
+        '<' | '>' => "&lt;".len(),
+        _ => 1,
+    }
+}
+
+impl error::Error for SlackError {}
+
+impl fmt::Display for SlackError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SlackError::MissingCredentials => write!(
+                formatter,
+                "Cannot post to Slack without a webhook_url under [slack] in \
+                 the credentials file."
+            ),
+            SlackError::Http(ref err) => {
+                write!(formatter, "Slack request failed: {}", err)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::context::test::CONTEXT;
+    use crate::incantation::Incantation;
+    use crate::job::{StepOutcome, StepReport};
+    use crate::notifications::notification_info::test::NOTIFICATION_INFO;
+    use crate::notifications::test::EVENT_FINISHED;
+    use crate::notifications::webhook::test::receive_one;
+    use crate::output::CapturedOutput;
+    use matches::assert_matches;
+    use std::time::Duration;
+
+    fn slack(webhook_url: &str, config: SlackConfig) -> Slack {
+        let credentials = SlackCredentials {
+            webhook_url: webhook_url.to_string(),
+        };
+        Slack::new(config, credentials)
+    }
+
+    fn texts(message: &Value) -> Vec<String> {
+        message["blocks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|block| {
+                let fields = block["fields"].as_array().cloned();
+                vec![block["text"].clone()]
+                    .into_iter()
+                    .chain(fields.unwrap_or_default())
+            })
+            .filter_map(|text| text["text"].as_str().map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn posts_blocks_to_webhook_url() {
+        let (url, receiver) = receive_one(200);
+        let mut slack = slack(&url, SlackConfig::default());
+        slack
+            .dispatch_notification(&EVENT_FINISHED, NOTIFICATION_INFO.clone())
+            .unwrap();
+        let received = receiver.join().unwrap();
+        let message: Value = serde_json::from_str(&received.body).unwrap();
+        assert_eq!(message["text"], "Notification summary");
+        assert_eq!(message.get("channel"), None);
+        assert_eq!(message.get("username"), None);
+        assert_eq!(
+            texts(&message),
+            [
+                ":white_check_mark: *Notification summary*",
+                "```\nfoo bar baz\n```",
+                "*Exit code*\n0",
+                "*Duration*\n2s",
+                "*Host*\nbuild-3",
+            ]
+        );
+    }
+
+    #[test]
+    fn overrides_channel_and_username() {
+        let config = SlackConfig {
+            channel: Some("#builds".to_string()),
+            username: Some("diditrun".to_string()),
+            icon_emoji: Some(":robot_face:".to_string()),
+        };
+        let message = slack("https://hooks.slack.com/services/T/B/X", config)
+            .message(&EVENT_FINISHED, NOTIFICATION_INFO.clone());
+        assert_eq!(message["channel"], "#builds");
+        assert_eq!(message["username"], "diditrun");
+        assert_eq!(message["icon_emoji"], ":robot_face:");
+    }
+
+    #[test]
+    fn shows_tail_of_output() {
+        let long_line = "x".repeat(MAX_TEXT_LENGTH);
+        let event = Event::TimedOut {
+            incantation: Incantation::new("cat", vec!["<in>"]),
+            timeout: Duration::from_secs(60),
+            output: Some(CapturedOutput {
+                stdout: vec!["a & b".to_string(), long_line.clone()],
+                stderr: vec![],
+            }),
+            resource_usage: None,
+            stragglers: vec![],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let message = slack("https://hooks.slack.com/", SlackConfig::default())
+            .message(&event, NOTIFICATION_INFO.clone());
+        let texts = texts(&message);
+        assert!(texts[0].starts_with(":x: "));
+        assert_eq!(texts[1], "```\ncat '&lt;in&gt;'\n```");
+        let stdout = texts.iter().find(|text| text.starts_with("*stdout*"));
+        let stdout = stdout.unwrap();
+        assert!(stdout.chars().count() <= MAX_TEXT_LENGTH);
+        assert!(stdout.ends_with(&format!("{}\n```", long_line)[100..]));
+        assert!(!texts.iter().any(|text| text.starts_with("*stderr*")));
+    }
+
+    #[test]
+    fn cuts_brief_of_long_shell_command() {
+        let incantation = Incantation::new("make &&", vec!["echo a >b;"; 500])
+            .through_shell("sh");
+        let event = Event::Finished {
+            incantation,
+            exit_code: 0,
+            succeeded: true,
+            elapsed_time: Duration::from_secs(1),
+            output: None,
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let info = NotificationInfo::from(event.clone());
+        let message = slack("https://hooks.slack.com/", SlackConfig::default())
+            .message(&event, info);
+        let headline = &texts(&message)[0];
+        assert!(headline.chars().count() <= MAX_TEXT_LENGTH);
+        assert!(headline.starts_with(":white_check_mark: *`make &amp;&amp; "));
+        assert!(headline.ends_with(" echo a &gt;b;…*"));
+    }
+
+    #[test]
+    fn escapes_code_without_cutting_entities() {
+        let code = code_block(&format!("x{}", "&".repeat(MAX_CODE_LENGTH)));
+        let text = &code[4..code.len() - 4];
+        assert_eq!(text.len(), MAX_CODE_LENGTH / 5 * 5);
+        assert_eq!(text.replace("&amp;", ""), "");
+        let code = code_block("a\n```\nb");
+        assert_eq!(code, "```\na\n``\u{200B}`\nb\n```");
+    }
+
+    #[test]
+    fn lists_commands_of_job() {
+        let event = Event::ParallelFinished {
+            commands: vec![
+                StepReport {
+                    incantation: Incantation::new("make", vec!["a"]),
+                    outcome: StepOutcome::Skipped,
+                },
+                StepReport {
+                    incantation: Incantation::new("make", vec!["b"]),
+                    outcome: StepOutcome::Skipped,
+                },
+            ],
+            elapsed_time: Duration::from_secs(1),
+            context: CONTEXT.clone(),
+        };
+        let message = slack("https://hooks.slack.com/", SlackConfig::default())
+            .message(&event, NOTIFICATION_INFO.clone());
+        assert_eq!(texts(&message)[1], "```\nmake a\nmake b\n```");
+    }
+
+    #[test]
+    fn shows_status_emoji() {
+        let still_running = Event::StillRunning {
+            incantation: Incantation::new("make", vec![]),
+            elapsed_time: Duration::from_secs(1),
+            output: None,
+            resource_usage: None,
+            context: CONTEXT.clone(),
+        };
+        let retrying = Event::Retrying {
+            failure: Box::new(EVENT_FINISHED.clone()),
+            retry: 1,
+            retries: 3,
+            delay: Duration::from_secs(1),
+        };
+        let exited = Event::Exited {
+            incantation: Incantation::new("make", vec![]),
+            pid: 1234,
+            elapsed_time: Duration::from_secs(1),
+            context: CONTEXT.clone(),
+        };
+        let expected = [
+            (still_running, ":hourglass_flowing_sand:"),
+            (retrying, ":repeat:"),
+            (exited, ":checkered_flag:"),
+        ];
+        for (event, emoji) in &expected {
+            let payload = Payload::new(event, NOTIFICATION_INFO.clone());
+            assert_eq!(status_emoji(&payload), *emoji);
+        }
+    }
+
+    #[test]
+    fn returns_error_if_webhook_fails() {
+        let (url, receiver) = receive_one(404);
+        let mut slack = slack(&url, SlackConfig::default());
+        let result = slack
+            .dispatch_notification(&EVENT_FINISHED, NOTIFICATION_INFO.clone());
+        receiver.join().unwrap();
+        assert_matches!(result, Err(NotifierError::Slack(SlackError::Http(_))));
+    }
+
+    // Hacks to minimize coverage report errors
+    #[test]
+    fn maximize_coverage() {
+        let err = SlackError::MissingCredentials;
+        let _ = format!("{:?} {}", err, err);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::context::TIME_FORMAT;
+use crate::notifications::chat;
+use crate::notifications::webhook::Payload;
+use crate::notifications::{
+    Dispatcher, Event, NotificationInfo, NotifierError,
+};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use std::error;
+use std::fmt;
+
+const CARD_CONTENT_TYPE: &str = "application/vnd.microsoft.card.adaptive";
+const CARD_SCHEMA: &str = "http://adaptivecards.io/schemas/adaptive-card.json";
+/// The newest version of Adaptive Cards that Teams renders.
+const CARD_VERSION: &str = "1.4";
+
+/// The most characters shown of the commands or of each output stream, so
+/// that the card stays well within the size Teams accepts.
+const MAX_CODE_LENGTH: usize = 2000;
+
+/// How cards are shown.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct TeamsConfig {
+    /// Whether cards span the width of the channel rather than that of a
+    /// message.
+    pub full_width: Option<bool>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TeamsCredentials {
+    pub webhook_url: String,
+}
+
+pub struct Teams {
+    agent: ureq::Agent,
+    config: TeamsConfig,
+    webhook_url: String,
+}
+
+#[derive(Debug)]
+pub enum TeamsError {
+    MissingCredentials,
+    Http(Box<ureq::Error>),
+}
+
+impl Teams {
+    pub fn new(config: TeamsConfig, credentials: TeamsCredentials) -> Self {
+        Teams {
+            agent: chat::agent(),
+            config,
+            webhook_url: credentials.webhook_url,
+        }
+    }
+
+    /// Builds the message for the event as an Adaptive Card titled with the
+    /// brief, over a table of facts about the run.
+    fn message(&self, event: &Event, info: NotificationInfo) -> Value {
+        let brief = info.brief.clone();
+        let payload = Payload::new(event, info);
+        let color = match payload.succeeded {
+            Some(true) => "Good",
+            Some(false) => "Attention",
+            None => "Default",
+        };
+        let mut body = vec![json!({
+            "type": "TextBlock",
+            "text": brief,
+            "size": "Medium",
+            "weight": "Bolder",
+            "color": color,
+            "wrap": true,
+        })];
+        let command_lines = payload.command_lines();
+        if !command_lines.is_empty() {
+            body.push(code_block(&command_lines.join("\n")));
+        }
+        let context = event.context();
+        let mut facts = chat::facts(event, &payload);
+        let started = context.start_time.format(TIME_FORMAT).to_string();
+        facts.push(("Started", started));
+        if let Some(end_time) = context.end_time {
+            facts.push(("Ended", end_time.format(TIME_FORMAT).to_string()));
+        }
+        let facts: Vec<_> = facts
+            .into_iter()
+            .map(|(title, value)| fact(title, &value))
+            .collect();
+        body.push(json!({ "type": "FactSet", "facts": facts }));
+        for (name, text) in chat::output_streams(event) {
+            body.push(json!({
+                "type": "TextBlock",
+                "text": name,
+                "weight": "Bolder",
+            }));
+            body.push(code_block(&text));
+        }
+        let mut card = json!({
+            "$schema": CARD_SCHEMA,
+            "type": "AdaptiveCard",
+            "version": CARD_VERSION,
+            "body": body,
+        });
+        if self.config.full_width == Some(true) {
+            card["msteams"] = json!({ "width": "Full" });
+        }
+        let attachment =
+            json!({ "contentType": CARD_CONTENT_TYPE, "content": card });
+        json!({ "type": "message", "attachments": [attachment] })
+    }
+}
+
+impl Dispatcher for Teams {
+    fn dispatch_notification(
+        &mut self,
+        event: &Event,
+        info: NotificationInfo,
+    ) -> Result<(), NotifierError> {
+        let message = self.message(event, info);
+        chat::post_json(&self.agent, &self.webhook_url, &message)
+            .map_err(TeamsError::Http)?;
+        Ok(())
+    }
+}
+
+fn fact(title: &str, value: &str) -> Value {
+    json!({ "title": title, "value": value })
+}
+
+/// Shows the end of `text` in a monospace block.
+fn code_block(text: &str) -> Value {
+    json!({
+        "type": "TextBlock",
+        "text": chat::tail(text, MAX_CODE_LENGTH),
+        "fontType": "Monospace",
+        "wrap": true,
+    })
+}
+
+impl error::Error for TeamsError {}
+
+impl fmt::Display for TeamsError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TeamsError::MissingCredentials => write!(
+                formatter,
+                "Cannot post to Teams without a webhook_url under [teams] in \
+                 the credentials file."
+            ),
+            TeamsError::Http(ref err) => {
+                write!(formatter, "Teams request failed: {}", err)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::context::test::CONTEXT;
+    use crate::context::RunContext;
+    use crate::incantation::Incantation;
+    use crate::notifications::notification_info::test::NOTIFICATION_INFO;
+    use crate::notifications::test::EVENT_FINISHED;
+    use crate::output::CapturedOutput;
+    use std::time::Duration;
+
+    fn message(event: &Event, config: TeamsConfig) -> Value {
+        let credentials = TeamsCredentials {
+            webhook_url: "https://example.webhook.office.com/".to_string(),
+        };
+        Teams::new(config, credentials)
+            .message(event, NOTIFICATION_INFO.clone())
+    }
+
+    fn card(message: &Value) -> &Value {
+        assert_eq!(message["type"], "message");
+        let attachment = &message["attachments"][0];
+        assert_eq!(attachment["contentType"], CARD_CONTENT_TYPE);
+        &attachment["content"]
+    }
+
+    #[test]
+    fn builds_card_with_facts() {
+        let message = message(&EVENT_FINISHED, TeamsConfig::default());
+        let card = card(&message);
+        assert_eq!(card["type"], "AdaptiveCard");
+        assert_eq!(card["version"], CARD_VERSION);
+        assert_eq!(card.get("msteams"), None);
+        let body = card["body"].as_array().unwrap();
+        assert_eq!(body.len(), 3);
+        assert_eq!(body[0]["text"], "Notification summary");
+        assert_eq!(body[0]["color"], "Good");
+        assert_eq!(body[1]["text"], "foo bar baz");
+        assert_eq!(body[1]["fontType"], "Monospace");
+        let started = CONTEXT.start_time.format(TIME_FORMAT).to_string();
+        let ended = CONTEXT.end_time.unwrap().format(TIME_FORMAT).to_string();
+        assert_eq!(
+            body[2],
+            json!({
+                "type": "FactSet",
+                "facts": [
+                    { "title": "Exit code", "value": "0" },
+                    { "title": "Duration", "value": "2s" },
+                    { "title": "Host", "value": "build-3" },
+                    { "title": "Started", "value": started },
+                    { "title": "Ended", "value": ended },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn spans_full_width() {
+        let config = TeamsConfig {
+            full_width: Some(true),
+        };
+        let message = message(&EVENT_FINISHED, config);
+        assert_eq!(card(&message)["msteams"]["width"], "Full");
+    }
+
+    #[test]
+    fn titles_output_streams() {
+        let event = Event::TimedOut {
+            incantation: Incantation::new("make", vec![]),
+            timeout: Duration::from_secs(60),
+            output: Some(CapturedOutput {
+                stdout: vec![],
+                stderr: vec!["error".to_string()],
+            }),
+            resource_usage: None,
+            stragglers: vec![],
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        };
+        let message = message(&event, TeamsConfig::default());
+        let body = card(&message)["body"].as_array().unwrap();
+        assert_eq!(body[0]["color"], "Attention");
+        assert_eq!(body[3]["text"], "stderr");
+        assert_eq!(body[3]["weight"], "Bolder");
+        assert_eq!(body[4]["text"], "error");
+        assert_eq!(body[4]["fontType"], "Monospace");
+        assert_eq!(body.len(), 5);
+    }
+
+    #[test]
+    fn omits_end_of_unfinished_commands() {
+        let event = Event::StillRunning {
+            incantation: Incantation::new("make", vec![]),
+            elapsed_time: Duration::from_secs(1),
+            output: None,
+            resource_usage: None,
+            context: RunContext {
+                end_time: None,
+                ..CONTEXT.clone()
+            },
+        };
+        let message = message(&event, TeamsConfig::default());
+        let body = card(&message)["body"].as_array().unwrap();
+        assert_eq!(body[0]["color"], "Default");
+        let facts = body[2]["facts"].as_array().unwrap();
+        let titles: Vec<_> = facts.iter().map(|fact| &fact["title"]).collect();
+        assert_eq!(titles, ["Duration", "Host", "Started"]);
+    }
+}
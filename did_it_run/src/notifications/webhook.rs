@@ -0,0 +1,795 @@
+use crate::config::ConfigError;
+use crate::context::RunContext;
+use crate::duration_format::parse_duration;
+use crate::exit_code::ExitCode;
+use crate::incantation::Incantation;
+use crate::job::{StepOutcome, StepReport};
+use crate::notifications::{
+    Dispatcher, Event, NotificationInfo, NotifierError,
+};
+use crate::resource_usage::ResourceUsage;
+use crate::signal::Signal;
+use crate::signature::{self, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use crate::watch::Pid;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::time::Duration;
+
+/// The version of the JSON document sent to webhooks, raised whenever a
+/// field is removed or changes meaning.
+pub const PAYLOAD_VERSION: u32 = 1;
+
+/// How long to wait for the endpoint to respond unless configured.
+pub const DEFAULT_WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_METHOD: &str = "POST";
+
+/// Methods that send a body along with the request.
+const METHODS: [&str; 3] = ["POST", "PUT", "PATCH"];
+
+/// Sends a JSON document describing each event to `url`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct UserWebhookConfig {
+    pub url: String,
+    pub method: Option<String>,
+    pub headers: Option<BTreeMap<String, String>>,
+    pub timeout: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub method: String,
+    pub headers: BTreeMap<String, String>,
+    pub timeout: Duration,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct WebhookCredentials {
+    /// Headers that hold secrets, such as `Authorization`, sent along with
+    /// the configured ones.
+    pub headers: Option<BTreeMap<String, String>>,
+    /// Shared with the receiver to sign each request.
+    pub secret: Option<String>,
+}
+
+pub struct Webhook {
+    agent: ureq::Agent,
+    config: WebhookConfig,
+    secret: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum WebhookError {
+    Http(Box<ureq::Error>),
+    Json(serde_json::Error),
+}
+
+/// Describes an event for programs rather than people.
+#[derive(Debug, Serialize)]
+pub struct Payload {
+    pub version: u32,
+    /// What happened, e.g., "finished" or "timed_out".
+    pub event: &'static str,
+    pub brief: String,
+    pub details: String,
+    /// The command the event is about, unless it is about several.
+    pub command: Option<CommandPayload>,
+    pub exit_code: Option<ExitCode>,
+    pub succeeded: Option<bool>,
+    /// The signal that ended the command.
+    pub signal: Option<Signal>,
+    /// How long the command had run for, in seconds.
+    pub duration: Option<f64>,
+    /// The steps of a job or the commands run in parallel.
+    pub steps: Vec<StepPayload>,
+    pub resource_usage: Option<ResourceUsagePayload>,
+    pub context: ContextPayload,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandPayload {
+    pub program: String,
+    pub args: Vec<String>,
+    /// The command as typed into a shell.
+    pub command_line: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StepPayload {
+    pub command: CommandPayload,
+    /// Whether the step never ran because an earlier one failed.
+    pub skipped: bool,
+    pub exit_code: Option<ExitCode>,
+    pub succeeded: Option<bool>,
+    pub duration: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResourceUsagePayload {
+    /// CPU times in seconds.
+    pub user_time: f64,
+    pub system_time: f64,
+    /// Peak resident set size in bytes.
+    pub max_rss: u64,
+    pub block_reads: u64,
+    pub block_writes: u64,
+    pub voluntary_context_switches: u64,
+    pub involuntary_context_switches: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ContextPayload {
+    pub host: Option<String>,
+    pub user: Option<String>,
+    pub working_directory: Option<String>,
+    /// The PID of diditrun itself rather than of the command.
+    pub pid: Pid,
+    /// RFC 3339 timestamps.
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    /// The names of the variables set for the command.
+    pub variables: Vec<String>,
+}
+
+impl WebhookConfig {
+    pub fn from_user_webhook_config(
+        user_webhook_config: UserWebhookConfig,
+    ) -> Result<Self, ConfigError> {
+        let url = user_webhook_config.url;
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(ConfigError::InvalidWebhookUrl(url));
+        }
+        let method = user_webhook_config
+            .method
+            .map(|method| method.to_uppercase())
+            .unwrap_or_else(|| DEFAULT_METHOD.to_string());
+        if !METHODS.contains(&method.as_str()) {
+            return Err(ConfigError::InvalidWebhookMethod(method));
+        }
+        let timeout = match user_webhook_config.timeout {
+            Some(timeout) => parse_duration(&timeout)?,
+            None => DEFAULT_WEBHOOK_TIMEOUT,
+        };
+        Ok(WebhookConfig {
+            url,
+            method,
+            headers: user_webhook_config.headers.unwrap_or_default(),
+            timeout,
+        })
+    }
+}
+
+impl Webhook {
+    pub fn new(
+        mut config: WebhookConfig,
+        credentials: Option<WebhookCredentials>,
+    ) -> Self {
+        let credentials = credentials.unwrap_or_default();
+        if let Some(headers) = credentials.headers {
+            config.headers.extend(headers);
+        }
+        let agent = ureq::AgentBuilder::new().timeout(config.timeout).build();
+        Webhook {
+            agent,
+            config,
+            secret: credentials.secret,
+        }
+    }
+}
+
+impl Dispatcher for Webhook {
+    fn dispatch_notification(
+        &mut self,
+        event: &Event,
+        info: NotificationInfo,
+    ) -> Result<(), NotifierError> {
+        let payload = Payload::new(event, info);
+        let body =
+            serde_json::to_string(&payload).map_err(WebhookError::Json)?;
+        let mut request = self
+            .agent
+            .request(&self.config.method, &self.config.url)
+            .set("Content-Type", "application/json");
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value);
+        }
+        if let Some(ref secret) = self.secret {
+            let timestamp = signature::timestamp();
+            let signature = signature::sign(secret, timestamp, body.as_bytes());
+            request = request
+                .set(TIMESTAMP_HEADER, &timestamp.to_string())
+                .set(SIGNATURE_HEADER, &signature);
+        }
+        request
+            .send_string(&body)
+            .map_err(|err| WebhookError::Http(Box::new(err)))?;
+        Ok(())
+    }
+}
+
+impl Payload {
+    pub fn new(event: &Event, info: NotificationInfo) -> Self {
+        let mut payload = Payload {
+            version: PAYLOAD_VERSION,
+            event: "",
+            brief: info.brief,
+            details: info.details,
+            command: None,
+            exit_code: None,
+            succeeded: None,
+            signal: None,
+            duration: None,
+            steps: vec![],
+            resource_usage: event
+                .resource_usage()
+                .map(ResourceUsagePayload::from),
+            context: ContextPayload::from(event.context()),
+        };
+        payload.describe(event);
+        payload
+    }
+
+    /// Returns the command the event is about, or those of every step, as
+    /// typed into a shell.
+    pub fn command_lines(&self) -> Vec<&str> {
+        match self.command {
+            Some(ref command) => vec![command.command_line.as_str()],
+            None => self
+                .steps
+                .iter()
+                .map(|step| step.command.command_line.as_str())
+                .collect(),
+        }
+    }
+
+    fn describe(&mut self, event: &Event) {
+        match *event {
+            Event::Finished {
+                ref incantation,
+                exit_code,
+                succeeded,
+                elapsed_time,
+                ..
+            } => {
+                self.event = "finished";
+                self.command = Some(CommandPayload::from(incantation));
+                self.exit_code = Some(exit_code);
+                self.succeeded = Some(succeeded);
+                self.duration = Some(elapsed_time.as_secs_f64());
+            },
+            Event::Killed {
+                ref incantation,
+                signal,
+                elapsed_time,
+                ..
+            } => {
+                self.event = "killed";
+                self.command = Some(CommandPayload::from(incantation));
+                self.succeeded = Some(false);
+                self.signal = Some(signal);
+                self.duration = Some(elapsed_time.as_secs_f64());
+            },
+            Event::Interrupted {
+                ref incantation,
+                signal,
+                elapsed_time,
+                ..
+            } => {
+                self.event = "interrupted";
+                self.command = Some(CommandPayload::from(incantation));
+                self.succeeded = Some(false);
+                self.signal = Some(signal);
+                self.duration = Some(elapsed_time.as_secs_f64());
+            },
+            Event::TimedOut {
+                ref incantation,
+                timeout,
+                ..
+            } => {
+                self.event = "timed_out";
+                self.command = Some(CommandPayload::from(incantation));
+                self.succeeded = Some(false);
+                self.duration = Some(timeout.as_secs_f64());
+            },
+            Event::JobFinished {
+                ref steps,
+                elapsed_time,
+                ref failure,
+                ..
+            } => {
+                self.event = "job_finished";
+                self.succeeded = Some(failure.is_none());
+                self.duration = Some(elapsed_time.as_secs_f64());
+                self.steps = steps.iter().map(StepPayload::from).collect();
+            },
+            Event::ParallelFinished {
+                ref commands,
+                elapsed_time,
+                ..
+            } => {
+                self.event = "parallel_finished";
+                self.succeeded =
+                    Some(commands.iter().all(
+                        |command| match command.outcome {
+                            StepOutcome::Finished { succeeded, .. } => {
+                                succeeded
+                            },
+                            StepOutcome::Skipped => false,
+                        },
+                    ));
+                self.duration = Some(elapsed_time.as_secs_f64());
+                self.steps = commands.iter().map(StepPayload::from).collect();
+            },
+            Event::Exited {
+                ref incantation,
+                elapsed_time,
+                ..
+            } => {
+                self.event = "exited";
+                self.command = Some(CommandPayload::from(incantation));
+                self.duration = Some(elapsed_time.as_secs_f64());
+            },
+            Event::StillRunning {
+                ref incantation,
+                elapsed_time,
+                ..
+            } => {
+                self.event = "still_running";
+                self.command = Some(CommandPayload::from(incantation));
+                self.duration = Some(elapsed_time.as_secs_f64());
+            },
+            Event::Stalled {
+                ref incantation,
+                elapsed_time,
+                terminated,
+                ..
+            } => {
+                self.event = "stalled";
+                self.command = Some(CommandPayload::from(incantation));
+                if terminated {
+                    self.succeeded = Some(false);
+                }
+                self.duration = Some(elapsed_time.as_secs_f64());
+            },
+            Event::OutputMatched {
+                ref incantation,
+                elapsed_time,
+                ..
+            } => {
+                self.event = "output_matched";
+                self.command = Some(CommandPayload::from(incantation));
+                self.duration = Some(elapsed_time.as_secs_f64());
+            },
+            Event::Retrying { ref failure, .. } => {
+                self.describe(failure);
+                self.event = "retrying";
+            },
+        }
+    }
+}
+
+impl From<&Incantation> for CommandPayload {
+    fn from(incantation: &Incantation) -> Self {
+        CommandPayload {
+            program: incantation.command.to_string_lossy().into_owned(),
+            args: incantation
+                .args
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            command_line: incantation.to_string(),
+        }
+    }
+}
+
+impl From<&StepReport> for StepPayload {
+    fn from(step: &StepReport) -> Self {
+        let command = CommandPayload::from(&step.incantation);
+        match step.outcome {
+            StepOutcome::Finished {
+                exit_code,
+                succeeded,
+                elapsed_time,
+            } => StepPayload {
+                command,
+                skipped: false,
+                exit_code: Some(exit_code),
+                succeeded: Some(succeeded),
+                duration: Some(elapsed_time.as_secs_f64()),
+            },
+            StepOutcome::Skipped => StepPayload {
+                command,
+                skipped: true,
+                exit_code: None,
+                succeeded: None,
+                duration: None,
+            },
+        }
+    }
+}
+
+impl From<&ResourceUsage> for ResourceUsagePayload {
+    fn from(usage: &ResourceUsage) -> Self {
+        ResourceUsagePayload {
+            user_time: usage.user_time.as_secs_f64(),
+            system_time: usage.system_time.as_secs_f64(),
+            max_rss: usage.max_rss,
+            block_reads: usage.block_reads,
+            block_writes: usage.block_writes,
+            voluntary_context_switches: usage.voluntary_context_switches,
+            involuntary_context_switches: usage.involuntary_context_switches,
+        }
+    }
+}
+
+impl From<&RunContext> for ContextPayload {
+    fn from(context: &RunContext) -> Self {
+        ContextPayload {
+            host: context.hostname.clone(),
+            user: context.username.clone(),
+            working_directory: context
+                .working_directory
+                .as_ref()
+                .map(|directory| directory.to_string_lossy().into_owned()),
+            pid: context.pid,
+            started_at: context.start_time.to_rfc3339(),
+            ended_at: context.end_time.map(|end_time| end_time.to_rfc3339()),
+            variables: context.variables.clone(),
+        }
+    }
+}
+
+impl error::Error for WebhookError {}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WebhookError::Http(ref err) => {
+                write!(formatter, "Webhook request failed: {}", err)
+            },
+            WebhookError::Json(ref err) => err.fmt(formatter),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::context::test::CONTEXT;
+    use crate::exit_code::SUCCESS;
+    use crate::notifications::notification_info::test::NOTIFICATION_INFO;
+    use crate::notifications::test::EVENT_FINISHED;
+    use crate::signature::{verify, SignatureCheck, DEFAULT_TOLERANCE};
+    use matches::assert_matches;
+    use serde_json::Value;
+    use std::thread;
+    use tiny_http::{Response, Server};
+
+    /// A request as the receiving end saw it.
+    pub struct Received {
+        pub method: String,
+        pub url: String,
+        pub headers: BTreeMap<String, String>,
+        pub body: String,
+    }
+
+    /// Starts a server that answers one request with `status` and returns
+    /// its URL along with the request it received.
+    pub fn receive_one(status: u16) -> (String, thread::JoinHandle<Received>) {
+        let (url, receiver) = receive(vec![status]);
+        let receiver =
+            thread::spawn(move || receiver.join().unwrap().pop().unwrap());
+        (url, receiver)
+    }
+
+    /// Starts a server that answers a request with each of `statuses` in turn
+    /// and returns its URL along with the requests it received.
+    pub fn receive(
+        statuses: Vec<u16>,
+    ) -> (String, thread::JoinHandle<Vec<Received>>) {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks/diditrun", server.server_addr());
+        let receiver = thread::spawn(move || {
+            let mut received = vec![];
+            for status in statuses {
+                let mut request = server.recv().unwrap();
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                received.push(Received {
+                    method: request.method().to_string(),
+                    url: request.url().to_string(),
+                    headers: request
+                        .headers()
+                        .iter()
+                        .map(|header| {
+                            (
+                                header.field.to_string().to_lowercase(),
+                                header.value.to_string(),
+                            )
+                        })
+                        .collect(),
+                    body,
+                });
+                request.respond(Response::empty(status)).unwrap_or_default();
+            }
+            received
+        });
+        (url, receiver)
+    }
+
+    fn config(url: &str) -> WebhookConfig {
+        WebhookConfig {
+            url: url.to_string(),
+            method: DEFAULT_METHOD.to_string(),
+            headers: BTreeMap::new(),
+            timeout: DEFAULT_WEBHOOK_TIMEOUT,
+        }
+    }
+
+    fn payload(event: &Event) -> Value {
+        serde_json::to_value(Payload::new(event, NOTIFICATION_INFO.clone()))
+            .unwrap()
+    }
+
+    #[test]
+    fn posts_json_describing_event() {
+        let (url, receiver) = receive_one(200);
+        let mut config = config(&url);
+        config
+            .headers
+            .insert("X-Source".to_string(), "build-3".to_string());
+        let credentials = WebhookCredentials {
+            headers: Some(
+                vec![("Authorization".to_string(), "Bearer t0k".to_string())]
+                    .into_iter()
+                    .collect(),
+            ),
+            secret: None,
+        };
+        let mut webhook = Webhook::new(config, Some(credentials));
+        webhook
+            .dispatch_notification(&EVENT_FINISHED, NOTIFICATION_INFO.clone())
+            .unwrap();
+        let received = receiver.join().unwrap();
+        assert_eq!(received.method, "POST");
+        assert_eq!(received.url, "/hooks/diditrun");
+        assert_eq!(received.headers["content-type"], "application/json");
+        assert_eq!(received.headers["x-source"], "build-3");
+        assert_eq!(received.headers["authorization"], "Bearer t0k");
+        assert!(!received.headers.contains_key("x-diditrun-signature"));
+        let body: Value = serde_json::from_str(&received.body).unwrap();
+        assert_eq!(body, payload(&EVENT_FINISHED));
+    }
+
+    #[test]
+    fn signs_requests_with_secret() {
+        let (url, receiver) = receive_one(200);
+        let credentials = WebhookCredentials {
+            headers: None,
+            secret: Some("s3cret".to_string()),
+        };
+        let mut webhook = Webhook::new(config(&url), Some(credentials));
+        webhook
+            .dispatch_notification(&EVENT_FINISHED, NOTIFICATION_INFO.clone())
+            .unwrap();
+        let received = receiver.join().unwrap();
+        let check = SignatureCheck {
+            timestamp: received.headers["x-diditrun-timestamp"].clone(),
+            signature: received.headers["x-diditrun-signature"].clone(),
+            tolerance: DEFAULT_TOLERANCE,
+        };
+        let body = received.body.as_bytes();
+        let now = signature::timestamp();
+        assert!(verify("s3cret", &check, body, now).is_ok());
+        assert!(verify("wrong", &check, body, now).is_err());
+    }
+
+    #[test]
+    fn sends_configured_method() {
+        let (url, receiver) = receive_one(204);
+        let config = WebhookConfig {
+            method: "PUT".to_string(),
+            ..config(&url)
+        };
+        let mut webhook = Webhook::new(config, None);
+        webhook
+            .dispatch_notification(&EVENT_FINISHED, NOTIFICATION_INFO.clone())
+            .unwrap();
+        assert_eq!(receiver.join().unwrap().method, "PUT");
+    }
+
+    #[test]
+    fn returns_error_if_endpoint_fails() {
+        let (url, receiver) = receive_one(500);
+        let mut webhook = Webhook::new(config(&url), None);
+        let result = webhook
+            .dispatch_notification(&EVENT_FINISHED, NOTIFICATION_INFO.clone());
+        receiver.join().unwrap();
+        assert_matches!(
+            result,
+            Err(NotifierError::Webhook(WebhookError::Http(_)))
+        );
+    }
+
+    #[test]
+    fn returns_error_if_endpoint_does_not_respond() {
+        // The server never answers, so the request must time out.
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", server.server_addr());
+        let config = WebhookConfig {
+            timeout: Duration::from_millis(100),
+            ..config(&url)
+        };
+        let mut webhook = Webhook::new(config, None);
+        let result = webhook
+            .dispatch_notification(&EVENT_FINISHED, NOTIFICATION_INFO.clone());
+        assert_matches!(
+            result,
+            Err(NotifierError::Webhook(WebhookError::Http(_)))
+        );
+    }
+
+    #[test]
+    fn describes_finished_command() {
+        let body = payload(&EVENT_FINISHED);
+        assert_eq!(body["version"], PAYLOAD_VERSION);
+        assert_eq!(body["event"], "finished");
+        assert_eq!(body["brief"], "Notification summary");
+        assert_eq!(body["command"]["program"], "foo");
+        assert_eq!(body["command"]["args"], serde_json::json!(["bar", "baz"]));
+        assert_eq!(body["command"]["command_line"], "foo bar baz");
+        assert_eq!(body["exit_code"], SUCCESS);
+        assert_eq!(body["succeeded"], true);
+        assert_eq!(body["signal"], Value::Null);
+        assert_eq!(body["duration"], 2.0);
+        assert_eq!(body["steps"], serde_json::json!([]));
+        assert_eq!(body["resource_usage"], Value::Null);
+        let context = &body["context"];
+        assert_eq!(context["host"], "build-3");
+        assert_eq!(context["user"], "ci");
+        assert_eq!(context["working_directory"], "/home/ci/project");
+        assert_eq!(context["pid"], 1234);
+        assert_eq!(context["started_at"], CONTEXT.start_time.to_rfc3339());
+        assert_eq!(context["ended_at"], CONTEXT.end_time.unwrap().to_rfc3339());
+    }
+
+    #[test]
+    fn describes_resource_usage() {
+        let usage = ResourceUsage {
+            user_time: Duration::from_millis(1500),
+            system_time: Duration::from_millis(250),
+            max_rss: 12 * 1024 * 1024,
+            block_reads: 3,
+            block_writes: 8,
+            voluntary_context_switches: 42,
+            involuntary_context_switches: 7,
+        };
+        let event = Event::Retrying {
+            failure: Box::new(Event::TimedOut {
+                incantation: Incantation::new("make", vec![]),
+                timeout: Duration::from_secs(60),
+                output: None,
+                resource_usage: Some(usage),
+                stragglers: vec![],
+                attempts: vec![],
+                context: CONTEXT.clone(),
+            }),
+            retry: 1,
+            retries: 2,
+            delay: Duration::from_secs(1),
+        };
+        let body = payload(&event);
+        assert_eq!(
+            body["resource_usage"],
+            serde_json::json!({
+                "user_time": 1.5,
+                "system_time": 0.25,
+                "max_rss": 12 * 1024 * 1024,
+                "block_reads": 3,
+                "block_writes": 8,
+                "voluntary_context_switches": 42,
+                "involuntary_context_switches": 7,
+            })
+        );
+    }
+
+    #[test]
+    fn describes_steps_of_job() {
+        let event = Event::JobFinished {
+            steps: vec![
+                StepReport {
+                    incantation: Incantation::new("make", vec![]),
+                    outcome: StepOutcome::Finished {
+                        exit_code: 2,
+                        succeeded: false,
+                        elapsed_time: Duration::from_millis(1500),
+                    },
+                },
+                StepReport {
+                    incantation: Incantation::new("make", vec!["install"]),
+                    outcome: StepOutcome::Skipped,
+                },
+            ],
+            elapsed_time: Duration::from_secs(2),
+            failure: Some(Box::new(EVENT_FINISHED.clone())),
+            context: CONTEXT.clone(),
+        };
+        let body = payload(&event);
+        assert_eq!(body["event"], "job_finished");
+        assert_eq!(body["command"], Value::Null);
+        assert_eq!(body["succeeded"], false);
+        let steps = body["steps"].as_array().unwrap();
+        assert_eq!(steps[0]["command"]["command_line"], "make");
+        assert_eq!(steps[0]["skipped"], false);
+        assert_eq!(steps[0]["exit_code"], 2);
+        assert_eq!(steps[0]["duration"], 1.5);
+        assert_eq!(steps[1]["skipped"], true);
+        assert_eq!(steps[1]["exit_code"], Value::Null);
+    }
+
+    #[test]
+    fn describes_retried_failure() {
+        let event = Event::Retrying {
+            failure: Box::new(Event::Killed {
+                incantation: Incantation::new("make", vec![]),
+                signal: 9,
+                core_dumped: false,
+                elapsed_time: Duration::from_secs(3),
+                output: None,
+                resource_usage: None,
+                attempts: vec![],
+                context: CONTEXT.clone(),
+            }),
+            retry: 1,
+            retries: 3,
+            delay: Duration::from_secs(10),
+        };
+        let body = payload(&event);
+        assert_eq!(body["event"], "retrying");
+        assert_eq!(body["signal"], 9);
+        assert_eq!(body["succeeded"], false);
+        assert_eq!(body["duration"], 3.0);
+    }
+
+    #[test]
+    fn reads_user_webhook_config() {
+        let user_config = UserWebhookConfig {
+            url: "https://example.com/hook".to_string(),
+            method: Some("put".to_string()),
+            headers: None,
+            timeout: Some("30s".to_string()),
+        };
+        let config =
+            WebhookConfig::from_user_webhook_config(user_config).unwrap();
+        assert_eq!(config.method, "PUT");
+        assert_eq!(config.timeout, Duration::from_secs(30));
+        let user_config = UserWebhookConfig {
+            url: "example.com/hook".to_string(),
+            ..Default::default()
+        };
+        assert_matches!(
+            WebhookConfig::from_user_webhook_config(user_config),
+            Err(ConfigError::InvalidWebhookUrl(_))
+        );
+        let user_config = UserWebhookConfig {
+            url: "https://example.com/hook".to_string(),
+            method: Some("GET".to_string()),
+            ..Default::default()
+        };
+        assert_matches!(
+            WebhookConfig::from_user_webhook_config(user_config),
+            Err(ConfigError::InvalidWebhookMethod(_))
+        );
+    }
+
+    // Hacks to minimize coverage report errors
+    #[test]
+    fn maximize_coverage() {
+        let json_err = serde_json::from_str::<Value>("oops").unwrap_err();
+        let err = WebhookError::Json(json_err);
+        let _ = format!("{:?} {}", err, err);
+    }
+}
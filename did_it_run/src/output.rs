@@ -0,0 +1,569 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::mem;
+use std::process::Child;
+use std::str;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const READ_BUFFER_SIZE: usize = 8 * 1024;
+/// The most bytes of a line buffered before the rest is dropped, so that
+/// output without line breaks cannot use up memory.
+const MAX_LINE_BYTES: usize = 16 * 1024;
+/// The most characters of a line kept in a tail.
+const MAX_LINE_LENGTH: usize = 1000;
+/// Ends lines that were cut short.
+const TRUNCATION_MARKER: &str = "…";
+const ESCAPE: char = '\x1b';
+const BELL: char = '\x07';
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputLine {
+    pub stream: Stream,
+    pub text: String,
+}
+
+/// The last lines a command wrote to stdout and stderr.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CapturedOutput {
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+}
+
+/// Keeps the last `capacity` lines pushed to it, cut to `MAX_LINE_LENGTH`.
+#[derive(Debug)]
+pub struct OutputTail {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+/// Tees a child's piped stdout and stderr to our own while keeping the last
+/// lines of each.
+pub struct OutputCapture {
+    receiver: Receiver<OutputLine>,
+    stdout: OutputTail,
+    stderr: OutputTail,
+}
+
+/// Starts every line written through it with a prefix. Writes only whole
+/// lines so that the output of commands run side by side does not mix within
+/// a line.
+pub struct PrefixedWriter<W: Write> {
+    writer: W,
+    prefix: Vec<u8>,
+    partial_line: Vec<u8>,
+}
+
+/// Splits a byte stream into lines, buffering any incomplete line up to
+/// `MAX_LINE_BYTES`.
+#[derive(Debug, Default)]
+pub struct LineSplitter {
+    partial_line: Vec<u8>,
+    /// Whether bytes were dropped from the partial line.
+    truncated: bool,
+    /// Whether the partial line was returned to its start, so that whatever
+    /// follows overwrites it.
+    carriage_return: bool,
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stream::Stdout => write!(formatter, "stdout"),
+            Stream::Stderr => write!(formatter, "stderr"),
+        }
+    }
+}
+
+impl OutputTail {
+    pub fn new(capacity: usize) -> Self {
+        OutputTail {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(truncate_line(line));
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.iter().cloned().collect()
+    }
+}
+
+impl OutputCapture {
+    /// Starts copying the child's stdout and stderr, which must be piped.
+    pub fn start(child: &mut Child, lines: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            tee(stdout, io::stdout(), Stream::Stdout, sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tee(stderr, io::stderr(), Stream::Stderr, sender);
+        }
+        OutputCapture::new(receiver, lines)
+    }
+
+    /// Starts copying the child's stdout and stderr, which must be piped,
+    /// starting every line with `prefix`.
+    pub fn start_prefixed(
+        child: &mut Child,
+        lines: usize,
+        prefix: &str,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            let writer = PrefixedWriter::new(io::stdout(), prefix);
+            tee(stdout, writer, Stream::Stdout, sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let writer = PrefixedWriter::new(io::stderr(), prefix);
+            tee(stderr, writer, Stream::Stderr, sender);
+        }
+        OutputCapture::new(receiver, lines)
+    }
+
+    /// Starts copying everything the child writes to its terminal, which
+    /// interleaves stdout and stderr, as stdout.
+    pub fn start_terminal<R>(terminal: R, lines: usize) -> Self
+    where
+        R: Read + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        tee(terminal, io::stdout(), Stream::Stdout, sender);
+        OutputCapture::new(receiver, lines)
+    }
+
+    fn new(receiver: Receiver<OutputLine>, lines: usize) -> Self {
+        OutputCapture {
+            receiver,
+            stdout: OutputTail::new(lines),
+            stderr: OutputTail::new(lines),
+        }
+    }
+
+    /// Records output, waiting up to `timeout` for some to arrive. Returns
+    /// the lines received.
+    pub fn receive(&mut self, timeout: Duration) -> Vec<OutputLine> {
+        let mut lines = vec![];
+        match self.receiver.recv_timeout(timeout) {
+            Ok(line) => lines.push(line),
+            Err(RecvTimeoutError::Timeout) => return lines,
+            Err(RecvTimeoutError::Disconnected) => {
+                // Both streams were closed while the child kept running.
+                thread::sleep(timeout);
+                return lines;
+            },
+        }
+        while let Ok(line) = self.receiver.try_recv() {
+            lines.push(line);
+        }
+        for line in &lines {
+            self.record(line.clone());
+        }
+        lines
+    }
+
+    /// Records the output that remains once the child has exited. Gives up
+    /// after `timeout` in case a background process inherited the streams
+    /// and keeps them open.
+    pub fn finish(mut self, timeout: Duration) -> CapturedOutput {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if deadline <= now {
+                break;
+            }
+            match self.receiver.recv_timeout(deadline - now) {
+                Ok(line) => self.record(line),
+                Err(_) => break,
+            }
+        }
+        self.output()
+    }
+
+    /// Returns the output recorded so far.
+    pub fn output(&self) -> CapturedOutput {
+        CapturedOutput {
+            stdout: self.stdout.lines(),
+            stderr: self.stderr.lines(),
+        }
+    }
+
+    fn record(&mut self, line: OutputLine) {
+        match line.stream {
+            Stream::Stdout => self.stdout.push(line.text),
+            Stream::Stderr => self.stderr.push(line.text),
+        }
+    }
+}
+
+impl<W: Write> PrefixedWriter<W> {
+    pub fn new(writer: W, prefix: &str) -> Self {
+        PrefixedWriter {
+            writer,
+            prefix: prefix.as_bytes().to_vec(),
+            partial_line: vec![],
+        }
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let mut prefixed = self.prefix.clone();
+        prefixed.extend_from_slice(line);
+        self.writer.write_all(&prefixed)
+    }
+}
+
+impl<W: Write> Write for PrefixedWriter<W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        for &byte in buffer {
+            self.partial_line.push(byte);
+            if byte == b'\n' {
+                let line = mem::take(&mut self.partial_line);
+                self.write_line(&line)?;
+            }
+        }
+        Ok(buffer.len())
+    }
+
+    /// Flushes the lines written so far, holding back any incomplete line.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for PrefixedWriter<W> {
+    fn drop(&mut self) {
+        if !self.partial_line.is_empty() {
+            let mut line = mem::take(&mut self.partial_line);
+            line.push(b'\n');
+            let _ = self.write_line(&line).and_then(|_| self.writer.flush());
+        }
+    }
+}
+
+impl LineSplitter {
+    /// Returns the lines completed by `bytes`.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        let mut lines = vec![];
+        for &byte in bytes {
+            match byte {
+                b'\n' => lines.push(self.take_line()),
+                b'\r' => self.carriage_return = true,
+                _ => {
+                    // Progress bars redraw a line by returning to its start,
+                    // so only the text after the last carriage return
+                    // remains visible.
+                    if self.carriage_return {
+                        self.partial_line.clear();
+                        self.truncated = false;
+                        self.carriage_return = false;
+                    }
+                    if self.partial_line.len() < MAX_LINE_BYTES {
+                        self.partial_line.push(byte);
+                    } else {
+                        self.truncated = true;
+                    }
+                },
+            }
+        }
+        lines
+    }
+
+    /// Returns the incomplete line left over at the end of the stream.
+    pub fn finish(mut self) -> Option<String> {
+        if self.partial_line.is_empty() {
+            None
+        } else {
+            Some(self.take_line())
+        }
+    }
+
+    fn take_line(&mut self) -> String {
+        let mut line = mem::take(&mut self.partial_line);
+        self.carriage_return = false;
+        if !mem::replace(&mut self.truncated, false) {
+            return to_line(&line);
+        }
+        // Drop the character that was cut in two, if any.
+        if let Err(err) = str::from_utf8(&line) {
+            if err.error_len().is_none() {
+                line.truncate(err.valid_up_to());
+            }
+        }
+        to_line(&line) + TRUNCATION_MARKER
+    }
+}
+
+fn to_line(bytes: &[u8]) -> String {
+    strip_escape_sequences(&String::from_utf8_lossy(bytes))
+}
+
+/// Cuts `line` to `MAX_LINE_LENGTH` characters, marking it if it was longer.
+fn truncate_line(line: String) -> String {
+    match line.char_indices().nth(MAX_LINE_LENGTH) {
+        Some((end, _)) => line[..end].to_string() + TRUNCATION_MARKER,
+        None => line,
+    }
+}
+
+/// Removes terminal escape sequences, e.g., colors and cursor movement, and
+/// other control characters that would garble notifications.
+pub fn strip_escape_sequences(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            ESCAPE => match characters.next() {
+                // Control sequence, e.g., "\x1b[1;31m"
+                Some('[') => {
+                    for character in characters.by_ref() {
+                        if ('@'..='~').contains(&character) {
+                            break;
+                        }
+                    }
+                },
+                // Operating system command, e.g., "\x1b]0;title\x07"
+                Some(']') => {
+                    while let Some(character) = characters.next() {
+                        if character == BELL {
+                            break;
+                        }
+                        if character == ESCAPE {
+                            characters.next();
+                            break;
+                        }
+                    }
+                },
+                // Other escape sequences end at their first non-intermediate
+                // character, e.g., "\x1b(B"
+                Some(character) if (' '..='/').contains(&character) => {
+                    for character in characters.by_ref() {
+                        if !(' '..='/').contains(&character) {
+                            break;
+                        }
+                    }
+                },
+                _ => {},
+            },
+            '\t' => stripped.push(character),
+            _ if character.is_control() => {},
+            _ => stripped.push(character),
+        }
+    }
+    stripped
+}
+
+/// Copies everything from `reader` to `writer` unchanged while sending each
+/// line to `sender`. Keeps reading if `writer` fails so that the child never
+/// blocks on a full pipe.
+pub fn tee<R, W>(
+    mut reader: R,
+    mut writer: W,
+    stream: Stream,
+    sender: Sender<OutputLine>,
+) -> JoinHandle<()>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buffer = [0; READ_BUFFER_SIZE];
+        let mut splitter = LineSplitter::default();
+        let mut writable = true;
+        loop {
+            let count = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(count) => count,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {
+                    continue;
+                },
+                Err(_) => break,
+            };
+            if writable {
+                writable = writer
+                    .write_all(&buffer[..count])
+                    .and_then(|_| writer.flush())
+                    .is_ok();
+            }
+            for text in splitter.push(&buffer[..count]) {
+                // The receiver only goes away once the command has finished,
+                // at which point the remaining lines are no longer needed.
+                let _ = sender.send(OutputLine { stream, text });
+            }
+        }
+        if let Some(text) = splitter.finish() {
+            let _ = sender.send(OutputLine { stream, text });
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer {
+        bytes: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.bytes.lock().unwrap().extend_from_slice(buffer);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tail_keeps_last_lines() {
+        let mut tail = OutputTail::new(2);
+        for line in &["one", "two", "three"] {
+            tail.push(line.to_string());
+        }
+        assert_eq!(tail.lines(), ["two", "three"]);
+    }
+
+    #[test]
+    fn tail_with_no_capacity_keeps_nothing() {
+        let mut tail = OutputTail::new(0);
+        tail.push("one".to_string());
+        assert!(tail.lines().is_empty());
+    }
+
+    #[test]
+    fn tail_truncates_long_lines() {
+        let mut tail = OutputTail::new(1);
+        tail.push("é".repeat(MAX_LINE_LENGTH + 1));
+        let expected = "é".repeat(MAX_LINE_LENGTH) + TRUNCATION_MARKER;
+        assert_eq!(tail.lines(), [expected]);
+    }
+
+    #[test]
+    fn prefixes_whole_lines() {
+        let buffer = SharedBuffer::default();
+        {
+            let mut writer = PrefixedWriter::new(buffer.clone(), "[2] ");
+            writer.write_all(b"one\ntw").unwrap();
+            writer.flush().unwrap();
+            assert_eq!(*buffer.bytes.lock().unwrap(), b"[2] one\n");
+            writer.write_all(b"o\n\nthree").unwrap();
+        }
+        assert_eq!(
+            *buffer.bytes.lock().unwrap(),
+            b"[2] one\n[2] two\n[2] \n[2] three\n"
+        );
+    }
+
+    #[test]
+    fn splits_lines_across_chunks() {
+        let mut splitter = LineSplitter::default();
+        assert_eq!(splitter.push(b"one\ntw"), ["one"]);
+        assert!(splitter.push(b"o").is_empty());
+        assert_eq!(splitter.push(b"\r\nthree\n\nfour"), ["two", "three", ""]);
+        assert_eq!(splitter.finish(), Some("four".to_string()));
+    }
+
+    #[test]
+    fn splits_invalid_utf8_lossily() {
+        let mut splitter = LineSplitter::default();
+        assert_eq!(splitter.push(b"caf\xe9\n"), ["caf\u{FFFD}"]);
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[test]
+    fn keeps_text_after_last_carriage_return() {
+        let mut splitter = LineSplitter::default();
+        let lines = splitter.push(b"progress 50%\rprogress 100%\r\n");
+        assert_eq!(lines, ["progress 100%"]);
+    }
+
+    #[test]
+    fn truncates_lines_without_end() {
+        let mut splitter = LineSplitter::default();
+        splitter.push(b"a");
+        for _ in 0..MAX_LINE_BYTES {
+            assert!(splitter.push(b"\xc3\xa9").is_empty());
+        }
+        assert_eq!(splitter.partial_line.len(), MAX_LINE_BYTES);
+        let lines = splitter.push(b"\nnext\n");
+        // The last character no longer fits whole.
+        let expected = "a".to_string() +
+            &"é".repeat(MAX_LINE_BYTES / 2 - 1) +
+            TRUNCATION_MARKER;
+        assert_eq!(lines, [expected, "next".to_string()]);
+    }
+
+    #[test]
+    fn keeps_only_current_progress_in_memory() {
+        let mut splitter = LineSplitter::default();
+        for percent in 0..MAX_LINE_BYTES {
+            splitter.push(format!("{}%\r", percent).as_bytes());
+        }
+        assert!(splitter.partial_line.len() < 10);
+        let last = format!("{}%", MAX_LINE_BYTES - 1);
+        assert_eq!(splitter.finish(), Some(last));
+    }
+
+    #[test]
+    fn strips_escape_sequences() {
+        let text =
+            "\x1b[1;31merror\x1b[0m: \x1b]0;title\x07bad \x1b(Bthing\x08\t!";
+        assert_eq!(strip_escape_sequences(text), "error: bad thing\t!");
+        let text = "\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\";
+        assert_eq!(strip_escape_sequences(text), "link");
+    }
+
+    #[test]
+    fn strips_escape_sequences_from_lines() {
+        let mut splitter = LineSplitter::default();
+        let lines = splitter.push(b"\x1b[32m   Compiling\x1b[0m foo\n");
+        assert_eq!(lines, ["   Compiling foo"]);
+    }
+
+    #[test]
+    fn tees_output() {
+        let input = b"one\ntwo\nthree".to_vec();
+        let writer = SharedBuffer::default();
+        let (sender, receiver) = mpsc::channel();
+        let handle = tee(
+            io::Cursor::new(input.clone()),
+            writer.clone(),
+            Stream::Stderr,
+            sender,
+        );
+        assert!(handle.join().is_ok());
+        assert_eq!(*writer.bytes.lock().unwrap(), input);
+        let lines = receiver.iter().collect::<Vec<_>>();
+        let expected = ["one", "two", "three"]
+            .iter()
+            .map(|text| OutputLine {
+                stream: Stream::Stderr,
+                text: text.to_string(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lines, expected);
+    }
+}
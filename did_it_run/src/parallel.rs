@@ -0,0 +1,309 @@
+use crate::context::RunContext;
+use crate::exit_code;
+use crate::exit_code::ExitCode;
+use crate::incantation::Incantation;
+use crate::job::{StepOutcome, StepReport};
+use crate::notifications::Event;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Separates the commands given to the parallel subcommand.
+pub const SEPARATOR: &str = ":::";
+
+/// Commands to run side by side.
+#[derive(Debug)]
+pub struct Parallel {
+    pub incantations: Vec<Incantation>,
+    /// How many commands may run at once, or all of them if `None`.
+    pub jobs: Option<usize>,
+    /// Whether to also notify as each command finishes.
+    pub notify_each: bool,
+}
+
+enum Message {
+    Progress(Event),
+    Finished {
+        index: usize,
+        event: Event,
+        exit_code: ExitCode,
+        elapsed_time: Duration,
+    },
+}
+
+/// Runs every command with `run`, each on a thread of its own, and describes
+/// how they all went. `notify` is called on this thread with the events the
+/// commands report while they run.
+pub fn run<R, N>(parallel: Parallel, run: R, mut notify: N) -> (Event, ExitCode)
+where
+    R: Fn(Incantation, &str, &mut dyn FnMut(Event)) -> (Event, ExitCode)
+        + Send
+        + Sync
+        + 'static,
+    N: FnMut(Event),
+{
+    let context = RunContext::start();
+    let start = Instant::now();
+    let count = parallel.incantations.len();
+    let jobs = parallel.jobs.unwrap_or(count).min(count);
+    let queue = parallel.incantations.clone().into_iter().enumerate();
+    let queue = Arc::new(Mutex::new(queue));
+    let stopped = Arc::new(AtomicBool::new(false));
+    let run = Arc::new(run);
+    let (sender, receiver) = mpsc::channel();
+    let workers = (0..jobs)
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let stopped = Arc::clone(&stopped);
+            let run = Arc::clone(&run);
+            let sender = sender.clone();
+            thread::spawn(move || {
+                while !stopped.load(Ordering::SeqCst) {
+                    let next = queue.lock().unwrap().next();
+                    let (index, incantation) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    let start = Instant::now();
+                    let (event, exit_code) =
+                        run(incantation, &output_prefix(index), &mut |event| {
+                            let _ = sender.send(Message::Progress(event));
+                        });
+                    // An interrupt comes from the user, who wants every
+                    // command to stop.
+                    if let Event::Interrupted { .. } = event {
+                        stopped.store(true, Ordering::SeqCst);
+                    }
+                    let _ = sender.send(Message::Finished {
+                        index,
+                        event,
+                        exit_code,
+                        elapsed_time: start.elapsed(),
+                    });
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    // The messages end once every worker is done with its sender.
+    drop(sender);
+    let mut outcomes = vec![StepOutcome::Skipped; count];
+    for message in receiver {
+        match message {
+            Message::Progress(event) => notify(event),
+            Message::Finished {
+                index,
+                event,
+                exit_code,
+                elapsed_time,
+            } => {
+                let succeeded = match event {
+                    Event::Finished { succeeded, .. } => succeeded,
+                    _ => false,
+                };
+                outcomes[index] = StepOutcome::Finished {
+                    exit_code,
+                    succeeded,
+                    elapsed_time,
+                };
+                if parallel.notify_each {
+                    notify(event);
+                }
+            },
+        }
+    }
+    for worker in workers {
+        // A command whose worker panicked is reported as skipped.
+        let _ = worker.join();
+    }
+    let exit_code = outcomes
+        .iter()
+        .filter_map(|outcome| match *outcome {
+            StepOutcome::Finished {
+                exit_code,
+                succeeded: false,
+                ..
+            } => Some(exit_code),
+            _ => None,
+        })
+        .next()
+        .unwrap_or(exit_code::SUCCESS);
+    let commands = parallel
+        .incantations
+        .into_iter()
+        .zip(outcomes)
+        .map(|(incantation, outcome)| StepReport {
+            incantation,
+            outcome,
+        })
+        .collect();
+    let event = Event::ParallelFinished {
+        commands,
+        elapsed_time: start.elapsed(),
+        context: context.finish(),
+    };
+    (event, exit_code)
+}
+
+/// Starts every line of output from the command at `index`, so that lines
+/// from commands running side by side can be told apart.
+fn output_prefix(index: usize) -> String {
+    format!("[{}] ", index + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::context::test::CONTEXT;
+    use std::sync::atomic::AtomicUsize;
+
+    fn finished(incantation: Incantation, exit_code: ExitCode) -> Event {
+        Event::Finished {
+            incantation,
+            exit_code,
+            succeeded: exit_code == exit_code::SUCCESS,
+            elapsed_time: Duration::from_secs(1),
+            output: None,
+            resource_usage: None,
+            attempts: vec![],
+            context: CONTEXT.clone(),
+        }
+    }
+
+    /// Runs commands named after the exit code they finish with.
+    fn exit_with_command(
+        incantation: Incantation,
+        _: &str,
+        _: &mut dyn FnMut(Event),
+    ) -> (Event, ExitCode) {
+        let exit_code = incantation.command.to_str().unwrap().parse().unwrap();
+        (finished(incantation, exit_code), exit_code)
+    }
+
+    fn parallel(commands: &[&str], jobs: Option<usize>) -> Parallel {
+        Parallel {
+            incantations: commands
+                .iter()
+                .map(|command| Incantation::new(*command, vec![]))
+                .collect(),
+            jobs,
+            notify_each: false,
+        }
+    }
+
+    fn outcomes(event: Event) -> Vec<StepOutcome> {
+        match event {
+            Event::ParallelFinished { commands, .. } => commands
+                .into_iter()
+                .map(|command| command.outcome)
+                .collect(),
+            event => panic!("Unexpected event: {:?}", event),
+        }
+    }
+
+    fn exit_codes(event: Event) -> Vec<Option<ExitCode>> {
+        outcomes(event)
+            .into_iter()
+            .map(|outcome| match outcome {
+                StepOutcome::Finished { exit_code, .. } => Some(exit_code),
+                StepOutcome::Skipped => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_every_command_in_order() {
+        let parallel = parallel(&["0", "3", "0", "2"], None);
+        let (event, exit_code) = run(parallel, exit_with_command, |_| {});
+        assert_eq!(exit_code, 3);
+        assert_eq!(exit_codes(event), [Some(0), Some(3), Some(0), Some(2)]);
+    }
+
+    #[test]
+    fn succeeds_if_every_command_succeeds() {
+        let parallel = parallel(&["0", "0"], Some(1));
+        let (_, exit_code) = run(parallel, exit_with_command, |_| {});
+        assert_eq!(exit_code, exit_code::SUCCESS);
+    }
+
+    #[test]
+    fn limits_commands_running_at_once() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+        let run_command = {
+            let running = Arc::clone(&running);
+            let most_running = Arc::clone(&most_running);
+            move |incantation, prefix: &str, notify: &mut dyn FnMut(Event)| {
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most_running.fetch_max(now_running, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                exit_with_command(incantation, prefix, notify)
+            }
+        };
+        let parallel = parallel(&["0"; 5], Some(2));
+        let (event, _) = run(parallel, run_command, |_| {});
+        assert_eq!(exit_codes(event), [Some(0); 5]);
+        assert_eq!(most_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn prefixes_output_of_each_command() {
+        let prefixes = Arc::new(Mutex::new(vec![]));
+        let run_command = {
+            let prefixes = Arc::clone(&prefixes);
+            move |incantation, prefix: &str, notify: &mut dyn FnMut(Event)| {
+                prefixes.lock().unwrap().push(prefix.to_string());
+                exit_with_command(incantation, prefix, notify)
+            }
+        };
+        run(parallel(&["0", "0"], Some(1)), run_command, |_| {});
+        assert_eq!(*prefixes.lock().unwrap(), ["[1] ", "[2] "]);
+    }
+
+    #[test]
+    fn notifies_progress_and_each_command_if_asked() {
+        let run_command =
+            |incantation: Incantation,
+             prefix: &str,
+             notify: &mut dyn FnMut(Event)| {
+                notify(finished(incantation.clone(), exit_code::SUCCESS));
+                exit_with_command(incantation, prefix, notify)
+            };
+        let mut notified = 0;
+        run(parallel(&["0", "1"], None), run_command, |_| notified += 1);
+        assert_eq!(notified, 2);
+        let mut parallel = parallel(&["0", "1"], None);
+        parallel.notify_each = true;
+        let mut notified = 0;
+        run(parallel, run_command, |_| notified += 1);
+        assert_eq!(notified, 4);
+    }
+
+    #[test]
+    fn stops_starting_commands_once_interrupted() {
+        let run_command =
+            |incantation: Incantation, _: &str, _: &mut dyn FnMut(Event)| {
+                let exit_code = exit_code::SIGNAL_BASE + 2;
+                let event = Event::Interrupted {
+                    incantation,
+                    signal: 2,
+                    elapsed_time: Duration::from_secs(1),
+                    output: None,
+                    resource_usage: None,
+                    stragglers: vec![],
+                    attempts: vec![],
+                    context: CONTEXT.clone(),
+                };
+                (event, exit_code)
+            };
+        let parallel = parallel(&["0", "0", "0"], Some(1));
+        let (event, exit_code) = run(parallel, run_command, |_| {});
+        assert_eq!(exit_code, exit_code::SIGNAL_BASE + 2);
+        assert_eq!(
+            outcomes(event)[1..],
+            [StepOutcome::Skipped, StepOutcome::Skipped]
+        );
+    }
+}
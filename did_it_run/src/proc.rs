@@ -0,0 +1,224 @@
+use crate::incantation::Incantation;
+use crate::resource_usage::{ResourceUsage, BYTES_PER_KIBIBYTE};
+use crate::watch::Pid;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::Duration;
+
+// Positions of fields among those that follow the command name in
+// `/proc/<pid>/stat`.
+const STATE_FIELD: usize = 0;
+const PARENT_FIELD: usize = 1;
+const GROUP_FIELD: usize = 2;
+const USER_TIME_FIELD: usize = 11;
+const SYSTEM_TIME_FIELD: usize = 12;
+const START_TIME_FIELD: usize = 19;
+
+/// The unit of the block I/O counts that `getrusage` reports.
+const BLOCK_SIZE: u64 = 512;
+
+/// A process that has not exited, as listed in `/proc/<pid>/stat`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProcessStatus {
+    pub pid: Pid,
+    pub parent: Pid,
+    pub group: Pid,
+    pub start_time: u64,
+}
+
+/// Lists the processes that have not exited.
+pub fn processes() -> io::Result<Vec<ProcessStatus>> {
+    let mut processes = vec![];
+    for entry in fs::read_dir("/proc")? {
+        let pid = match entry?.file_name().to_string_lossy().parse::<Pid>() {
+            Ok(pid) => pid,
+            Err(_) => continue,
+        };
+        // Processes may exit while we look at them.
+        if let Ok(Some(process)) = status(pid) {
+            processes.push(process);
+        }
+    }
+    Ok(processes)
+}
+
+/// Returns the status of the process unless it has exited and is waiting to
+/// be reaped.
+pub fn status(pid: Pid) -> io::Result<Option<ProcessStatus>> {
+    let (state, fields) = read_stat(pid)?;
+    if state == "Z" || state == "X" {
+        return Ok(None);
+    }
+    Ok(Some(ProcessStatus {
+        pid,
+        parent: fields[PARENT_FIELD] as Pid,
+        group: fields[GROUP_FIELD] as Pid,
+        start_time: fields[START_TIME_FIELD],
+    }))
+}
+
+pub fn incantation(pid: Pid) -> io::Result<Incantation> {
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid))?;
+    if cmdline.is_empty() {
+        // Kernel threads and zombies have no command line.
+        let comm = fs::read_to_string(format!("/proc/{}/comm", pid))?;
+        return Ok(Incantation::new(comm.trim_end(), vec![]));
+    }
+    // Arguments are terminated rather than separated by NUL.
+    let cmdline = cmdline.strip_suffix(&[0]).unwrap_or(&cmdline);
+    let mut args = cmdline
+        .split(|&byte| byte == 0)
+        .map(|arg| OsStr::from_bytes(arg).to_os_string())
+        .collect::<Vec<_>>();
+    let command = args.remove(0);
+    Ok(Incantation::new(command, args))
+}
+
+/// Returns whether the process's name or program is `name`.
+pub fn has_name(pid: Pid, name: &str) -> io::Result<bool> {
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid))?;
+    if comm.trim_end() == name {
+        return Ok(true);
+    }
+    let program = incantation(pid)?.command;
+    Ok(Path::new(&program).file_name() == Some(OsStr::new(name)))
+}
+
+/// Returns the PID of the process's parent, even if it has exited.
+pub fn parent(pid: Pid) -> io::Result<Pid> {
+    Ok(stat(pid)?[PARENT_FIELD] as Pid)
+}
+
+/// Returns when the process started, in clock ticks since boot.
+pub fn start_time(pid: Pid) -> io::Result<u64> {
+    Ok(stat(pid)?[START_TIME_FIELD])
+}
+
+/// Returns the resources a running process has used so far, in the same
+/// units that `getrusage` reports once it has exited.
+pub fn resource_usage(pid: Pid) -> io::Result<ResourceUsage> {
+    let stat = stat(pid)?;
+    let status = fs::read_to_string(format!("/proc/{}/status", pid))?;
+    // Reading I/O counters needs more privileges than the rest.
+    let io =
+        fs::read_to_string(format!("/proc/{}/io", pid)).unwrap_or_default();
+    Ok(ResourceUsage {
+        user_time: ticks_duration(stat[USER_TIME_FIELD]),
+        system_time: ticks_duration(stat[SYSTEM_TIME_FIELD]),
+        max_rss: field(&status, "VmHWM") * BYTES_PER_KIBIBYTE,
+        block_reads: field(&io, "read_bytes") / BLOCK_SIZE,
+        block_writes: field(&io, "write_bytes") / BLOCK_SIZE,
+        voluntary_context_switches: field(&status, "voluntary_ctxt_switches"),
+        involuntary_context_switches: field(
+            &status,
+            "nonvoluntary_ctxt_switches",
+        ),
+    })
+}
+
+/// Returns how long the system has been up.
+pub fn uptime() -> io::Result<Duration> {
+    fs::read_to_string("/proc/uptime")?
+        .split_whitespace()
+        .next()
+        .and_then(|uptime| uptime.parse::<f64>().ok())
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| invalid_data("uptime"))
+}
+
+pub fn ticks_duration(ticks: u64) -> Duration {
+    // Safe because `sysconf` does not access memory.
+    let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    let ticks_per_second = if 0 < ticks_per_second {
+        ticks_per_second as u64
+    } else {
+        100
+    };
+    Duration::from_secs(ticks / ticks_per_second) +
+        Duration::from_secs(ticks % ticks_per_second) /
+            ticks_per_second as u32
+}
+
+/// Returns the numeric fields that follow the command name in
+/// `/proc/<pid>/stat`.
+fn stat(pid: Pid) -> io::Result<Vec<u64>> {
+    Ok(read_stat(pid)?.1)
+}
+
+/// Returns the state of the process, e.g., "R" or "Z", and the numeric
+/// fields that follow the command name in `/proc/<pid>/stat`.
+fn read_stat(pid: Pid) -> io::Result<(String, Vec<u64>)> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid))?;
+    // The command name is in parentheses and may contain anything.
+    let end = stat
+        .rfind(')')
+        .ok_or_else(|| invalid_data("process stat"))?;
+    let state = stat[end + 1..]
+        .split_whitespace()
+        .nth(STATE_FIELD)
+        .unwrap_or_default()
+        .to_string();
+    // The state is the only field that is not a number.
+    let fields = stat[end + 1..]
+        .split_whitespace()
+        .map(|field| field.parse().unwrap_or_default())
+        .collect::<Vec<_>>();
+    if fields.len() <= START_TIME_FIELD {
+        return Err(invalid_data("process stat"));
+    }
+    Ok((state, fields))
+}
+
+/// Returns the number in a `name: value` line of a procfs file, or 0 if
+/// there is none.
+fn field(file: &str, name: &str) -> u64 {
+    file.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|&(key, _)| key == name)
+        .and_then(|(_, value)| value.split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_default()
+}
+
+fn invalid_data(name: &str) -> io::Error {
+    let message = format!("Cannot parse {}.", name);
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn reads_fields() {
+        let status = "Name:\tcat\nVmHWM:\t    1024 kB\nThreads:\t1\n";
+        assert_eq!(field(status, "VmHWM"), 1024);
+        assert_eq!(field(status, "Threads"), 1);
+        assert_eq!(field(status, "VmRSS"), 0);
+    }
+
+    #[test]
+    fn lists_running_processes() {
+        let own_pid = process::id();
+        let processes = processes().unwrap();
+        let own_status = processes
+            .iter()
+            .find(|process| process.pid == own_pid)
+            .unwrap();
+        assert_eq!(own_status.start_time, start_time(own_pid).unwrap());
+        assert!(processes
+            .iter()
+            .any(|process| process.pid == own_status.parent));
+    }
+
+    #[test]
+    fn reads_resource_usage_of_running_process() {
+        let usage = resource_usage(process::id()).unwrap();
+        assert!(0 < usage.max_rss);
+        assert!(0 < usage.voluntary_context_switches);
+    }
+}
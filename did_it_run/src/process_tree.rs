@@ -0,0 +1,272 @@
+use crate::incantation::Incantation;
+#[cfg(target_os = "linux")]
+use crate::proc;
+use crate::watch::Pid;
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+use std::io;
+#[cfg(target_os = "linux")]
+use std::thread;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often the tree is checked for processes that have yet to exit.
+#[cfg(target_os = "linux")]
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long to wait for killed processes to exit and be reaped.
+#[cfg(target_os = "linux")]
+const REAP_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A process started by the command that outlived it and had to be killed.
+#[derive(Clone, Debug)]
+pub struct Straggler {
+    pub pid: Pid,
+    pub incantation: Incantation,
+}
+
+/// A command's process and every process it started, which must all stop
+/// when the command is terminated.
+#[derive(Debug)]
+pub struct ProcessTree {
+    root: Pid,
+    /// The root's descendants and when they started, to tell them apart from
+    /// processes that later reuse their PIDs.
+    #[cfg(target_os = "linux")]
+    descendants: Vec<proc::ProcessStatus>,
+}
+
+/// Makes processes orphaned from now on children of diditrun rather than of
+/// init, so that those that are killed can be reaped. Only meant for while the
+/// command is being terminated, as diditrun does not otherwise reap orphans.
+#[cfg(target_os = "linux")]
+pub fn adopt_orphans() -> io::Result<()> {
+    set_child_subreaper(1)
+}
+
+/// Leaves processes orphaned from now on to init again.
+#[cfg(target_os = "linux")]
+pub fn stop_adopting_orphans() -> io::Result<()> {
+    set_child_subreaper(0)
+}
+
+#[cfg(target_os = "linux")]
+fn set_child_subreaper(value: libc::c_ulong) -> io::Result<()> {
+    // Safe because `prctl` does not access memory for this option.
+    let result =
+        unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, value, 0, 0, 0) };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn adopt_orphans() -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn stop_adopting_orphans() -> io::Result<()> {
+    Ok(())
+}
+
+impl ProcessTree {
+    /// Finds the processes started by `root`, which must lead a process group
+    /// of its own: those still in its group and those descended from it.
+    #[cfg(target_os = "linux")]
+    pub fn find(root: Pid) -> io::Result<Self> {
+        let processes = proc::processes()?;
+        let parents = processes
+            .iter()
+            .map(|process| (process.pid, process.parent))
+            .collect::<HashMap<_, _>>();
+        let descends_from_root = |mut pid| {
+            // Bounded in case the processes changed parents while listed.
+            for _ in 0..parents.len() {
+                match parents.get(&pid) {
+                    Some(&parent) if parent == root => return true,
+                    Some(&parent) => pid = parent,
+                    None => return false,
+                }
+            }
+            false
+        };
+        let descendants = processes
+            .iter()
+            .filter(|process| process.pid != root)
+            .filter(|process| {
+                process.group == root || descends_from_root(process.pid)
+            })
+            .cloned()
+            .collect();
+        Ok(ProcessTree { root, descendants })
+    }
+
+    /// Only the root's process group can be found on this platform.
+    #[cfg(not(target_os = "linux"))]
+    pub fn find(root: Pid) -> io::Result<Self> {
+        Ok(ProcessTree { root })
+    }
+
+    /// Sends `signal` once to every process in the tree that is still
+    /// running.
+    #[cfg(unix)]
+    pub fn signal(&self, signal: libc::c_int) {
+        let root = self.root as libc::pid_t;
+        // Most of the tree is in the process group the root leads, unless
+        // the root has moved to another. Processes may exit at any point, so
+        // failing to signal them is fine.
+        // Safe because `getpgid` and `kill` do not access memory.
+        unsafe {
+            if libc::getpgid(root) != root {
+                libc::kill(root, signal);
+            }
+            libc::kill(-root, signal);
+        }
+        #[cfg(target_os = "linux")]
+        for pid in self.running() {
+            let pid = pid as libc::pid_t;
+            // Safe because `getpgid` and `kill` do not access memory, and the
+            // process was just checked to not have been replaced by another.
+            unsafe {
+                if libc::getpgid(pid) != root {
+                    libc::kill(pid, signal);
+                }
+            }
+        }
+    }
+
+    /// Waits until every process other than the root has exited or
+    /// `deadline` has passed, then kills the rest. Returns the processes that
+    /// had to be killed.
+    #[cfg(target_os = "linux")]
+    pub fn kill_stragglers(&self, deadline: Instant) -> Vec<Straggler> {
+        while !self.running().is_empty() && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+        }
+        let stragglers = self
+            .running()
+            .into_iter()
+            .filter_map(|pid| {
+                // Read the command line before the process is gone.
+                let incantation = proc::incantation(pid).ok()?;
+                // Safe because `kill` does not access memory.
+                unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+                Some(Straggler { pid, incantation })
+            })
+            .collect::<Vec<_>>();
+        self.reap(Instant::now() + REAP_TIMEOUT);
+        stragglers
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn kill_stragglers(&self, _: Instant) -> Vec<Straggler> {
+        vec![]
+    }
+
+    /// Returns the descendants that are still running.
+    #[cfg(target_os = "linux")]
+    fn running(&self) -> Vec<Pid> {
+        self.descendants
+            .iter()
+            .filter(|descendant| match proc::status(descendant.pid) {
+                Ok(Some(status)) => status.start_time == descendant.start_time,
+                _ => false,
+            })
+            .map(|descendant| descendant.pid)
+            .collect()
+    }
+
+    /// Waits until `deadline` for killed descendants to exit, reaping those
+    /// that were orphaned and adopted by diditrun.
+    #[cfg(target_os = "linux")]
+    fn reap(&self, deadline: Instant) {
+        let own_pid = std::process::id();
+        loop {
+            let exited = self.running().is_empty();
+            for descendant in &self.descendants {
+                let pid = descendant.pid;
+                let adopted = proc::parent(pid).ok() == Some(own_pid) &&
+                    proc::start_time(pid).ok() ==
+                        Some(descendant.start_time);
+                if adopted {
+                    // Safe because `waitpid` does not access memory when
+                    // given a null status.
+                    unsafe {
+                        libc::waitpid(
+                            pid as libc::pid_t,
+                            std::ptr::null_mut(),
+                            libc::WNOHANG,
+                        )
+                    };
+                }
+            }
+            if exited || deadline <= Instant::now() {
+                return;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::*;
+    use crate::job_control;
+    use std::process::{Child, Command};
+
+    /// Starts `script` in a process group of its own.
+    fn spawn_isolated(script: &str) -> Child {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        job_control::isolate(&mut command, false);
+        command.spawn().unwrap()
+    }
+
+    /// Waits until the child has started `descendants` sleep processes.
+    fn wait_for_sleeps(child: &Child, descendants: usize) -> ProcessTree {
+        loop {
+            let tree = ProcessTree::find(child.id()).unwrap();
+            let running = tree.running();
+            // The shell's children run it until they have called exec.
+            if running.len() == descendants &&
+                running.iter().all(|&pid| {
+                    proc::has_name(pid, "sleep").unwrap_or(false)
+                })
+            {
+                return tree;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    #[test]
+    fn finds_descendants_of_root() {
+        // The second sleep leaves the process group but is still a child of
+        // the root.
+        let mut child = spawn_isolated("sleep 10 & setsid sleep 10 & wait");
+        let tree = wait_for_sleeps(&child, 2);
+        assert!(!tree.running().contains(&child.id()));
+        tree.signal(libc::SIGTERM);
+        child.wait().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(1);
+        assert!(tree.kill_stragglers(deadline).is_empty());
+    }
+
+    #[test]
+    fn kills_descendants_that_ignore_signal() {
+        // Ignored signals stay ignored in the processes the shell starts.
+        let mut child = spawn_isolated("trap '' TERM; sleep 10 & wait");
+        let tree = wait_for_sleeps(&child, 1);
+        tree.signal(libc::SIGTERM);
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let stragglers = tree.kill_stragglers(deadline);
+        assert_eq!(stragglers.len(), 1);
+        assert_eq!(stragglers[0].incantation.command, "sleep");
+        assert!(tree.running().is_empty());
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
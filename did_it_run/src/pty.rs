@@ -0,0 +1,297 @@
+use signal_hook::iterator::Signals;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::ptr;
+use std::thread;
+
+/// Written to the terminal when our stdin reaches end of file so that the
+/// command sees it too.
+const END_OF_TRANSMISSION: u8 = 0x04;
+
+/// A pseudo-terminal that stands in for the user's terminal so that commands
+/// keep their colors and progress bars while their output is captured.
+pub struct Pty {
+    master: File,
+    slave: Option<File>,
+    resizes: Signals,
+    /// Closed, along with the terminal, to stop forwarding our stdin so that
+    /// the next terminal gets it.
+    input_stop: Option<File>,
+}
+
+/// Puts a terminal in raw mode and restores its previous mode when dropped.
+pub struct RawMode {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl Pty {
+    pub fn open() -> io::Result<Self> {
+        let mut master: RawFd = -1;
+        let mut slave: RawFd = -1;
+        // Safe because `openpty` only writes to the descriptors passed in and
+        // accepts null name, termios and window size arguments.
+        let result = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                ptr::null_mut::<libc::termios>(),
+                ptr::null_mut::<libc::winsize>(),
+            )
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // Safe because `openpty` opened these descriptors for us to own.
+        let (master, slave) =
+            unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+        let pty = Pty {
+            master,
+            slave: Some(slave),
+            resizes: Signals::new([libc::SIGWINCH])?,
+            input_stop: None,
+        };
+        pty.resize();
+        Ok(pty)
+    }
+
+    /// Connects the command's stdin, stdout and stderr to the terminal and
+    /// makes it the command's controlling terminal.
+    pub fn attach(&self, command: &mut Command) -> io::Result<()> {
+        let slave = match self.slave {
+            Some(ref slave) => slave,
+            None => {
+                let message = "Terminal is already detached.";
+                return Err(io::Error::other(message));
+            },
+        };
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave.try_clone()?));
+        // Safe because the closure only makes async-signal-safe system calls.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY as _, 0) ==
+                    -1
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Closes our copy of the terminal's slave side so that reading the
+    /// output stops once the command, which must have been spawned, exits.
+    pub fn detach(&mut self) {
+        self.slave = None;
+    }
+
+    /// Returns a reader for everything the command writes to the terminal.
+    pub fn output(&self) -> io::Result<File> {
+        self.master.try_clone()
+    }
+
+    /// Copies our stdin to the command until the terminal is dropped.
+    pub fn forward_input(&mut self) -> io::Result<()> {
+        let mut master = self.master.try_clone()?;
+        let (stop, input_stop) = pipe()?;
+        self.input_stop = Some(input_stop);
+        thread::spawn(move || {
+            let mut buffer = [0; 1024];
+            loop {
+                match wait_for_input(&stop) {
+                    Ok(true) => {},
+                    Ok(false) | Err(_) => return,
+                }
+                // Safe because `read` writes at most `buffer.len()` bytes to
+                // `buffer`. Reading stdin directly rather than through a
+                // buffered `Stdin` leaves what the command did not get to
+                // for the next one.
+                let count = unsafe {
+                    libc::read(
+                        libc::STDIN_FILENO,
+                        buffer.as_mut_ptr() as *mut libc::c_void,
+                        buffer.len(),
+                    )
+                };
+                let count = match count {
+                    -1 if io::Error::last_os_error().kind() ==
+                        io::ErrorKind::Interrupted =>
+                    {
+                        continue;
+                    },
+                    -1 | 0 => break,
+                    count => count as usize,
+                };
+                if master.write_all(&buffer[..count]).is_err() {
+                    return;
+                }
+            }
+            let _ = master.write_all(&[END_OF_TRANSMISSION]);
+        });
+        Ok(())
+    }
+
+    /// Matches the terminal's window size to the user's if it has changed.
+    pub fn sync_window_size(&self) {
+        if self.resizes.pending().count() > 0 {
+            self.resize();
+        }
+    }
+
+    /// Matches the terminal's window size to the user's, if they have one.
+    fn resize(&self) {
+        let mut size = libc::winsize {
+            ws_row: 0,
+            ws_col: 0,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // Safe because `size` is a valid `winsize` for the ioctls to read and
+        // write.
+        unsafe {
+            if libc::ioctl(
+                libc::STDOUT_FILENO,
+                libc::TIOCGWINSZ as _,
+                &mut size,
+            ) == 0
+            {
+                libc::ioctl(
+                    self.master.as_raw_fd(),
+                    libc::TIOCSWINSZ as _,
+                    &size,
+                );
+            }
+        }
+    }
+}
+
+/// Waits until our stdin can be read, or `stop` is closed. Returns whether
+/// there is input.
+fn wait_for_input(stop: &File) -> io::Result<bool> {
+    let mut fds = [
+        libc::pollfd {
+            fd: stop.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    loop {
+        // Safe because `fds` is a valid array of `pollfd` of the given length.
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) } != -1 {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    // Nothing is ever written to `stop`, so any event means it was closed.
+    Ok(fds[0].revents == 0 && fds[1].revents != 0)
+}
+
+/// Opens a pipe that commands do not inherit, returning its read and write
+/// ends.
+fn pipe() -> io::Result<(File, File)> {
+    let mut fds: [RawFd; 2] = [-1; 2];
+    // Safe because `pipe` writes two descriptors to `fds`.
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // Safe because `pipe` opened these descriptors for us to own.
+    let ends =
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    for &fd in &fds {
+        // Safe because `fcntl` does not access memory for this command.
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(ends)
+}
+
+impl RawMode {
+    /// Puts stdin in raw mode if it is a terminal so that keystrokes,
+    /// including Ctrl-C, pass straight through to the command's terminal.
+    pub fn enable() -> io::Result<Option<Self>> {
+        let fd = libc::STDIN_FILENO;
+        // Safe because `isatty` does not access memory.
+        if unsafe { libc::isatty(fd) } != 1 {
+            return Ok(None);
+        }
+        // Safe because `termios` is plain data that `tcgetattr` fills in.
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        // Safe because `raw` is a valid `termios`.
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(RawMode { fd, original }))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // Safe because `original` is the valid `termios` read in `enable`.
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.original) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn runs_command_in_terminal() {
+        let mut pty = Pty::open().unwrap();
+        let mut command = Command::new("bash");
+        command.args(["-c", "[ -t 0 ] && [ -t 1 ] && [ -t 2 ] && echo tty"]);
+        pty.attach(&mut command).unwrap();
+        let mut child = command.spawn().unwrap();
+        drop(command);
+        pty.detach();
+        let mut output = pty.output().unwrap();
+        assert!(child.wait().unwrap().success());
+        let mut buffer = vec![];
+        // Reading fails with EIO on Linux once the command has closed the
+        // terminal.
+        let _ = output.read_to_end(&mut buffer);
+        assert_eq!(String::from_utf8_lossy(&buffer).trim(), "tty");
+    }
+
+    #[test]
+    fn stops_waiting_for_input_once_stopped() {
+        let (stop, input_stop) = pipe().unwrap();
+        drop(input_stop);
+        assert!(!wait_for_input(&stop).unwrap());
+    }
+
+    #[test]
+    fn cannot_attach_detached_terminal() {
+        let mut pty = Pty::open().unwrap();
+        pty.detach();
+        assert!(pty.attach(&mut Command::new("true")).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::duration_format::duration_format;
+use std::fmt;
+use std::time::Duration;
+
+pub const BYTES_PER_KIBIBYTE: u64 = 1024;
+const BYTE_UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+/// Resources a command used over its lifetime, as reported by the operating
+/// system once it has exited.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResourceUsage {
+    pub user_time: Duration,
+    pub system_time: Duration,
+    /// Peak resident set size in bytes.
+    pub max_rss: u64,
+    pub block_reads: u64,
+    pub block_writes: u64,
+    pub voluntary_context_switches: u64,
+    pub involuntary_context_switches: u64,
+}
+
+impl ResourceUsage {
+    /// Returns a line per resource, e.g., "Max memory: 12.3 MiB".
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!(
+                "CPU time: {} user, {} system",
+                duration_format(&self.user_time),
+                duration_format(&self.system_time)
+            ),
+            format!("Max memory: {}", bytes_format(self.max_rss)),
+            format!(
+                "Block I/O: {} reads, {} writes",
+                self.block_reads, self.block_writes
+            ),
+            format!(
+                "Context switches: {} voluntary, {} involuntary",
+                self.voluntary_context_switches,
+                self.involuntary_context_switches
+            ),
+        ]
+    }
+}
+
+impl fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.lines().join("\n"))
+    }
+}
+
+#[cfg(unix)]
+impl From<libc::rusage> for ResourceUsage {
+    fn from(usage: libc::rusage) -> Self {
+        // Linux reports the peak resident set size in kibibytes while macOS
+        // reports it in bytes.
+        let max_rss = usage.ru_maxrss.max(0) as u64;
+        let max_rss = if cfg!(target_os = "macos") {
+            max_rss
+        } else {
+            max_rss * BYTES_PER_KIBIBYTE
+        };
+        ResourceUsage {
+            user_time: timeval_duration(usage.ru_utime),
+            system_time: timeval_duration(usage.ru_stime),
+            max_rss,
+            block_reads: usage.ru_inblock.max(0) as u64,
+            block_writes: usage.ru_oublock.max(0) as u64,
+            voluntary_context_switches: usage.ru_nvcsw.max(0) as u64,
+            involuntary_context_switches: usage.ru_nivcsw.max(0) as u64,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn timeval_duration(time: libc::timeval) -> Duration {
+    Duration::from_secs(time.tv_sec.max(0) as u64) +
+        Duration::from_micros(time.tv_usec.max(0) as u64)
+}
+
+/// Formats a byte count with a binary unit, e.g., "1.5 MiB".
+pub fn bytes_format(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while BYTES_PER_KIBIBYTE as f64 <= value && unit < BYTE_UNITS.len() - 1 {
+        value /= BYTES_PER_KIBIBYTE as f64;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, BYTE_UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, BYTE_UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(bytes_format(0), "0 B");
+        assert_eq!(bytes_format(1023), "1023 B");
+        assert_eq!(bytes_format(1024), "1.0 KiB");
+        assert_eq!(bytes_format(1536 * 1024), "1.5 MiB");
+        assert_eq!(bytes_format(3 * 1024 * 1024 * 1024), "3.0 GiB");
+        assert_eq!(bytes_format(u64::MAX), "16777216.0 TiB");
+    }
+
+    #[test]
+    fn lists_resource_usage() {
+        let usage = ResourceUsage {
+            user_time: Duration::from_millis(1500),
+            system_time: Duration::from_millis(250),
+            max_rss: 12 * 1024 * 1024,
+            block_reads: 3,
+            block_writes: 8,
+            voluntary_context_switches: 42,
+            involuntary_context_switches: 7,
+        };
+        let expected = "CPU time: 1s user, 250ms system\nMax memory: 12.0 \
+                        MiB\nBlock I/O: 3 reads, 8 writes\nContext switches: \
+                        42 voluntary, 7 involuntary";
+        assert_eq!(usage.to_string(), expected);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn converts_rusage() {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        usage.ru_utime.tv_sec = 2;
+        usage.ru_utime.tv_usec = 500_000;
+        usage.ru_stime.tv_usec = 1_000;
+        usage.ru_maxrss = 2048;
+        usage.ru_inblock = 1;
+        usage.ru_oublock = 2;
+        usage.ru_nvcsw = 3;
+        usage.ru_nivcsw = 4;
+        let expected = ResourceUsage {
+            user_time: Duration::from_millis(2500),
+            system_time: Duration::from_millis(1),
+            max_rss: 2 * 1024 * 1024,
+            block_reads: 1,
+            block_writes: 2,
+            voluntary_context_switches: 3,
+            involuntary_context_switches: 4,
+        };
+        assert_eq!(ResourceUsage::from(usage), expected);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::exit_code::ExitCode;
+use crate::signal::{Interrupts, Signal};
+use serde_derive::Deserialize;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const BACKOFF_NAMES: [&str; 2] = ["constant", "exponential"];
+
+/// Doubling stops here so that the delay cannot overflow.
+const MAX_BACKOFF_EXPONENT: u32 = 16;
+
+/// The longest delay between attempts, however it was configured or grew.
+const MAX_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How often an interrupt is checked for while waiting to retry.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How the delay between attempts grows.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backoff {
+    /// Wait the same delay before every retry.
+    Constant,
+    /// Double the delay after every retry.
+    Exponential,
+}
+
+/// The exit code diditrun would have exited with after one run of the
+/// command, and how long that run took.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attempt {
+    pub exit_code: ExitCode,
+    pub elapsed_time: Duration,
+}
+
+impl Backoff {
+    /// Returns the backoff named by one of `BACKOFF_NAMES`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "constant" => Some(Backoff::Constant),
+            "exponential" => Some(Backoff::Exponential),
+            _ => None,
+        }
+    }
+
+    /// Returns how long to wait before the `retry`th retry, counting from 1,
+    /// up to `MAX_DELAY`.
+    pub fn delay(self, delay: Duration, retry: u32) -> Duration {
+        let delay = match self {
+            Backoff::Constant => Some(delay),
+            Backoff::Exponential => {
+                let exponent =
+                    retry.saturating_sub(1).min(MAX_BACKOFF_EXPONENT);
+                delay.checked_mul(1 << exponent)
+            },
+        };
+        delay.map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY))
+    }
+}
+
+/// Waits for `duration` unless diditrun is interrupted first, in which case
+/// the interrupting signal is returned.
+pub fn wait(duration: Duration) -> io::Result<Option<Signal>> {
+    let interrupts = Interrupts::listen()?;
+    // A deadline too far off to represent is never reached.
+    let deadline = Instant::now().checked_add(duration);
+    loop {
+        if let Some(signal) = interrupts.pending().pop() {
+            return Ok(Some(signal));
+        }
+        match deadline {
+            Some(deadline) if deadline <= Instant::now() => return Ok(None),
+            _ => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_backoff_names() {
+        for name in &BACKOFF_NAMES {
+            assert!(Backoff::from_name(name).is_some());
+        }
+        assert_eq!(Backoff::from_name("linear"), None);
+    }
+
+    #[test]
+    fn keeps_constant_delay() {
+        let delay = Duration::from_secs(30);
+        for retry in 1..5 {
+            assert_eq!(Backoff::Constant.delay(delay, retry), delay);
+        }
+    }
+
+    #[test]
+    fn doubles_exponential_delay() {
+        let delay = Duration::from_secs(30);
+        let delays = (1..5)
+            .map(|retry| Backoff::Exponential.delay(delay, retry).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [30, 60, 120, 240]);
+        let delay = Duration::from_secs(1);
+        let delay = Backoff::Exponential.delay(delay, u32::MAX);
+        assert_eq!(delay, Duration::from_secs(1 << MAX_BACKOFF_EXPONENT));
+    }
+
+    #[test]
+    fn caps_delay() {
+        let delay = Duration::from_secs(30);
+        assert_eq!(Backoff::Exponential.delay(delay, 16), MAX_DELAY);
+        let delay = Duration::from_secs(u64::MAX);
+        assert_eq!(Backoff::Constant.delay(delay, 1), MAX_DELAY);
+        assert_eq!(Backoff::Exponential.delay(delay, 2), MAX_DELAY);
+    }
+
+    #[test]
+    fn waits_for_duration() {
+        let duration = Duration::from_millis(50);
+        let start = Instant::now();
+        assert_eq!(wait(duration).unwrap(), None);
+        assert!(duration <= start.elapsed());
+    }
+}
@@ -0,0 +1,107 @@
+use std::io;
+
+pub type Signal = i32;
+
+/// Signals that ask diditrun to stop, which are forwarded to the child.
+#[cfg(unix)]
+const INTERRUPT_SIGNALS: [Signal; 3] =
+    [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// Listens for interrupt signals received by diditrun for as long as it is
+/// alive.
+pub struct Interrupts {
+    #[cfg(unix)]
+    signals: signal_hook::iterator::Signals,
+}
+
+impl Interrupts {
+    #[cfg(unix)]
+    pub fn listen() -> io::Result<Self> {
+        let signals = signal_hook::iterator::Signals::new(INTERRUPT_SIGNALS)?;
+        Ok(Interrupts { signals })
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen() -> io::Result<Self> {
+        Ok(Interrupts {})
+    }
+
+    /// Returns the signals received since the last call, without blocking.
+    #[cfg(unix)]
+    pub fn pending(&self) -> Vec<Signal> {
+        self.signals.pending().collect()
+    }
+
+    #[cfg(not(unix))]
+    pub fn pending(&self) -> Vec<Signal> {
+        vec![]
+    }
+}
+
+/// Returns the conventional name of a signal, e.g., "SIGSEGV", or "signal N"
+/// if the signal is not recognized.
+pub fn signal_name(signal: Signal) -> String {
+    match known_signal_name(signal) {
+        Some(name) => name.to_string(),
+        None => format!("signal {}", signal),
+    }
+}
+
+#[cfg(unix)]
+fn known_signal_name(signal: Signal) -> Option<&'static str> {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGCHLD => "SIGCHLD",
+        libc::SIGCONT => "SIGCONT",
+        libc::SIGSTOP => "SIGSTOP",
+        libc::SIGTSTP => "SIGTSTP",
+        libc::SIGTTIN => "SIGTTIN",
+        libc::SIGTTOU => "SIGTTOU",
+        libc::SIGURG => "SIGURG",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGVTALRM => "SIGVTALRM",
+        libc::SIGPROF => "SIGPROF",
+        libc::SIGWINCH => "SIGWINCH",
+        libc::SIGIO => "SIGIO",
+        libc::SIGSYS => "SIGSYS",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(not(unix))]
+fn known_signal_name(_: Signal) -> Option<&'static str> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names_known_signals() {
+        assert_eq!(signal_name(libc::SIGINT), "SIGINT");
+        assert_eq!(signal_name(libc::SIGKILL), "SIGKILL");
+        assert_eq!(signal_name(libc::SIGSEGV), "SIGSEGV");
+    }
+
+    #[test]
+    fn names_unknown_signals_by_number() {
+        assert_eq!(signal_name(1234), "signal 1234");
+    }
+}
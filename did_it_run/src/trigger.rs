@@ -0,0 +1,185 @@
+use crate::config::TriggerConfig;
+use crate::output::{OutputLine, OutputTail, Stream};
+use regex::Regex;
+use std::mem;
+use std::time::{Duration, Instant};
+
+/// How many of the lines before a matching line are sent along with it.
+const CONTEXT_LINES: usize = 5;
+
+/// A line of output that matched a trigger.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputMatch {
+    pub pattern: String,
+    pub stream: Stream,
+    pub line: String,
+    /// The lines written to the same stream just before `line`.
+    pub context: Vec<String>,
+    /// How many earlier matches were not notified because of the trigger's
+    /// rate limit.
+    pub suppressed: u32,
+}
+
+/// Checks each line of a command's output against the configured triggers.
+pub struct Triggers {
+    triggers: Vec<Trigger>,
+    stdout: OutputTail,
+    stderr: OutputTail,
+}
+
+struct Trigger {
+    pattern: Regex,
+    rate_limit: Duration,
+    last_match: Option<Instant>,
+    suppressed: u32,
+}
+
+impl Triggers {
+    pub fn new(configs: &[TriggerConfig]) -> Self {
+        let triggers = configs
+            .iter()
+            .map(|config| Trigger {
+                pattern: config.pattern.clone(),
+                rate_limit: config.rate_limit,
+                last_match: None,
+                suppressed: 0,
+            })
+            .collect();
+        Triggers {
+            triggers,
+            stdout: OutputTail::new(CONTEXT_LINES),
+            stderr: OutputTail::new(CONTEXT_LINES),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    /// Returns a match for every trigger that `line` sets off, unless the
+    /// trigger has already matched within its rate limit.
+    pub fn check(
+        &mut self,
+        line: &OutputLine,
+        now: Instant,
+    ) -> Vec<OutputMatch> {
+        let context = match line.stream {
+            Stream::Stdout => &mut self.stdout,
+            Stream::Stderr => &mut self.stderr,
+        };
+        let mut matches = vec![];
+        for trigger in &mut self.triggers {
+            if !trigger.pattern.is_match(&line.text) {
+                continue;
+            }
+            if let Some(last_match) = trigger.last_match {
+                // A rate limit that ends past the end of time never does.
+                let limited = last_match
+                    .checked_add(trigger.rate_limit)
+                    .is_none_or(|until| now < until);
+                if limited {
+                    trigger.suppressed += 1;
+                    continue;
+                }
+            }
+            trigger.last_match = Some(now);
+            matches.push(OutputMatch {
+                pattern: trigger.pattern.as_str().to_string(),
+                stream: line.stream,
+                line: line.text.clone(),
+                context: context.lines(),
+                suppressed: mem::take(&mut trigger.suppressed),
+            });
+        }
+        context.push(line.text.clone());
+        matches
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn triggers(patterns: &[(&str, u64)]) -> Triggers {
+        let configs = patterns
+            .iter()
+            .map(|&(pattern, rate_limit)| TriggerConfig {
+                pattern: Regex::new(pattern).unwrap(),
+                rate_limit: Duration::from_secs(rate_limit),
+            })
+            .collect::<Vec<_>>();
+        Triggers::new(&configs)
+    }
+
+    fn line(stream: Stream, text: &str) -> OutputLine {
+        OutputLine {
+            stream,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn matches_lines_with_context() {
+        let mut triggers = triggers(&[("^error:", 0), ("NaN", 0)]);
+        let now = Instant::now();
+        assert!(!triggers.is_empty());
+        for text in &["compiling", "linking"] {
+            let matches = triggers.check(&line(Stream::Stderr, text), now);
+            assert!(matches.is_empty());
+        }
+        triggers.check(&line(Stream::Stdout, "elsewhere"), now);
+        let matches =
+            triggers.check(&line(Stream::Stderr, "error: NaN found"), now);
+        let expected = ["^error:", "NaN"]
+            .iter()
+            .map(|pattern| OutputMatch {
+                pattern: pattern.to_string(),
+                stream: Stream::Stderr,
+                line: "error: NaN found".to_string(),
+                context: vec!["compiling".to_string(), "linking".to_string()],
+                suppressed: 0,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(matches, expected);
+    }
+
+    #[test]
+    fn keeps_limited_context() {
+        let mut triggers = triggers(&[("error", 0)]);
+        let now = Instant::now();
+        for index in 0..10 {
+            let text = index.to_string();
+            triggers.check(&line(Stream::Stdout, &text), now);
+        }
+        let matches = triggers.check(&line(Stream::Stdout, "error"), now);
+        assert_eq!(matches[0].context, ["5", "6", "7", "8", "9"]);
+    }
+
+    #[test]
+    fn limits_rate_of_matches() {
+        let mut triggers = triggers(&[("error", 60)]);
+        let now = Instant::now();
+        let error = line(Stream::Stderr, "error");
+        assert_eq!(triggers.check(&error, now).len(), 1);
+        assert!(triggers.check(&error, now).is_empty());
+        let later = now + Duration::from_secs(30);
+        assert!(triggers.check(&error, later).is_empty());
+        let much_later = now + Duration::from_secs(60);
+        let matches = triggers.check(&error, much_later);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].suppressed, 2);
+        let matches =
+            triggers.check(&error, much_later + Duration::from_secs(60));
+        assert_eq!(matches[0].suppressed, 0);
+    }
+
+    #[test]
+    fn limits_matches_forever_with_huge_rate_limit() {
+        let mut triggers = triggers(&[("error", u64::MAX)]);
+        let now = Instant::now();
+        let error = line(Stream::Stderr, "error");
+        assert_eq!(triggers.check(&error, now).len(), 1);
+        let later = now + Duration::from_secs(3600);
+        assert!(triggers.check(&error, later).is_empty());
+    }
+}
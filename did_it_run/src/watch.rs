@@ -0,0 +1,408 @@
+use crate::incantation::Incantation;
+#[cfg(target_os = "linux")]
+use crate::proc;
+use std::error;
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::fs::File;
+use std::io;
+#[cfg(target_os = "linux")]
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub type Pid = u32;
+
+/// How often a process that cannot be waited on directly is checked for.
+#[cfg(target_os = "linux")]
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A process that diditrun did not start.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WatchTarget {
+    Pid(Pid),
+    Name(String),
+}
+
+/// A running process being watched until it exits.
+#[derive(Debug)]
+pub struct Process {
+    pub pid: Pid,
+    pub incantation: Incantation,
+    /// How long the process had been running when watching began.
+    pub elapsed_time: Duration,
+    #[cfg(target_os = "linux")]
+    start_time: u64,
+    /// Refers to the process for as long as it runs, unless the kernel is too
+    /// old to support it.
+    #[cfg(target_os = "linux")]
+    pidfd: Option<File>,
+}
+
+#[derive(Debug)]
+pub enum WatchError {
+    Io(io::Error),
+    NoSuchProcess(WatchTarget),
+    AmbiguousName(String, Vec<Pid>),
+    Unsupported,
+}
+
+impl Process {
+    /// Finds the process to watch.
+    pub fn find(target: &WatchTarget) -> Result<Self, WatchError> {
+        let (pid, name) = match *target {
+            WatchTarget::Pid(pid) => (pid, None),
+            WatchTarget::Name(ref name) => {
+                (find_pid(name)?, Some(name.as_str()))
+            },
+        };
+        Process::open(pid, name).map_err(|err| match err {
+            WatchError::Io(ref io_err)
+                if io_err.kind() == io::ErrorKind::NotFound =>
+            {
+                WatchError::NoSuchProcess(target.clone())
+            },
+            err => err,
+        })
+    }
+
+    /// Opens the process with `pid`, which must still be named `name` if
+    /// given.
+    #[cfg(target_os = "linux")]
+    fn open(pid: Pid, name: Option<&str>) -> Result<Self, WatchError> {
+        let not_found = || io::Error::from(io::ErrorKind::NotFound).into();
+        // The PID keeps referring to the process the pidfd refers to for as
+        // long as it runs, so the process is read about only once the pidfd
+        // is open and then checked to still be running.
+        let pidfd = match pidfd::open(pid) {
+            Ok(pidfd) => Some(pidfd),
+            // Kernels before 5.3 cannot open a pidfd.
+            Err(ref err) if err.raw_os_error() == Some(libc::ENOSYS) => None,
+            // The process is gone, or no process could have this PID.
+            Err(ref err)
+                if err.raw_os_error() == Some(libc::ESRCH) ||
+                    err.raw_os_error() == Some(libc::EINVAL) =>
+            {
+                return Err(not_found());
+            },
+            Err(err) => return Err(err.into()),
+        };
+        let start_time = proc::start_time(pid)?;
+        let incantation = proc::incantation(pid)?;
+        if let Some(name) = name {
+            if !proc::has_name(pid, name)? {
+                return Err(not_found());
+            }
+        }
+        if let Some(ref pidfd) = pidfd {
+            if pidfd::has_exited(pidfd)? {
+                return Err(not_found());
+            }
+        }
+        let elapsed_time = proc::uptime()?
+            .checked_sub(proc::ticks_duration(start_time))
+            .unwrap_or_default();
+        Ok(Process {
+            pid,
+            incantation,
+            elapsed_time,
+            start_time,
+            pidfd,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn open(_: Pid, _: Option<&str>) -> Result<Self, WatchError> {
+        Err(WatchError::Unsupported)
+    }
+
+    /// Waits for the process to exit and returns how long it ran for.
+    pub fn wait(self) -> Result<Duration, WatchError> {
+        let now = Instant::now();
+        self.wait_for_exit()?;
+        Ok(self.elapsed_time + now.elapsed())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn wait_for_exit(&self) -> io::Result<()> {
+        match self.pidfd {
+            Some(ref pidfd) => pidfd::wait(pidfd),
+            None => self.poll(),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn wait_for_exit(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            WatchError::Unsupported,
+        ))
+    }
+
+    /// Waits until the process is gone or its PID has been reused.
+    #[cfg(target_os = "linux")]
+    fn poll(&self) -> io::Result<()> {
+        loop {
+            match proc::start_time(self.pid) {
+                Ok(start_time) if start_time == self.start_time => {},
+                Ok(_) => return Ok(()),
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                    return Ok(());
+                },
+                Err(err) => return Err(err),
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn find_pid(name: &str) -> Result<Pid, WatchError> {
+    let own_pid = std::process::id();
+    let mut pids = vec![];
+    for entry in fs::read_dir("/proc")? {
+        let pid = match entry?.file_name().to_string_lossy().parse::<Pid>() {
+            Ok(pid) => pid,
+            Err(_) => continue,
+        };
+        if pid == own_pid {
+            continue;
+        }
+        // Processes may exit while we look at them.
+        if let Ok(true) = proc::has_name(pid, name) {
+            pids.push(pid);
+        }
+    }
+    match pids.len() {
+        0 => Err(WatchError::NoSuchProcess(WatchTarget::Name(name.into()))),
+        1 => Ok(pids[0]),
+        _ => {
+            pids.sort();
+            Err(WatchError::AmbiguousName(name.into(), pids))
+        },
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn find_pid(_: &str) -> Result<Pid, WatchError> {
+    Err(WatchError::Unsupported)
+}
+
+/// Waits on a process through a file descriptor that refers to it, which,
+/// unlike its PID, cannot be reused.
+#[cfg(target_os = "linux")]
+mod pidfd {
+    use super::Pid;
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    pub fn open(pid: Pid) -> io::Result<File> {
+        // Safe because `pidfd_open` does not access memory.
+        let fd = unsafe {
+            libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0)
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // Safe because `pidfd_open` opened this descriptor for us to own.
+        Ok(unsafe { File::from_raw_fd(fd as libc::c_int) })
+    }
+
+    /// Blocks until the process exits.
+    pub fn wait(pidfd: &File) -> io::Result<()> {
+        poll(pidfd, -1).map(|_| ())
+    }
+
+    pub fn has_exited(pidfd: &File) -> io::Result<bool> {
+        poll(pidfd, 0)
+    }
+
+    /// Waits up to `timeout` milliseconds, or forever if negative, for the
+    /// process to exit, which makes its pidfd readable. Returns whether it
+    /// has.
+    fn poll(pidfd: &File, timeout: libc::c_int) -> io::Result<bool> {
+        let mut poll_fd = libc::pollfd {
+            fd: pidfd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        loop {
+            // Safe because `poll_fd` is a valid `pollfd` for `poll` to fill.
+            match unsafe { libc::poll(&mut poll_fd, 1, timeout) } {
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                },
+                count => return Ok(count == 1),
+            }
+        }
+    }
+}
+
+impl fmt::Display for WatchTarget {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WatchTarget::Pid(pid) => write!(formatter, "PID {}", pid),
+            WatchTarget::Name(ref name) => {
+                write!(formatter, "name \"{}\"", name)
+            },
+        }
+    }
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WatchError::Io(ref err) => err.fmt(formatter),
+            WatchError::NoSuchProcess(ref target) => {
+                write!(formatter, "No process found with {}.", target)
+            },
+            WatchError::AmbiguousName(ref name, ref pids) => write!(
+                formatter,
+                "Several processes are named \"{}\": {}. Specify one with \
+                 --pid.",
+                name,
+                pids.iter()
+                    .map(Pid::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            WatchError::Unsupported => write!(
+                formatter,
+                "Watching processes is not supported on this platform."
+            ),
+        }
+    }
+}
+
+impl error::Error for WatchError {}
+
+impl From<io::Error> for WatchError {
+    fn from(err: io::Error) -> Self {
+        WatchError::Io(err)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::*;
+    use std::process::{Child, Command};
+
+    fn sleep(duration: &str) -> Child {
+        let child = Command::new("sleep").arg(duration).spawn().unwrap();
+        // The child runs the test binary until it has called exec, and its
+        // command line is briefly empty while exec sets up its arguments.
+        while proc::incantation(child.id()).unwrap().args.is_empty() ||
+            !proc::has_name(child.id(), "sleep").unwrap()
+        {
+            thread::sleep(Duration::from_millis(1));
+        }
+        child
+    }
+
+    #[test]
+    fn reads_command_line_of_process() {
+        let mut child = sleep("10");
+        let process = Process::find(&WatchTarget::Pid(child.id())).unwrap();
+        assert_eq!(process.incantation.command, "sleep");
+        assert_eq!(process.incantation.args, ["10"]);
+        assert!(process.elapsed_time < Duration::from_secs(10));
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn waits_for_process_to_exit() {
+        let mut child = sleep("0.2");
+        let process = Process::find(&WatchTarget::Pid(child.id())).unwrap();
+        // A child stays a zombie, and so readable through procfs, until it is
+        // reaped.
+        let handle = thread::spawn(move || child.wait().unwrap());
+        let elapsed_time = process.wait().unwrap();
+        assert!(handle.join().unwrap().success());
+        assert!(Duration::from_millis(100) <= elapsed_time);
+    }
+
+    #[test]
+    fn polls_for_process_to_exit() {
+        let mut child = sleep("0.2");
+        let process = Process::find(&WatchTarget::Pid(child.id())).unwrap();
+        let handle = thread::spawn(move || child.wait().unwrap());
+        process.poll().unwrap();
+        assert!(handle.join().unwrap().success());
+    }
+
+    #[test]
+    fn does_not_watch_process_that_has_exited() {
+        let mut child = Command::new("true").spawn().unwrap();
+        // The child stays a zombie, and so readable through procfs, until it
+        // is reaped.
+        while proc::status(child.id()).unwrap().is_some() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let target = WatchTarget::Pid(child.id());
+        match Process::find(&target) {
+            Err(WatchError::NoSuchProcess(missing)) => {
+                assert_eq!(missing, target)
+            },
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn finds_process_by_name() {
+        let mut child = Command::new("bash")
+            .args(["-c", "exec -a diditrun-watch-test sleep 10"])
+            .spawn()
+            .unwrap();
+        let target = WatchTarget::Name("diditrun-watch-test".into());
+        let mut found = Process::find(&target);
+        for _ in 0..100 {
+            if found.is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+            found = Process::find(&target);
+        }
+        assert_eq!(found.unwrap().pid, child.id());
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn returns_error_for_missing_process() {
+        let target = WatchTarget::Name("wingardium-leviosa".into());
+        match Process::find(&target) {
+            Err(WatchError::NoSuchProcess(missing)) => {
+                assert_eq!(missing, target)
+            },
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        let target = WatchTarget::Pid(u32::MAX);
+        match Process::find(&target) {
+            Err(WatchError::NoSuchProcess(missing)) => {
+                assert_eq!(missing, target)
+            },
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    // Hacks to minimize coverage report errors
+    #[test]
+    fn maximize_coverage() {
+        let errors = [
+            WatchError::Io(io::Error::from(io::ErrorKind::Other)),
+            WatchError::NoSuchProcess(WatchTarget::Pid(1)),
+            WatchError::AmbiguousName("foo".into(), vec![1, 2]),
+            WatchError::Unsupported,
+        ];
+        for err in &errors {
+            let _ = format!("{} {:?}", err, err);
+        }
+    }
+}
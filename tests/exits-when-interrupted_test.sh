@@ -0,0 +1,13 @@
+#!/usr/bin/env bash
+BINARY_NAME=$1
+shift
+
+$BINARY_NAME $@ sleep 10 &
+pid=$!
+sleep 1
+kill -TERM $pid
+wait $pid
+code=$?
+if [[ "$code" -ne 143 ]]; then
+  exit 1
+fi
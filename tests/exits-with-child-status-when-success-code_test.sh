@@ -0,0 +1,9 @@
+#!/usr/bin/env bash
+BINARY_NAME=$1
+shift
+
+$BINARY_NAME $@ --success-codes 0,3 -- bash -c 'exit 3'
+code=$?
+if [[ "$code" -ne 3 ]]; then
+  exit 1
+fi
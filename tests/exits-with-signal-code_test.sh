@@ -0,0 +1,9 @@
+#!/usr/bin/env bash
+BINARY_NAME=$1
+shift
+
+$BINARY_NAME $@ -- bash -c 'kill -KILL $$'
+code=$?
+if [[ "$code" -ne 137 ]]; then
+  exit 1
+fi
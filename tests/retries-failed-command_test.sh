@@ -0,0 +1,13 @@
+#!/usr/bin/env bash
+BINARY_NAME=$1
+shift
+
+attempts=$(mktemp)
+$BINARY_NAME $@ --retries 3 --retry-delay 0s -- \
+  bash -c "echo attempt >> $attempts; [ \$(wc -l < $attempts) -ge 2 ]"
+code=$?
+count=$(wc -l < "$attempts")
+rm -f "$attempts"
+if [[ "$code" -ne 0 || "$count" -ne 2 ]]; then
+  exit 1
+fi